@@ -28,9 +28,9 @@ compile_error!("You must enable either the 'keyboard' or 'macropad' feature. Exa
 // Stop compilation here if feature check failed - provide dummies to suppress other errors
 #[cfg(not(any(feature = "keyboard", feature = "macropad")))]
 mod dummy {
-    pub use is31fl3743a::SwSetting;
+    pub use is31fl3743a::{Address, SwSetting};
     pub const SW_SETTING: SwSetting = SwSetting::Sw1Sw11;
-    pub const ADDR_CTRL2: u8 = 0;
+    pub const ADDR_CTRL2: Address = Address::MIN;
 }
 #[cfg(not(any(feature = "keyboard", feature = "macropad")))]
 use dummy::*;
@@ -50,13 +50,14 @@ use framework16_keyboard::{
 use is31fl3743a::devices::UnknownDevice;
 #[cfg(any(feature = "keyboard", feature = "macropad"))]
 use is31fl3743a::SwSetting;
+use is31fl3743a::{AddrPin, Address};
 
-/// I2C address of the first (or only) LED controller
-const ADDR_CTRL1: u8 = 0x20;
+/// I2C address of the first (or only) LED controller, 0x20
+const ADDR_CTRL1: Address = Address::new(AddrPin::Gnd, AddrPin::Gnd);
 
-/// I2C address of the second LED controller (keyboard only)
+/// I2C address of the second LED controller (keyboard only), 0x23
 #[cfg(feature = "keyboard")]
-const ADDR_CTRL2: u8 = 0x23;
+const ADDR_CTRL2: Address = Address::new(AddrPin::Vcc, AddrPin::Gnd);
 
 /// SW enablement setting - use keyboard value unless only macropad is enabled
 #[cfg(feature = "keyboard")]
@@ -0,0 +1,118 @@
+use core::convert::TryFrom;
+
+/// What an ADDR pin is connected to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum AddrPin {
+    Gnd = 0b00,
+    Scl = 0b01,
+    Sda = 0b10,
+    Vcc = 0b11,
+}
+
+impl AddrPin {
+    pub(crate) const fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::Gnd,
+            0b01 => Self::Scl,
+            0b10 => Self::Sda,
+            _ => Self::Vcc,
+        }
+    }
+}
+
+/// 7-bit I2C slave address of a controller, `0b0100_A4A3A2A1`.
+///
+/// A2:A1 are selected by the ADDR1 pin and A4:A3 by the ADDR2 pin, which gives the 16 addresses
+/// from 0x20 to 0x2F. Only those can be represented.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Address(u8);
+
+impl Address {
+    /// Address with both ADDR pins connected to GND
+    pub const MIN: Address = Address::new(AddrPin::Gnd, AddrPin::Gnd);
+    /// Address with both ADDR pins connected to VCC
+    pub const MAX: Address = Address::new(AddrPin::Vcc, AddrPin::Vcc);
+
+    /// Address selected by the connections of the ADDR1 and ADDR2 pins
+    pub const fn new(addr1: AddrPin, addr2: AddrPin) -> Self {
+        Address(0b010_0000 | (addr2 as u8) << 2 | addr1 as u8)
+    }
+
+    /// The raw 7-bit address
+    pub const fn as_u8(self) -> u8 {
+        self.0
+    }
+
+    /// What the ADDR1 pin is connected to
+    pub const fn addr1(self) -> AddrPin {
+        AddrPin::from_bits(self.0)
+    }
+
+    /// What the ADDR2 pin is connected to
+    pub const fn addr2(self) -> AddrPin {
+        AddrPin::from_bits(self.0 >> 2)
+    }
+}
+
+impl Default for Address {
+    fn default() -> Self {
+        Address::MIN
+    }
+}
+
+impl From<Address> for u8 {
+    fn from(address: Address) -> u8 {
+        address.0
+    }
+}
+
+/// A raw 7-bit address that can't be selected with the ADDR pins
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct InvalidAddress(pub u8);
+
+impl TryFrom<u8> for Address {
+    type Error = InvalidAddress;
+
+    fn try_from(address: u8) -> Result<Self, Self::Error> {
+        if (Address::MIN.0..=Address::MAX.0).contains(&address) {
+            Ok(Address(address))
+        } else {
+            Err(InvalidAddress(address))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PINS: [AddrPin; 4] = [AddrPin::Gnd, AddrPin::Scl, AddrPin::Sda, AddrPin::Vcc];
+
+    #[test]
+    fn strapping() {
+        assert_eq!(Address::MIN.as_u8(), 0x20);
+        assert_eq!(Address::MAX.as_u8(), 0x2F);
+        assert_eq!(Address::default(), Address::MIN);
+        assert_eq!(Address::new(AddrPin::Vcc, AddrPin::Gnd).as_u8(), 0x23);
+        assert_eq!(Address::new(AddrPin::Gnd, AddrPin::Scl).as_u8(), 0x24);
+        assert_eq!(u8::from(Address::new(AddrPin::Sda, AddrPin::Sda)), 0x2A);
+        for addr1 in PINS {
+            for addr2 in PINS {
+                let address = Address::new(addr1, addr2);
+                assert_eq!((address.addr1(), address.addr2()), (addr1, addr2));
+                assert_eq!(Address::try_from(address.as_u8()), Ok(address));
+            }
+        }
+    }
+
+    #[test]
+    fn raw_addresses() {
+        for raw in 0x20..=0x2F {
+            assert_eq!(Address::try_from(raw).map(Address::as_u8), Ok(raw));
+        }
+        for raw in [0x00, 0x1F, 0x30, 0x7F, 0xFF] {
+            assert_eq!(Address::try_from(raw), Err(InvalidAddress(raw)));
+        }
+    }
+}
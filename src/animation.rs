@@ -0,0 +1,495 @@
+//! # Format
+//!
+//! All numbers are little endian. The header is 11 bytes:
+//!
+//! | Offset | Size | Content                        |
+//! |--------|------|--------------------------------|
+//! | 0      | 4    | Magic, `ISAN`                  |
+//! | 4      | 1    | Version, currently 1           |
+//! | 5      | 1    | Width of the display           |
+//! | 6      | 1    | Height of the display          |
+//! | 7      | 2    | Number of frames               |
+//! | 9      | 2    | Duration of a frame in ms      |
+//!
+//! Every frame starts with a tag byte and describes all 0xC6 PWM registers:
+//!
+//! - `0x00` Key frame: 0xC6 raw bytes.
+//! - `0x01` Run length encoded: pairs of count (1 to 255) and value, until 0xC6 bytes are
+//!   covered.
+//! - `0x02` Delta to the previous frame (or a dark frame for the first one): number of runs,
+//!   then for every run the offset of its first register, its length and the new values.
+use crate::framebuffer::{FrameBuffer, Output};
+use crate::IS31FL3743;
+#[cfg(feature = "std")]
+use std::vec::Vec;
+
+/// Magic bytes at the start of every animation
+pub const MAGIC: [u8; 4] = *b"ISAN";
+/// Current version of the format
+pub const VERSION: u8 = 1;
+/// Length of the header in bytes
+pub const HEADER_LEN: usize = 11;
+
+const FRAME_LEN: usize = 0xC6;
+
+const TAG_KEY: u8 = 0x00;
+const TAG_RLE: u8 = 0x01;
+const TAG_DELTA: u8 = 0x02;
+
+/// Extra bytes a burst write costs on the bus: unlock, page select and register address
+const BURST_OVERHEAD: usize = 5;
+
+/// Reasons an animation can't be decoded or encoded
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FormatError {
+    /// The data doesn't start with [MAGIC]
+    BadMagic,
+    /// The version is newer than this decoder
+    UnsupportedVersion(u8),
+    /// The data ends in the middle of a frame
+    Truncated,
+    /// A frame has an unknown tag or writes outside of the PWM registers
+    InvalidFrame(u16),
+    /// More than 65535 frames
+    TooManyFrames,
+    /// The animation was made for a display of another size, holds its width and height
+    SizeMismatch(u8, u8),
+}
+
+/// Header of an animation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Header {
+    pub width: u8,
+    pub height: u8,
+    pub frame_count: u16,
+    pub frame_duration_ms: u16,
+}
+
+impl Header {
+    /// Decode the header at the start of an animation
+    pub fn parse(data: &[u8]) -> Result<Self, FormatError> {
+        if data.len() < HEADER_LEN {
+            return Err(FormatError::Truncated);
+        }
+        if data[0..4] != MAGIC {
+            return Err(FormatError::BadMagic);
+        }
+        if data[4] != VERSION {
+            return Err(FormatError::UnsupportedVersion(data[4]));
+        }
+        Ok(Header {
+            width: data[5],
+            height: data[6],
+            frame_count: u16::from_le_bytes([data[7], data[8]]),
+            frame_duration_ms: u16::from_le_bytes([data[9], data[10]]),
+        })
+    }
+
+    /// Encode into the first [HEADER_LEN] bytes of an animation
+    pub fn to_bytes(&self) -> [u8; HEADER_LEN] {
+        let mut buf = [0x00; HEADER_LEN];
+        buf[0..4].copy_from_slice(&MAGIC);
+        buf[4] = VERSION;
+        buf[5] = self.width;
+        buf[6] = self.height;
+        buf[7..9].copy_from_slice(&self.frame_count.to_le_bytes());
+        buf[9..11].copy_from_slice(&self.frame_duration_ms.to_le_bytes());
+        buf
+    }
+}
+
+/// Apply the frame at `offset` to `frame`. Calls `dirty` with the offset and length of every
+/// changed range of a delta frame. Returns the offset of the next frame and whether the whole
+/// frame was replaced.
+fn decode_frame<F: FnMut(usize, usize)>(
+    data: &[u8],
+    offset: usize,
+    index: u16,
+    frame: &mut [u8; FRAME_LEN],
+    mut dirty: F,
+) -> Result<(usize, bool), FormatError> {
+    let invalid = FormatError::InvalidFrame(index);
+    let byte = |at: usize| data.get(at).copied().ok_or(FormatError::Truncated);
+    match byte(offset)? {
+        TAG_KEY => {
+            let end = offset + 1 + FRAME_LEN;
+            frame.copy_from_slice(data.get(offset + 1..end).ok_or(FormatError::Truncated)?);
+            Ok((end, true))
+        }
+        TAG_RLE => {
+            let mut at = offset + 1;
+            let mut filled = 0;
+            while filled < FRAME_LEN {
+                let count = byte(at)? as usize;
+                let value = byte(at + 1)?;
+                if count == 0 || filled + count > FRAME_LEN {
+                    return Err(invalid);
+                }
+                frame[filled..filled + count].fill(value);
+                filled += count;
+                at += 2;
+            }
+            Ok((at, true))
+        }
+        TAG_DELTA => {
+            let runs = byte(offset + 1)?;
+            let mut at = offset + 2;
+            for _ in 0..runs {
+                let start = byte(at)? as usize;
+                let len = byte(at + 1)? as usize;
+                if start + len > FRAME_LEN {
+                    return Err(invalid);
+                }
+                let values = data
+                    .get(at + 2..at + 2 + len)
+                    .ok_or(FormatError::Truncated)?;
+                frame[start..start + len].copy_from_slice(values);
+                dirty(start, len);
+                at += 2 + len;
+            }
+            Ok((at, false))
+        }
+        _ => Err(invalid),
+    }
+}
+
+/// Plays an animation stored in the format described in the [module](self) documentation.
+///
+/// Key frames are written with [fill_matrix](IS31FL3743::fill_matrix), delta frames only write
+/// the ranges that changed, unless a full frame is cheaper.
+pub struct Player<'a> {
+    data: &'a [u8],
+    pub header: Header,
+    /// Start over after the last frame
+    pub looping: bool,
+    frame: FrameBuffer,
+    /// Offset of the next frame in `data`
+    offset: usize,
+    /// Index of the next frame
+    index: u16,
+    next_at: Option<u32>,
+}
+
+impl<'a> Player<'a> {
+    /// Check the whole animation and prepare to play it from the first frame. The animation
+    /// must have been made for a display of the same size as `device`.
+    pub fn new<I2C>(data: &'a [u8], device: &IS31FL3743<I2C>) -> Result<Self, FormatError> {
+        let header = Header::parse(data)?;
+        if (header.width, header.height) != (device.width, device.height) {
+            return Err(FormatError::SizeMismatch(header.width, header.height));
+        }
+        let mut frame = [0x00; FRAME_LEN];
+        let mut offset = HEADER_LEN;
+        for index in 0..header.frame_count {
+            offset = decode_frame(data, offset, index, &mut frame, |_, _| {})?.0;
+        }
+        Ok(Player {
+            data,
+            header,
+            looping: true,
+            frame: FrameBuffer::new(),
+            offset: HEADER_LEN,
+            index: 0,
+            next_at: None,
+        })
+    }
+
+    /// Start over at the first frame with the next tick
+    pub fn rewind(&mut self) {
+        self.frame.clear();
+        self.offset = HEADER_LEN;
+        self.index = 0;
+        self.next_at = None;
+    }
+
+    /// Whether the last frame has been shown and the player isn't looping
+    pub fn is_done(&self) -> bool {
+        !self.looping && self.index >= self.header.frame_count
+    }
+
+    /// The frame currently shown
+    pub fn frame(&self) -> &FrameBuffer {
+        &self.frame
+    }
+
+    /// Write the next frame if it is due at `now_ms`. The first call shows the first frame.
+    /// Returns whether a frame was written. Writes to a device or through a
+    /// [PowerLimited](crate::power::PowerLimited), see [Output].
+    pub fn tick<O: Output>(&mut self, output: &mut O, now_ms: u32) -> Result<bool, O::Error> {
+        if self.header.frame_count == 0 {
+            return Ok(false);
+        }
+        if let Some(next_at) = self.next_at {
+            // Not due yet, also works when the timer wraps around
+            if now_ms.wrapping_sub(next_at) > u32::MAX / 2 {
+                return Ok(false);
+            }
+        }
+        if self.index >= self.header.frame_count {
+            if !self.looping {
+                return Ok(false);
+            }
+            self.rewind();
+        }
+
+        let first = self.index == 0;
+        let mut ranges = [(0u8, 0u8); 16];
+        let mut range_count = 0;
+        let mut cost = 0;
+        let (offset, full) = decode_frame(
+            self.data,
+            self.offset,
+            self.index,
+            &mut self.frame.pwm,
+            |start, len| {
+                if range_count < ranges.len() {
+                    ranges[range_count] = (start as u8, len as u8);
+                }
+                range_count += 1;
+                cost += len + BURST_OVERHEAD;
+            },
+        )
+        // Checked in new
+        .unwrap_or((self.data.len(), true));
+        self.offset = offset;
+        self.index += 1;
+        self.next_at = Some(now_ms.wrapping_add(self.header.frame_duration_ms as u32));
+
+        if full || first || range_count > ranges.len() || cost > FRAME_LEN + BURST_OVERHEAD {
+            self.frame.flush(output)?;
+        } else {
+            for &(start, len) in &ranges[..range_count] {
+                let start = start as usize;
+                output.write_range(start, &self.frame.pwm[start..start + len as usize])?;
+            }
+        }
+        Ok(true)
+    }
+}
+
+/// Encode frames into an animation. Every frame is stored in the smallest of the three
+/// encodings.
+#[cfg(feature = "std")]
+pub fn encode(
+    width: u8,
+    height: u8,
+    frame_duration_ms: u16,
+    frames: &[[u8; FRAME_LEN]],
+) -> Result<Vec<u8>, FormatError> {
+    let frame_count = u16::try_from(frames.len()).map_err(|_| FormatError::TooManyFrames)?;
+    let header = Header {
+        width,
+        height,
+        frame_count,
+        frame_duration_ms,
+    };
+    let mut data = header.to_bytes().to_vec();
+    let mut previous = [0x00; FRAME_LEN];
+    for (index, frame) in frames.iter().enumerate() {
+        let mut best = encode_key(frame);
+        for candidate in [Some(encode_rle(frame)), encode_delta(&previous, frame)]
+            .into_iter()
+            .flatten()
+        {
+            if candidate.len() < best.len() {
+                best = candidate;
+            }
+        }
+        // The first frame is shown in full when looping, so it must not depend on the last one
+        if index == 0 && best[0] == TAG_DELTA {
+            best = encode_key(frame);
+        }
+        data.extend_from_slice(&best);
+        previous = *frame;
+    }
+    Ok(data)
+}
+
+#[cfg(feature = "std")]
+fn encode_key(frame: &[u8; FRAME_LEN]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(1 + FRAME_LEN);
+    out.push(TAG_KEY);
+    out.extend_from_slice(frame);
+    out
+}
+
+#[cfg(feature = "std")]
+fn encode_rle(frame: &[u8; FRAME_LEN]) -> Vec<u8> {
+    let mut out = std::vec![TAG_RLE];
+    let mut at = 0;
+    while at < FRAME_LEN {
+        let value = frame[at];
+        let count = frame[at..]
+            .iter()
+            .take(0xFF)
+            .take_while(|&&other| other == value)
+            .count();
+        out.extend_from_slice(&[count as u8, value]);
+        at += count;
+    }
+    out
+}
+
+#[cfg(feature = "std")]
+fn encode_delta(previous: &[u8; FRAME_LEN], frame: &[u8; FRAME_LEN]) -> Option<Vec<u8>> {
+    // Changed ranges, merged if the gap between them is cheaper than a new run header
+    let mut runs: Vec<(usize, usize)> = Vec::new();
+    for at in (0..FRAME_LEN).filter(|&at| previous[at] != frame[at]) {
+        match runs.last_mut() {
+            Some((start, len)) if at - (*start + *len) <= 2 => *len = at + 1 - *start,
+            _ => runs.push((at, 1)),
+        }
+    }
+    if runs.len() > 0xFF {
+        return None;
+    }
+    let mut out = std::vec![TAG_DELTA, runs.len() as u8];
+    for (start, len) in runs {
+        out.extend_from_slice(&[start as u8, len as u8]);
+        out.extend_from_slice(&frame[start..start + len]);
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, Bus};
+    use crate::{CS_PINS, SW_PINS};
+
+    fn device(width: u8, height: u8) -> IS31FL3743<Bus> {
+        IS31FL3743 {
+            width,
+            height,
+            ..mock::device()
+        }
+    }
+
+    /// An animation with one key frame where every register holds `value`
+    fn animation(width: u8, height: u8, value: u8) -> [u8; HEADER_LEN + 1 + FRAME_LEN] {
+        let header = Header {
+            width,
+            height,
+            frame_count: 1,
+            frame_duration_ms: 10,
+        };
+        let mut data = [value; HEADER_LEN + 1 + FRAME_LEN];
+        data[..HEADER_LEN].copy_from_slice(&header.to_bytes());
+        data[HEADER_LEN] = TAG_KEY;
+        data
+    }
+
+    #[test]
+    fn player_checks_the_display_size() {
+        let data = animation(CS_PINS, SW_PINS, 0x42);
+        assert_eq!(
+            Player::new(&data, &device(6, SW_PINS)).err(),
+            Some(FormatError::SizeMismatch(CS_PINS, SW_PINS))
+        );
+        assert_eq!(
+            Player::new(&data, &device(CS_PINS, 1)).err(),
+            Some(FormatError::SizeMismatch(CS_PINS, SW_PINS))
+        );
+
+        let mut device = device(CS_PINS, SW_PINS);
+        let mut player = Player::new(&data, &device).unwrap();
+        assert!(player.tick(&mut device, 0).unwrap());
+        assert!(device.i2c.pwm().iter().all(|&pwm| pwm == 0x42));
+    }
+
+    #[test]
+    fn player_checks_every_frame() {
+        let mut data = animation(CS_PINS, SW_PINS, 0x00);
+        data[HEADER_LEN] = 0x07;
+        let device = device(CS_PINS, SW_PINS);
+        assert_eq!(
+            Player::new(&data, &device).err(),
+            Some(FormatError::InvalidFrame(0))
+        );
+        data[HEADER_LEN] = TAG_KEY;
+        assert_eq!(
+            Player::new(&data[..HEADER_LEN + 10], &device).err(),
+            Some(FormatError::Truncated)
+        );
+    }
+
+    /// Registers written since the last call, as `(register, value)`
+    #[cfg(feature = "std")]
+    fn take_writes(device: &mut IS31FL3743<Bus>) -> Vec<(u8, u8)> {
+        let writes = device.i2c.writes.drain(..);
+        writes
+            .map(|(page, register, value)| {
+                assert_eq!(page, 0, "only the PWM page is written");
+                (register, value)
+            })
+            .collect()
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn encode_and_play() {
+        let lit = [0x10; FRAME_LEN];
+        let mut flash = lit;
+        flash[5..8].copy_from_slice(&[0x80, 0x81, 0x82]);
+        let mut gradient = [0x00; FRAME_LEN];
+        for (at, pwm) in gradient.iter_mut().enumerate() {
+            *pwm = (at * 7) as u8;
+        }
+        let mut sparkle = gradient;
+        sparkle[0] = 0xFF;
+        sparkle[100..102].fill(0xFF);
+        let frames = [lit, flash, gradient, sparkle];
+
+        let data = encode(CS_PINS, SW_PINS, 20, &frames).unwrap();
+        // RLE, delta, key and delta with two runs
+        let mut expected = Header {
+            width: CS_PINS,
+            height: SW_PINS,
+            frame_count: 4,
+            frame_duration_ms: 20,
+        }
+        .to_bytes()
+        .to_vec();
+        expected.extend_from_slice(&[TAG_RLE, 0xC6, 0x10]);
+        expected.extend_from_slice(&[TAG_DELTA, 1, 5, 3, 0x80, 0x81, 0x82]);
+        expected.push(TAG_KEY);
+        expected.extend_from_slice(&gradient);
+        expected.extend_from_slice(&[TAG_DELTA, 2, 0, 1, 0xFF, 100, 2, 0xFF, 0xFF]);
+        assert_eq!(data, expected);
+
+        let mut device = mock::device();
+        let mut player = Player::new(&data, &device).unwrap();
+        player.looping = false;
+
+        // The first frame is written in full, even though it's stored as RLE
+        assert!(player.tick(&mut device, 0).unwrap());
+        let full: Vec<(u8, u8)> = (1..=0xC6).map(|register| (register, 0x10)).collect();
+        assert_eq!(take_writes(&mut device), full);
+        assert!(!player.tick(&mut device, 19).unwrap());
+        assert!(take_writes(&mut device).is_empty());
+
+        // Only the changed range
+        assert!(player.tick(&mut device, 20).unwrap());
+        assert_eq!(
+            take_writes(&mut device),
+            [(0x06, 0x80), (0x07, 0x81), (0x08, 0x82)]
+        );
+        assert_eq!(device.i2c.pwm(), flash);
+
+        assert!(player.tick(&mut device, 40).unwrap());
+        assert_eq!(take_writes(&mut device).len(), FRAME_LEN);
+        assert_eq!(device.i2c.pwm(), gradient);
+
+        // One burst per run
+        assert!(player.tick(&mut device, 60).unwrap());
+        assert_eq!(
+            take_writes(&mut device),
+            [(0x01, 0xFF), (0x65, 0xFF), (0x66, 0xFF)]
+        );
+        assert_eq!(device.i2c.pwm(), sparkle);
+        assert_eq!(player.frame().pwm, sparkle);
+
+        assert!(player.is_done());
+        assert!(!player.tick(&mut device, 80).unwrap());
+    }
+}
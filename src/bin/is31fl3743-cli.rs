@@ -0,0 +1,38 @@
+//! Control an IS31FL3743A from Linux over i2c-dev. Run with `--help` for the usage.
+use is31fl3743a::cli::{self, Args, Command, USAGE};
+use linux_embedded_hal::{Delay, I2cdev};
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if args.command == Command::Help {
+        print!("{USAGE}");
+        return ExitCode::SUCCESS;
+    }
+    let i2c = match I2cdev::new(&args.bus) {
+        Ok(i2c) => i2c,
+        Err(err) => {
+            eprintln!("error: failed to open {}: {err}", args.bus);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut device = cli::bare_device(i2c, args.address);
+    match cli::run(
+        &mut device,
+        &mut Delay,
+        &args.command,
+        &mut std::io::stdout(),
+    ) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,119 @@
+//! Convert PNG images, PNG strips and GIF animations into PWM frames.
+//!
+//! Pixels are mapped onto a bare IS31FL3743A: with `--order mono` every pixel is one LED
+//! (18x11), otherwise every pixel is an RGB LED on three adjacent CS pins (6x11).
+//!
+//! ```text
+//! is31fl3743-convert [OPTIONS] <INPUT>
+//!
+//!   --order <mono|rgb|grb|bgr|...>  Channel order of the LEDs [default: mono]
+//!   --width <N>                     Width of the display [default: whole chip]
+//!   --height <N>                    Height of the display [default: 11]
+//!   --brightness <0-255>            Brightness of a white pixel [default: 255]
+//!   --dither                        Dither rounding errors
+//!   --strip <N>                     Width of one frame in a PNG strip
+//!   --format <blob|rust|animation>  Output format [default: blob]
+//!   --name <NAME>                   Name of the array for --format rust [default: FRAMES]
+//!   --duration <MS>                 Frame duration for --format animation [default: 100]
+//!   -o, --output <FILE>             Output file [default: stdout]
+//! ```
+use is31fl3743a::color::ColorOrder;
+use is31fl3743a::convert::{self, Mapping, Options};
+use is31fl3743a::{register, CS_PINS, SW_PINS};
+use std::io::Write;
+use std::process::ExitCode;
+
+fn parse_order(order: &str) -> Option<ColorOrder> {
+    Some(match order {
+        "mono" => ColorOrder::Mono,
+        "rgb" => ColorOrder::Rgb,
+        "rbg" => ColorOrder::Rbg,
+        "grb" => ColorOrder::Grb,
+        "gbr" => ColorOrder::Gbr,
+        "brg" => ColorOrder::Brg,
+        "bgr" => ColorOrder::Bgr,
+        _ => return None,
+    })
+}
+
+fn run() -> Result<(), String> {
+    let mut args = std::env::args().skip(1);
+    let mut input = None;
+    let mut order = ColorOrder::Mono;
+    let mut width = None;
+    let mut height = SW_PINS;
+    let mut brightness = 0xFF;
+    let mut dither = false;
+    let mut strip_width = None;
+    let mut format = String::from("blob");
+    let mut name = String::from("FRAMES");
+    let mut duration = 100;
+    let mut output = None;
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or(format!("missing value for {arg}"));
+        match arg.as_str() {
+            "--order" => {
+                let value = value()?;
+                order = parse_order(&value).ok_or(format!("unknown order {value}"))?;
+            }
+            "--width" => width = Some(value()?.parse().map_err(|err| format!("{err}"))?),
+            "--height" => height = value()?.parse().map_err(|err| format!("{err}"))?,
+            "--brightness" => brightness = value()?.parse().map_err(|err| format!("{err}"))?,
+            "--dither" => dither = true,
+            "--strip" => strip_width = Some(value()?.parse().map_err(|err| format!("{err}"))?),
+            "--format" => format = value()?,
+            "--name" => name = value()?,
+            "--duration" => duration = value()?.parse().map_err(|err| format!("{err}"))?,
+            "-o" | "--output" => output = Some(value()?),
+            _ if arg.starts_with('-') => return Err(format!("unknown option {arg}")),
+            _ => input = Some(arg),
+        }
+    }
+    let input = input.ok_or("missing input file")?;
+
+    let (calc_pixel, max_width): (fn(u8, u8) -> u8, u8) = match order {
+        ColorOrder::Mono => (|x, y| register(y, x), CS_PINS),
+        _ => (|x, y| register(y, 3 * x), CS_PINS / 3),
+    };
+    let width = width.unwrap_or(max_width);
+    if width > max_width || height > SW_PINS {
+        return Err(format!("display is at most {max_width}x{SW_PINS}"));
+    }
+    let mut options = Options::new(
+        Mapping {
+            width,
+            height,
+            calc_pixel,
+        },
+        order,
+    );
+    options.brightness = brightness;
+    options.dither = dither;
+    options.strip_width = strip_width;
+
+    let converted = convert::convert_file(&input, &options).map_err(|err| format!("{err}"))?;
+    let bytes = match format.as_str() {
+        "blob" => converted.to_blob(),
+        "rust" => converted.to_rust(&name).into_bytes(),
+        "animation" => converted
+            .to_animation(duration)
+            .map_err(|err| format!("{err:?}"))?,
+        _ => return Err(format!("unknown format {format}")),
+    };
+    match output {
+        Some(path) => std::fs::write(path, bytes),
+        None => std::io::stdout().write_all(&bytes),
+    }
+    .map_err(|err| format!("{err}"))
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {err}");
+            ExitCode::FAILURE
+        }
+    }
+}
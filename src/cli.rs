@@ -0,0 +1,547 @@
+use crate::animation::{self, FormatError, Player};
+use crate::{register, scan, Address, Error, PwmFreq, CS_PINS, IS31FL3743, SW_PINS};
+use core::convert::TryFrom;
+use core::fmt;
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+use std::format;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::string::String;
+use std::time::Instant;
+use std::vec::Vec;
+
+/// Usage of the `is31fl3743-cli` binary
+pub const USAGE: &str = "\
+Usage: is31fl3743-cli [--bus <PATH>] [--address <ADDR>] <COMMAND>
+
+Options:
+  -h, --help         Print this help
+  --bus <PATH>       I2C bus device [default: /dev/i2c-1]
+  --address <ADDR>   7-bit address of the controller, 0x20 to 0x2F [default: 0x20]
+
+Commands:
+  probe                      Scan the bus for controllers
+  setup                      Reset and set up the controller
+  fill <BRIGHTNESS>          Set all LEDs to one brightness
+  pixel <CS> <SW> <BRIGHTNESS>
+                             Set the brightness of a single LED, both zero based
+  dump                       Print all registers
+  self-test                  Run the production self test, fails if the board doesn't pass
+  pwm-freq <FREQ>            Set the PWM frequency: 29k, 31k25, 15k6, 7k8, 3k9, 1k95, 977, 488
+  scaling <SCALE>            Set the scaling of all LEDs
+  frame <FILE> [INTERVAL_MS] Push raw 0xC6 byte frames or an animation file
+";
+
+/// A parsed subcommand
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Print the usage, without touching the bus
+    Help,
+    Probe,
+    Setup,
+    Fill(u8),
+    Pixel {
+        x: u8,
+        y: u8,
+        brightness: u8,
+    },
+    Dump,
+    SelfTest,
+    PwmFreq(PwmFreq),
+    Scaling(u8),
+    /// Raw frames are shown `interval_ms` apart, animations use their own timing
+    Frame {
+        path: PathBuf,
+        interval_ms: u32,
+    },
+}
+
+/// Parsed command line
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Args {
+    pub bus: String,
+    pub address: Address,
+    pub command: Command,
+}
+
+fn parse_number<T: TryFrom<u32>>(value: &str) -> Result<T, String> {
+    let number = match value.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => value.parse(),
+    }
+    .map_err(|_| format!("invalid number {value}"))?;
+    T::try_from(number).map_err(|_| format!("{value} is out of range"))
+}
+
+fn parse_pwm_freq(value: &str) -> Result<PwmFreq, String> {
+    Ok(match value {
+        "29k" => PwmFreq::P29k,
+        "31k25" => PwmFreq::P31k25,
+        "15k6" => PwmFreq::P15k6,
+        "7k8" => PwmFreq::P7k8,
+        "3k9" => PwmFreq::P3k9,
+        "1k95" => PwmFreq::P1k95,
+        "977" => PwmFreq::P977,
+        "488" => PwmFreq::P488,
+        _ => return Err(format!("unknown PWM frequency {value}")),
+    })
+}
+
+impl Args {
+    /// Parse the arguments, without the name of the binary
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Args, String> {
+        let mut args = args.into_iter();
+        let mut bus = String::from("/dev/i2c-1");
+        let mut address = Address::MIN;
+        let mut positional = Vec::new();
+        let mut help = false;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--bus" => bus = args.next().ok_or("missing value for --bus")?,
+                "--address" => {
+                    let value = args.next().ok_or("missing value for --address")?;
+                    address = Address::try_from(parse_number::<u8>(&value)?)
+                        .map_err(|_| format!("{value} is not a valid address"))?;
+                }
+                "-h" | "--help" => help = true,
+                _ => positional.push(arg),
+            }
+        }
+
+        if help {
+            return Ok(Args {
+                bus,
+                address,
+                command: Command::Help,
+            });
+        }
+
+        let mut positional = positional.iter().map(String::as_str);
+        let mut next = |name: &str| positional.next().ok_or(format!("missing {name}"));
+        let command = match next("command")? {
+            "probe" => Command::Probe,
+            "setup" => Command::Setup,
+            "fill" => Command::Fill(parse_number(next("brightness")?)?),
+            "pixel" => Command::Pixel {
+                x: parse_number(next("x")?)?,
+                y: parse_number(next("y")?)?,
+                brightness: parse_number(next("brightness")?)?,
+            },
+            "dump" => Command::Dump,
+            "self-test" => Command::SelfTest,
+            "pwm-freq" => Command::PwmFreq(parse_pwm_freq(next("frequency")?)?),
+            "scaling" => Command::Scaling(parse_number(next("scale")?)?),
+            "frame" => Command::Frame {
+                path: PathBuf::from(next("file")?),
+                interval_ms: match next("interval") {
+                    Ok(interval) => parse_number(interval)?,
+                    Err(_) => 100,
+                },
+            },
+            command => return Err(format!("unknown command {command}\n\n{USAGE}")),
+        };
+        Ok(Args {
+            bus,
+            address,
+            command,
+        })
+    }
+}
+
+/// Errors while running a command
+#[derive(Debug)]
+pub enum CliError<E> {
+    Device(Error<E>),
+    Io(io::Error),
+    Format(FormatError),
+    /// A frame file whose length isn't a multiple of 0xC6
+    InvalidFrameFile(usize),
+    /// The self test ran, but the board didn't pass
+    SelfTestFailed,
+}
+
+impl<E: fmt::Debug> fmt::Display for CliError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CliError::Device(err) => write!(f, "device error: {err:?}"),
+            CliError::Io(err) => write!(f, "{err}"),
+            CliError::Format(err) => write!(f, "invalid animation: {err:?}"),
+            CliError::InvalidFrameFile(len) => {
+                write!(f, "frame file of {len} bytes isn't a multiple of 0xC6")
+            }
+            CliError::SelfTestFailed => write!(f, "self test failed"),
+        }
+    }
+}
+
+impl<E> From<Error<E>> for CliError<E> {
+    fn from(err: Error<E>) -> Self {
+        CliError::Device(err)
+    }
+}
+
+impl<E> From<io::Error> for CliError<E> {
+    fn from(err: io::Error) -> Self {
+        CliError::Io(err)
+    }
+}
+
+/// A device spanning the whole chip, one register per LED. x is the CS pin, y the SW pin.
+pub fn bare_device<I2C>(i2c: I2C, address: Address) -> IS31FL3743<I2C> {
+    IS31FL3743::new(i2c, address, CS_PINS, SW_PINS, |x: u8, y: u8| -> u8 {
+        register(y, x)
+    })
+}
+
+/// Run a command against a device and print its output. Works with any I2C bus, including
+/// mocks.
+pub fn run<I2C, I2cError, DEL, W>(
+    device: &mut IS31FL3743<I2C>,
+    delay: &mut DEL,
+    command: &Command,
+    out: &mut W,
+) -> Result<(), CliError<I2cError>>
+where
+    I2C: I2c<Error = I2cError>,
+    I2cError: embedded_hal::i2c::Error,
+    DEL: DelayNs,
+    W: Write,
+{
+    let device_err = |err: I2cError| CliError::Device(Error::I2cError(err));
+    match command {
+        Command::Help => write!(out, "{USAGE}")?,
+        Command::Probe => {
+            let found = scan(&mut device.i2c).map_err(device_err)?;
+            if found.is_empty() {
+                writeln!(out, "No controller found")?;
+            }
+            for address in found.iter() {
+                writeln!(
+                    out,
+                    "0x{:02X} (ADDR1 {:?}, ADDR2 {:?})",
+                    address.as_u8(),
+                    address.addr1(),
+                    address.addr2()
+                )?;
+            }
+        }
+        Command::Setup => {
+            // Every run starts with a new driver, keep the frequency set by an earlier run
+            if let Some(pwm_freq) = device.pwm_freq().map_err(device_err)? {
+                device.pwm_freq = pwm_freq;
+            }
+            device.setup(delay)?
+        }
+        Command::Fill(brightness) => device.fill(*brightness).map_err(device_err)?,
+        Command::Pixel { x, y, brightness } => device.pixel(*x, *y, *brightness)?,
+        Command::Dump => {
+            let config = device.read_config().map_err(device_err)?;
+            writeln!(out, "{config}")?;
+            let snapshot = device.snapshot().map_err(device_err)?;
+            for (name, page) in [("PWM", &snapshot.pwm), ("Scaling", &snapshot.scaling)] {
+                writeln!(out, "{name}:")?;
+                for (sw, row) in page.chunks(CS_PINS as usize).enumerate() {
+                    write!(out, "  SW{:<2}", sw + 1)?;
+                    for value in row {
+                        write!(out, " {value:02X}")?;
+                    }
+                    writeln!(out)?;
+                }
+            }
+        }
+        Command::SelfTest => {
+            let report = device.self_test(delay).map_err(device_err)?;
+            writeln!(out, "{report}")?;
+            if !report.passed() {
+                return Err(CliError::SelfTestFailed);
+            }
+        }
+        Command::PwmFreq(freq) => device.set_pwm_freq(delay, *freq)?,
+        Command::Scaling(scale) => device.set_scaling(*scale).map_err(device_err)?,
+        Command::Frame { path, interval_ms } => {
+            let data = std::fs::read(path)?;
+            if data.starts_with(&animation::MAGIC) {
+                // Frames hold raw registers, so the display the animation was made for, like
+                // 6x11 RGB LEDs, only has to fit onto the chip
+                let header = animation::Header::parse(&data).map_err(CliError::Format)?;
+                if header.width > CS_PINS || header.height > SW_PINS {
+                    return Err(CliError::Format(FormatError::SizeMismatch(
+                        header.width,
+                        header.height,
+                    )));
+                }
+                let size = (device.width, device.height);
+                (device.width, device.height) = (header.width, header.height);
+                let played = play(device, delay, &data);
+                (device.width, device.height) = size;
+                played?;
+            } else {
+                if data.is_empty() || data.len() % 0xC6 != 0 {
+                    return Err(CliError::InvalidFrameFile(data.len()));
+                }
+                for (index, frame) in data.chunks(0xC6).enumerate() {
+                    if index > 0 {
+                        delay.delay_ms(*interval_ms);
+                    }
+                    device.fill_matrix(frame).map_err(device_err)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Play an animation made for the size of `device` once
+fn play<I2C, I2cError, DEL>(
+    device: &mut IS31FL3743<I2C>,
+    delay: &mut DEL,
+    data: &[u8],
+) -> Result<(), CliError<I2cError>>
+where
+    I2C: I2c<Error = I2cError>,
+    DEL: DelayNs,
+{
+    let mut player = Player::new(data, device).map_err(CliError::Format)?;
+    player.looping = false;
+    let start = Instant::now();
+    while !player.is_done() {
+        player
+            .tick(device, start.elapsed().as_millis() as u32)
+            .map_err(|err| CliError::Device(Error::I2cError(err)))?;
+        delay.delay_ms(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{Bus, NoDelay};
+    use embedded_hal::i2c::ErrorKind;
+    use std::vec;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|&arg| String::from(arg)))
+    }
+
+    fn command(args: &[&str]) -> Command {
+        parse(args).unwrap().command
+    }
+
+    fn run_on(
+        bus: Bus,
+        command: &Command,
+    ) -> (IS31FL3743<Bus>, Result<String, CliError<ErrorKind>>) {
+        let address = Address::try_from(bus.address).unwrap();
+        let mut device = bare_device(bus, address);
+        let mut out = Vec::new();
+        let result = run(&mut device, &mut NoDelay, command, &mut out);
+        let result = result.map(|()| String::from_utf8(out).unwrap());
+        (device, result)
+    }
+
+    #[test]
+    fn help_is_not_an_error() {
+        assert_eq!(command(&["--help"]), Command::Help);
+        assert_eq!(command(&["fill", "-h"]), Command::Help);
+        let (_, out) = run_on(Bus::new(0x20), &Command::Help);
+        assert_eq!(out.unwrap(), USAGE);
+    }
+
+    #[test]
+    fn parse_options() {
+        let args = parse(&["--bus", "/dev/i2c-3", "--address", "0x23", "probe"]).unwrap();
+        assert_eq!(args.bus, "/dev/i2c-3");
+        assert_eq!(args.address.as_u8(), 0x23);
+        assert!(parse(&["--address", "0x30", "probe"]).is_err());
+        assert!(parse(&["fill", "256"]).is_err());
+        assert!(parse(&["frobnicate"]).is_err());
+        assert!(parse(&[]).is_err());
+        assert_eq!(
+            command(&["pixel", "1", "2", "0xFF"]),
+            Command::Pixel {
+                x: 1,
+                y: 2,
+                brightness: 0xFF
+            }
+        );
+        assert_eq!(
+            command(&["pwm-freq", "977"]),
+            Command::PwmFreq(PwmFreq::P977)
+        );
+    }
+
+    #[test]
+    fn probe_lists_controllers() {
+        let (_, out) = run_on(Bus::new(0x23), &Command::Probe);
+        assert_eq!(out.unwrap(), "0x23 (ADDR1 Vcc, ADDR2 Gnd)\n");
+        let (_, out) = run_on(Bus::new(0x23), &command(&["--address", "0x23", "probe"]));
+        assert!(out.unwrap().starts_with("0x23"));
+    }
+
+    #[test]
+    fn fill_pixel_and_scaling() {
+        let (device, out) = run_on(Bus::new(0x20), &command(&["fill", "0x10"]));
+        out.unwrap();
+        assert!(device.i2c.pwm().iter().all(|&pwm| pwm == 0x10));
+
+        let (device, out) = run_on(Bus::new(0x20), &command(&["pixel", "17", "10", "7"]));
+        out.unwrap();
+        assert_eq!(device.i2c.pwm()[register(10, 17) as usize - 1], 7);
+
+        let (device, out) = run_on(Bus::new(0x20), &command(&["scaling", "0x80"]));
+        out.unwrap();
+        assert!(device.i2c.scaling().iter().all(|&scale| scale == 0x80));
+    }
+
+    #[test]
+    fn pixel_outside_of_the_chip() {
+        for args in [["pixel", "18", "0", "1"], ["pixel", "0", "11", "1"]] {
+            let (device, out) = run_on(Bus::new(0x20), &command(&args));
+            assert!(matches!(
+                out,
+                Err(CliError::Device(Error::InvalidLocation(_)))
+            ));
+            assert!(device.i2c.pwm().iter().all(|&pwm| pwm == 0));
+        }
+    }
+
+    #[test]
+    fn setup_and_pwm_freq() {
+        let (mut device, out) = run_on(Bus::new(0x20), &command(&["pwm-freq", "3k9"]));
+        out.unwrap();
+        assert_eq!(device.pwm_freq().unwrap(), Some(PwmFreq::P3k9));
+
+        // A later run of the tool
+        let (mut device, out) = run_on(device.i2c, &Command::Setup);
+        out.unwrap();
+        assert_eq!(device.pwm_freq().unwrap(), Some(PwmFreq::P3k9));
+        let config: crate::registers::Configuration = device.get_register().unwrap();
+        assert!(!config.shutdown());
+    }
+
+    #[test]
+    fn dump_prints_both_pages() {
+        let mut bus = Bus::new(0x20);
+        bus.pages[0][0x01] = 0xAB;
+        let (_, out) = run_on(bus, &Command::Dump);
+        let out = out.unwrap();
+        assert!(out.contains("PWM:\n  SW1  AB 00"));
+        assert!(out.contains("Scaling:\n  SW1  00"));
+        assert!(out.contains("SW11"));
+    }
+
+    #[test]
+    fn self_test_fails_without_controller() {
+        let (_, out) = run_on(Bus::new(0x20), &Command::SelfTest);
+        assert!(out.unwrap().contains("PASS"));
+
+        let mut device = bare_device(Bus::new(0x21), Address::MIN);
+        let result = run(
+            &mut device,
+            &mut NoDelay,
+            &Command::SelfTest,
+            &mut Vec::new(),
+        );
+        assert!(matches!(result, Err(CliError::SelfTestFailed)));
+    }
+
+    #[test]
+    fn raw_frames() {
+        let path = std::env::temp_dir().join(format!("is31fl3743-frames-{}", std::process::id()));
+        let mut frames = vec![0x11; 0xC6];
+        frames.extend_from_slice(&[0x22; 0xC6]);
+        std::fs::write(&path, &frames).unwrap();
+        let (device, out) = run_on(
+            Bus::new(0x20),
+            &Command::Frame {
+                path: path.clone(),
+                interval_ms: 0,
+            },
+        );
+        out.unwrap();
+        assert!(device.i2c.pwm().iter().all(|&pwm| pwm == 0x22));
+
+        std::fs::write(&path, [0x00; 10]).unwrap();
+        let (_, out) = run_on(
+            Bus::new(0x20),
+            &Command::Frame {
+                path: path.clone(),
+                interval_ms: 0,
+            },
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(out, Err(CliError::InvalidFrameFile(10))));
+    }
+
+    #[test]
+    #[cfg(feature = "convert")]
+    fn converted_rgb_animation() {
+        use crate::color::ColorOrder;
+        use crate::convert::{self, Converted, Mapping, Options};
+        use image::{Rgba, RgbaImage};
+
+        // Two frames of 6x11 RGB LEDs: all red, then all blue
+        let mut image = RgbaImage::from_pixel(12, 11, Rgba([0xFF, 0x00, 0x00, 0xFF]));
+        for x in 6..12 {
+            for y in 0..11 {
+                image.put_pixel(x, y, Rgba([0x00, 0x00, 0xFF, 0xFF]));
+            }
+        }
+        let mut options = Options::new(
+            Mapping {
+                width: 6,
+                height: 11,
+                calc_pixel: |x, y| register(y, 3 * x),
+            },
+            ColorOrder::Rgb,
+        );
+        options.strip_width = Some(6);
+        let frames = convert::convert_image(&image, &options).unwrap();
+        let converted = Converted {
+            width: 6,
+            height: 11,
+            durations_ms: vec![0; frames.len()],
+            frames,
+        };
+        let path =
+            std::env::temp_dir().join(format!("is31fl3743-animation-{}", std::process::id()));
+        std::fs::write(&path, converted.to_animation(1).unwrap()).unwrap();
+
+        let (device, out) = run_on(
+            Bus::new(0x20),
+            &Command::Frame {
+                path: path.clone(),
+                interval_ms: 0,
+            },
+        );
+        std::fs::remove_file(&path).unwrap();
+        out.unwrap();
+        for (index, &pwm) in device.i2c.pwm().iter().enumerate() {
+            let blue = index % 3 == 2;
+            assert_eq!(pwm, if blue { 0xFF } else { 0x00 });
+        }
+        // The device keeps its own size
+        assert_eq!((device.width, device.height), (CS_PINS, SW_PINS));
+    }
+
+    #[test]
+    fn animation_larger_than_the_chip() {
+        let path = std::env::temp_dir().join(format!("is31fl3743-large-{}", std::process::id()));
+        let data = animation::encode(CS_PINS + 1, SW_PINS, 10, &[[0x00; 0xC6]]).unwrap();
+        std::fs::write(&path, data).unwrap();
+        let (_, out) = run_on(
+            Bus::new(0x20),
+            &Command::Frame {
+                path: path.clone(),
+                interval_ms: 0,
+            },
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(
+            out,
+            Err(CliError::Format(FormatError::SizeMismatch(19, 11)))
+        ));
+    }
+}
@@ -0,0 +1,148 @@
+/// A color with 8 bits per channel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const BLACK: Rgb = Rgb::new(0x00, 0x00, 0x00);
+    pub const WHITE: Rgb = Rgb::new(0xFF, 0xFF, 0xFF);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Rgb { r, g, b }
+    }
+
+    /// Scale all channels by `scale / 256`
+    pub const fn scale(self, scale: u8) -> Self {
+        Rgb::new(
+            scale8(self.r, scale),
+            scale8(self.g, scale),
+            scale8(self.b, scale),
+        )
+    }
+
+    /// Brightness of the color, for single color LEDs
+    pub const fn luma(self) -> u8 {
+        ((self.r as u16 * 54 + self.g as u16 * 183 + self.b as u16 * 19) >> 8) as u8
+    }
+}
+
+/// A color as hue, saturation and value. A hue of 0 and 255 are both red.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Hsv {
+    pub h: u8,
+    pub s: u8,
+    pub v: u8,
+}
+
+impl Hsv {
+    pub const fn new(h: u8, s: u8, v: u8) -> Self {
+        Hsv { h, s, v }
+    }
+
+    pub const fn to_rgb(self) -> Rgb {
+        let Hsv { h, s, v } = self;
+        if s == 0 {
+            return Rgb::new(v, v, v);
+        }
+        let (v, s) = (v as u16, s as u16);
+        let region = h / 43;
+        let remainder = (h - region * 43) as u16 * 6;
+        let p = ((v * (255 - s)) >> 8) as u8;
+        let q = ((v * (255 - ((s * remainder) >> 8))) >> 8) as u8;
+        let t = ((v * (255 - ((s * (255 - remainder)) >> 8))) >> 8) as u8;
+        let v = v as u8;
+        match region {
+            0 => Rgb::new(v, t, p),
+            1 => Rgb::new(q, v, p),
+            2 => Rgb::new(p, v, t),
+            3 => Rgb::new(p, q, v),
+            4 => Rgb::new(t, p, v),
+            _ => Rgb::new(v, p, q),
+        }
+    }
+}
+
+impl From<Hsv> for Rgb {
+    fn from(hsv: Hsv) -> Rgb {
+        hsv.to_rgb()
+    }
+}
+
+/// Order in which the channels of one LED are connected to adjacent CS pins
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorOrder {
+    /// Single color LEDs, one register per LED
+    Mono,
+    Rgb,
+    Rbg,
+    Grb,
+    Gbr,
+    Brg,
+    Bgr,
+}
+
+impl ColorOrder {
+    /// Number of registers one LED takes
+    pub const fn channels(self) -> u8 {
+        match self {
+            ColorOrder::Mono => 1,
+            _ => 3,
+        }
+    }
+
+    /// Register values of a color, in the order they are connected
+    pub const fn split(self, color: Rgb) -> [u8; 3] {
+        let Rgb { r, g, b } = color;
+        match self {
+            ColorOrder::Mono => [color.luma(), 0, 0],
+            ColorOrder::Rgb => [r, g, b],
+            ColorOrder::Rbg => [r, b, g],
+            ColorOrder::Grb => [g, r, b],
+            ColorOrder::Gbr => [g, b, r],
+            ColorOrder::Brg => [b, r, g],
+            ColorOrder::Bgr => [b, g, r],
+        }
+    }
+}
+
+/// Multiply `value` by `scale / 256`
+pub(crate) const fn scale8(value: u8, scale: u8) -> u8 {
+    ((value as u16 * (scale as u16 + 1)) >> 8) as u8
+}
+
+/// Approximation of a sine wave. A full period is 256, the output swings from 1 to 255 around 128.
+pub(crate) const fn sin8(theta: u8) -> u8 {
+    let x = (theta & 0x7F) as u16;
+    let y = (x * (128 - x) / 32) as u8;
+    let y = if y > 127 { 127 } else { y };
+    if theta < 128 {
+        128 + y
+    } else {
+        128 - y
+    }
+}
+
+/// Approximation of the angle of a vector. A full turn is 256, 0 points along the positive x
+/// axis.
+pub(crate) const fn atan2_8(dy: i16, dx: i16) -> u8 {
+    if dx == 0 && dy == 0 {
+        return 0;
+    }
+    let ax = dx.unsigned_abs() as u32;
+    let ay = dy.unsigned_abs() as u32;
+    // Angle inside of the first quadrant, 0 to 64
+    let angle = if ax >= ay {
+        32 * ay / ax
+    } else {
+        64 - 32 * ax / ay
+    } as u8;
+    match (dx >= 0, dy >= 0) {
+        (true, true) => angle,
+        (false, true) => 128 - angle,
+        (false, false) => 128 + angle,
+        (true, false) => 0u8.wrapping_sub(angle),
+    }
+}
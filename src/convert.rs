@@ -0,0 +1,420 @@
+use crate::animation::{self, FormatError};
+use crate::color::{ColorOrder, Rgb};
+use crate::IS31FL3743;
+use image::codecs::gif::GifDecoder;
+use image::imageops::{self, FilterType};
+use image::{AnimationDecoder, ImageFormat, RgbaImage};
+use std::fmt::{self, Write as _};
+use std::format;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::Path;
+use std::string::String;
+use std::vec::Vec;
+
+/// A frame of all PWM registers, ready for [fill_matrix](IS31FL3743::fill_matrix)
+pub type Frame = [u8; 0xC6];
+
+/// How image pixels end up in PWM registers
+#[derive(Clone, Copy, Debug)]
+pub struct Mapping {
+    pub width: u8,
+    pub height: u8,
+    /// Register of the (first channel of the) LED at an x,y coordinate
+    pub calc_pixel: fn(x: u8, y: u8) -> u8,
+}
+
+impl<I2C> From<&IS31FL3743<I2C>> for Mapping {
+    fn from(device: &IS31FL3743<I2C>) -> Self {
+        Mapping {
+            width: device.width,
+            height: device.height,
+            calc_pixel: device.calc_pixel,
+        }
+    }
+}
+
+/// Conversion settings
+#[derive(Clone, Copy, Debug)]
+pub struct Options {
+    pub mapping: Mapping,
+    /// [ColorOrder::Mono] writes the brightness of a pixel into one register, the other orders
+    /// split it into three adjacent registers.
+    pub order: ColorOrder,
+    /// Brightness of a white pixel
+    pub brightness: u8,
+    /// Distribute rounding errors to neighbouring pixels (Floyd-Steinberg)
+    pub dither: bool,
+    /// Width of a single frame in a horizontal strip of frames. `None` treats the image as a
+    /// single frame.
+    pub strip_width: Option<u32>,
+}
+
+impl Options {
+    pub fn new(mapping: Mapping, order: ColorOrder) -> Self {
+        Options {
+            mapping,
+            order,
+            brightness: 0xFF,
+            dither: false,
+            strip_width: None,
+        }
+    }
+}
+
+/// Errors while converting an image
+#[derive(Debug)]
+pub enum ConvertError {
+    Io(std::io::Error),
+    Image(image::ImageError),
+    Format(FormatError),
+    /// The strip width is zero or doesn't divide the image width
+    InvalidStrip {
+        image_width: u32,
+        strip_width: u32,
+    },
+}
+
+impl fmt::Display for ConvertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConvertError::Io(err) => write!(f, "{err}"),
+            ConvertError::Image(err) => write!(f, "{err}"),
+            ConvertError::Format(err) => write!(f, "{err:?}"),
+            ConvertError::InvalidStrip {
+                image_width,
+                strip_width,
+            } => write!(
+                f,
+                "strip width {strip_width} doesn't divide image width {image_width}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConvertError {}
+
+impl From<std::io::Error> for ConvertError {
+    fn from(err: std::io::Error) -> Self {
+        ConvertError::Io(err)
+    }
+}
+
+impl From<image::ImageError> for ConvertError {
+    fn from(err: image::ImageError) -> Self {
+        ConvertError::Image(err)
+    }
+}
+
+impl From<FormatError> for ConvertError {
+    fn from(err: FormatError) -> Self {
+        ConvertError::Format(err)
+    }
+}
+
+/// Frames converted from an image, and how long each of them is shown
+#[derive(Clone, Debug, Default)]
+pub struct Converted {
+    /// Width of the display the frames were mapped to
+    pub width: u8,
+    /// Height of the display the frames were mapped to
+    pub height: u8,
+    pub frames: Vec<Frame>,
+    /// Duration of every frame in ms, 0 if the image has no timing
+    pub durations_ms: Vec<u32>,
+}
+
+impl Converted {
+    /// All frames back to back, to be sent with [fill_matrix](IS31FL3743::fill_matrix) one
+    /// 0xC6 byte chunk at a time
+    pub fn to_blob(&self) -> Vec<u8> {
+        self.frames.concat()
+    }
+
+    /// Rust source code of a `const` array of all frames
+    pub fn to_rust(&self, name: &str) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "pub const {name}: [[u8; 0xC6]; {}] = [",
+            self.frames.len()
+        );
+        for frame in &self.frames {
+            out.push_str("    [\n");
+            for row in frame.chunks(18) {
+                let values: Vec<String> =
+                    row.iter().map(|value| format!("0x{value:02X}")).collect();
+                let _ = writeln!(out, "        {},", values.join(", "));
+            }
+            out.push_str("    ],\n");
+        }
+        out.push_str("];\n");
+        out
+    }
+
+    /// Encode in the [animation] format. The frame duration is the average of all frames, or
+    /// `default_duration_ms` if the image has no timing.
+    pub fn to_animation(&self, default_duration_ms: u16) -> Result<Vec<u8>, FormatError> {
+        let total: u32 = self.durations_ms.iter().sum();
+        let duration = match total {
+            0 => default_duration_ms,
+            _ => (total / self.durations_ms.len() as u32).min(u16::MAX as u32) as u16,
+        };
+        animation::encode(self.width, self.height, duration, &self.frames)
+    }
+}
+
+/// Convert a PNG, or a still or animated GIF, from a file
+pub fn convert_file<P: AsRef<Path>>(path: P, options: &Options) -> Result<Converted, ConvertError> {
+    let path = path.as_ref();
+    if ImageFormat::from_path(path)? == ImageFormat::Gif {
+        let decoder = GifDecoder::new(BufReader::new(File::open(path)?))?;
+        let mut converted = Converted {
+            width: options.mapping.width,
+            height: options.mapping.height,
+            ..Default::default()
+        };
+        for frame in decoder.into_frames() {
+            let frame = frame?;
+            let (numerator, denominator) = frame.delay().numer_denom_ms();
+            let duration = numerator / denominator.max(1);
+            for pwm in convert_image(frame.buffer(), options)? {
+                converted.frames.push(pwm);
+                converted.durations_ms.push(duration);
+            }
+        }
+        Ok(converted)
+    } else {
+        let image = image::open(path)?.to_rgba8();
+        let frames = convert_image(&image, options)?;
+        let durations_ms = std::vec![0; frames.len()];
+        Ok(Converted {
+            width: options.mapping.width,
+            height: options.mapping.height,
+            frames,
+            durations_ms,
+        })
+    }
+}
+
+/// Convert a single image, or a strip of frames if [Options::strip_width] is set
+pub fn convert_image(image: &RgbaImage, options: &Options) -> Result<Vec<Frame>, ConvertError> {
+    let strip_width = options.strip_width.unwrap_or(image.width());
+    if strip_width == 0 || !image.width().is_multiple_of(strip_width) {
+        return Err(ConvertError::InvalidStrip {
+            image_width: image.width(),
+            strip_width,
+        });
+    }
+    let frames = (0..image.width() / strip_width)
+        .map(|index| {
+            let view =
+                imageops::crop_imm(image, index * strip_width, 0, strip_width, image.height());
+            convert_frame(&view.to_image(), options)
+        })
+        .collect();
+    Ok(frames)
+}
+
+/// Scale one frame to the display and map its pixels to registers
+fn convert_frame(image: &RgbaImage, options: &Options) -> Frame {
+    let Mapping {
+        width,
+        height,
+        calc_pixel,
+    } = options.mapping;
+    let scaled = imageops::resize(image, width as u32, height as u32, FilterType::Triangle);
+
+    // Target value of every channel, with the alpha channel blended onto black
+    let channels = if options.order == ColorOrder::Mono {
+        1
+    } else {
+        3
+    };
+    let mut target: Vec<f32> = Vec::with_capacity(width as usize * height as usize * channels);
+    for pixel in scaled.pixels() {
+        let [r, g, b, a] = pixel.0;
+        let alpha = a as f32 / 255.0;
+        let scale = alpha * options.brightness as f32 / 255.0;
+        if channels == 1 {
+            target.push(Rgb::new(r, g, b).luma() as f32 * scale);
+        } else {
+            target.extend([r as f32 * scale, g as f32 * scale, b as f32 * scale]);
+        }
+    }
+
+    let index = |x: usize, y: usize, channel: usize| (y * width as usize + x) * channels + channel;
+    let mut frame = [0x00; 0xC6];
+    for y in 0..height as usize {
+        for x in 0..width as usize {
+            let mut values = [0u8; 3];
+            for (channel, value) in values.iter_mut().enumerate().take(channels) {
+                let wanted = target[index(x, y, channel)];
+                let quantized = wanted.round().clamp(0.0, 255.0);
+                *value = quantized as u8;
+                if !options.dither {
+                    continue;
+                }
+                let error = wanted - quantized;
+                let mut spread = |dx: isize, dy: usize, weight: f32| {
+                    let nx = x as isize + dx;
+                    let ny = y + dy;
+                    if nx >= 0 && (nx as usize) < width as usize && ny < height as usize {
+                        target[index(nx as usize, ny, channel)] += error * weight;
+                    }
+                };
+                spread(1, 0, 7.0 / 16.0);
+                spread(-1, 1, 3.0 / 16.0);
+                spread(0, 1, 5.0 / 16.0);
+                spread(1, 1, 1.0 / 16.0);
+            }
+            let register = calc_pixel(x as u8, y as u8);
+            let color = Rgb::new(values[0], values[1], values[2]);
+            let split = match options.order {
+                // Already reduced to a single channel
+                ColorOrder::Mono => [values[0], 0, 0],
+                order => order.split(color),
+            };
+            for (offset, value) in split.iter().take(channels).enumerate() {
+                if let 0x01..=0xC6 = register as usize + offset {
+                    frame[register as usize + offset - 1] = *value;
+                }
+            }
+        }
+    }
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::register;
+    use image::Rgba;
+
+    /// A display of `width`x`height` LEDs in the first rows of the chip
+    fn options(width: u8, height: u8, order: ColorOrder) -> Options {
+        let calc_pixel = match order {
+            ColorOrder::Mono => |x, y| register(y, x),
+            _ => |x, y| register(y, 3 * x),
+        };
+        Options::new(
+            Mapping {
+                width,
+                height,
+                calc_pixel,
+            },
+            order,
+        )
+    }
+
+    /// An image with one row of opaque pixels
+    fn row(pixels: &[[u8; 3]]) -> RgbaImage {
+        let mut image = RgbaImage::new(pixels.len() as u32, 1);
+        for (x, &[r, g, b]) in pixels.iter().enumerate() {
+            image.put_pixel(x as u32, 0, Rgba([r, g, b, 0xFF]));
+        }
+        image
+    }
+
+    /// The first `len` registers of every frame
+    fn registers(frames: &[Frame], len: usize) -> Vec<Vec<u8>> {
+        frames.iter().map(|frame| frame[..len].to_vec()).collect()
+    }
+
+    #[test]
+    fn scales_to_the_display() {
+        let image = RgbaImage::from_pixel(5, 3, Rgba([0xFF, 0xFF, 0xFF, 0xFF]));
+        let frames = convert_image(&image, &options(2, 2, ColorOrder::Mono)).unwrap();
+        let mut expected = [0x00; 0xC6];
+        for &at in &[0, 1, 18, 19] {
+            expected[at] = 0xFF;
+        }
+        assert_eq!(frames, [expected]);
+
+        let frames = convert_image(
+            &row(&[[0xFF; 3], [0x00; 3]]),
+            &options(4, 1, ColorOrder::Mono),
+        );
+        assert_eq!(
+            registers(&frames.unwrap(), 5),
+            [[0xFF, 0xBF, 0x40, 0x00, 0x00]]
+        );
+    }
+
+    #[test]
+    fn grayscale() {
+        let image = row(&[[0xFF, 0x00, 0x00], [0x00, 0xFF, 0x00], [0x00, 0x00, 0xFF]]);
+        let frames = convert_image(&image, &options(3, 1, ColorOrder::Mono)).unwrap();
+        assert_eq!(registers(&frames, 4), [[0x35, 0xB6, 0x12, 0x00]]);
+
+        // Alpha and brightness both dim a pixel
+        let mut image = row(&[[0xFF; 3], [0xFF; 3]]);
+        image.put_pixel(1, 0, Rgba([0xFF, 0xFF, 0xFF, 0x80]));
+        let mut options = options(2, 1, ColorOrder::Mono);
+        options.brightness = 0x80;
+        let frames = convert_image(&image, &options).unwrap();
+        assert_eq!(registers(&frames, 2), [[0x80, 0x40]]);
+    }
+
+    #[test]
+    fn channel_order() {
+        let image = row(&[[0x10, 0x20, 0x30], [0x40, 0x50, 0x60]]);
+        for (order, expected) in [
+            (ColorOrder::Rgb, [0x10, 0x20, 0x30, 0x40, 0x50, 0x60]),
+            (ColorOrder::Grb, [0x20, 0x10, 0x30, 0x50, 0x40, 0x60]),
+            (ColorOrder::Bgr, [0x30, 0x20, 0x10, 0x60, 0x50, 0x40]),
+            (ColorOrder::Brg, [0x30, 0x10, 0x20, 0x60, 0x40, 0x50]),
+        ] {
+            let frames = convert_image(&image, &options(2, 1, order)).unwrap();
+            let mut frame = [0x00; 0xC6];
+            frame[..6].copy_from_slice(&expected);
+            assert_eq!(frames, [frame], "{order:?}");
+        }
+    }
+
+    #[test]
+    fn dithering() {
+        // Every pixel wants half a step
+        let image = RgbaImage::from_pixel(4, 2, Rgba([0xFF, 0xFF, 0xFF, 0x80]));
+        let mut options = options(4, 2, ColorOrder::Mono);
+        options.brightness = 0x01;
+        let frames = convert_image(&image, &options).unwrap();
+        assert_eq!(frames[0][..4], [0x01; 4]);
+        assert_eq!(frames[0][18..22], [0x01; 4]);
+
+        options.dither = true;
+        let frames = convert_image(&image, &options).unwrap();
+        assert_eq!(frames[0][..4], [0x01, 0x00, 0x01, 0x00]);
+        assert_eq!(frames[0][18..22], [0x00, 0x01, 0x00, 0x01]);
+    }
+
+    #[test]
+    fn strips() {
+        let image = row(&[
+            [0x10; 3], [0x20; 3], [0x30; 3], [0x40; 3], [0x50; 3], [0x60; 3],
+        ]);
+        let mut options = options(2, 1, ColorOrder::Mono);
+        options.strip_width = Some(2);
+        let frames = convert_image(&image, &options).unwrap();
+        assert_eq!(
+            registers(&frames, 3),
+            [[0x10, 0x20, 0x00], [0x30, 0x40, 0x00], [0x50, 0x60, 0x00]]
+        );
+    }
+
+    #[test]
+    fn invalid_strip() {
+        let image = row(&[[0x00; 3]; 6]);
+        let mut options = options(2, 1, ColorOrder::Mono);
+        for strip_width in [0, 4, 7] {
+            options.strip_width = Some(strip_width);
+            assert!(matches!(
+                convert_image(&image, &options),
+                Err(ConvertError::InvalidStrip {
+                    image_width: 6,
+                    strip_width: width,
+                }) if width == strip_width
+            ));
+        }
+    }
+}
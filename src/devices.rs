@@ -1,5 +1,5 @@
 #[allow(unused_imports)]
-use crate::{Error, IS31FL3743};
+use crate::{Address, Error, IS31FL3743};
 #[allow(unused_imports)]
 use core::convert::TryFrom;
 #[allow(unused_imports)]
@@ -24,18 +24,18 @@ where
 
     pub fn configure(i2c: I2C) -> UnknownDevice<I2C> {
         UnknownDevice {
-            device: IS31FL3743 {
+            device: IS31FL3743::new(
                 i2c,
-                address: 0b0100000,
+                Address::MIN,
                 // Dummy values, not used
-                width: 18 * 11,
+                18 * 11,
                 // Dummy values, not used
-                height: 1,
-                calc_pixel: |_x: u8, _y: u8| -> u8 {
+                1,
+                |_x: u8, _y: u8| -> u8 {
                     // Dummy value, don't use this function
                     unimplemented!("No Matrix support yet")
                 },
-            },
+            ),
         }
     }
 
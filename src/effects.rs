@@ -0,0 +1,427 @@
+use crate::color::{atan2_8, scale8, sin8, ColorOrder, Hsv};
+use crate::framebuffer::{FrameBuffer, Output};
+use crate::layout::Layout;
+use crate::reactive::Heat;
+use crate::IS31FL3743;
+
+/// The available effects. Can be swapped at any time with [Engine::set_effect].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Effect {
+    /// All LEDs in the same color
+    Solid,
+    /// All LEDs in the same color, slowly fading in and out
+    Breathing,
+    /// All LEDs in the same color, cycling through all hues
+    RainbowCycle,
+    /// Rainbow running horizontally across the display
+    ColorWave,
+    /// LEDs light up when hit with [Engine::hit] and fade out
+    ReactiveFade,
+    /// Rainbow rotating around the center of the display
+    Spiral,
+}
+
+impl Effect {
+    /// Every effect, ordered by [id](Self::id)
+    pub const ALL: [Effect; 6] = [
+        Effect::Solid,
+        Effect::Breathing,
+        Effect::RainbowCycle,
+        Effect::ColorWave,
+        Effect::ReactiveFade,
+        Effect::Spiral,
+    ];
+
+    /// Number of the effect in VIA, 1 and up in declaration order. 0 is
+    /// left for "no effect".
+    pub const fn id(self) -> u8 {
+        self as u8 + 1
+    }
+
+    /// The effect with an [id](Self::id), `None` for 0 and unknown ids
+    pub fn from_id(id: u8) -> Option<Self> {
+        Self::ALL.get((id as usize).checked_sub(1)?).copied()
+    }
+}
+
+/// Parameters shared by all effects
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Params {
+    /// How fast the effect runs. 0 is almost still, 255 is the fastest.
+    pub speed: u8,
+    /// Base hue of the effect
+    pub hue: u8,
+    pub saturation: u8,
+    /// Maximum brightness of the effect
+    pub brightness: u8,
+}
+
+impl Params {
+    const DEFAULT: Params = Params {
+        speed: 0x80,
+        hue: 0x00,
+        saturation: 0xFF,
+        brightness: 0xFF,
+    };
+}
+
+impl Default for Params {
+    fn default() -> Self {
+        Params::DEFAULT
+    }
+}
+
+/// Runs an [Effect] on a [FrameBuffer] and writes it to the device.
+///
+/// [calc_pixel](IS31FL3743::calc_pixel) of the device has to return the register of the first
+/// channel of an LED, the other channels follow in the given [ColorOrder]. Keyboards that don't
+/// fit a grid can be rendered from a [Layout] with [tick_layout](Self::tick_layout) instead.
+pub struct Engine {
+    pub effect: Effect,
+    pub params: Params,
+    pub order: ColorOrder,
+    pub frame: FrameBuffer,
+    /// LEDs hit for [Effect::ReactiveFade], indexed by register - 1
+    heat: Heat,
+    /// Time of the frame being rendered
+    now_ms: u32,
+}
+
+impl Engine {
+    pub const fn new(effect: Effect, order: ColorOrder) -> Self {
+        Engine {
+            effect,
+            params: Params::DEFAULT,
+            order,
+            frame: FrameBuffer::new(),
+            heat: Heat::new(),
+            now_ms: 0,
+        }
+    }
+
+    /// Switch to another effect, keeping the parameters
+    pub fn set_effect(&mut self, effect: Effect) {
+        self.effect = effect;
+    }
+
+    /// Light up the LED whose first channel is at `register` and start fading it out, for
+    /// [Effect::ReactiveFade]. Fades out like a released key of
+    /// [Reactive](crate::reactive::Reactive).
+    pub fn hit(&mut self, register: u8, now_ms: u32) {
+        if let 0x01..=0xC6 = register {
+            self.heat.release(register as usize - 1, now_ms);
+        }
+    }
+
+    /// Render the effect at a point in time and write it to the device, see [Output].
+    /// `now_ms` may wrap around.
+    pub fn tick<O: Output>(&mut self, output: &mut O, now_ms: u32) -> Result<(), O::Error> {
+        self.render(output.device(), now_ms);
+        self.frame.flush(output)
+    }
+
+    /// Render the effect at a point in time into [frame](Self::frame), without writing it to
+    /// the device
+    pub fn render<I2C>(&mut self, device: &IS31FL3743<I2C>, now_ms: u32) {
+        let phase = self.advance(now_ms);
+        // Positions are the centers of the LEDs, in half LEDs
+        let (width, height) = (2 * device.width as u16, 2 * device.height as u16);
+        for y in 0..device.height {
+            for x in 0..device.width {
+                let register = (device.calc_pixel)(x, y);
+                let (x, y) = (2 * x as u16 + 1, 2 * y as u16 + 1);
+                let color = self.color_at(width, height, x, y, register, phase);
+                self.frame.set_color(register, self.order, color.to_rgb());
+            }
+        }
+    }
+
+    /// Render the LEDs of one controller of a [Layout] and write them to the device, see
+    /// [tick](Self::tick). The geometric effects use the physical positions of the LEDs, so
+    /// they line up across controllers when every controller has its own engine.
+    pub fn tick_layout<O: Output>(
+        &mut self,
+        output: &mut O,
+        layout: &Layout,
+        controller: u8,
+        now_ms: u32,
+    ) -> Result<(), O::Error> {
+        self.render_layout(layout, controller, now_ms);
+        self.frame.flush(output)
+    }
+
+    /// Render the LEDs of one controller of a [Layout] into [frame](Self::frame), without
+    /// writing it to the device. [order](Self::order) is ignored in favor of the one of the
+    /// layout.
+    pub fn render_layout(&mut self, layout: &Layout, controller: u8, now_ms: u32) {
+        let phase = self.advance(now_ms);
+        for led in layout.leds_of(controller) {
+            let color = self.color_at(
+                layout.width,
+                layout.height,
+                led.x,
+                led.y,
+                led.register,
+                phase,
+            );
+            self.frame
+                .set_color(led.register, layout.order, color.to_rgb());
+        }
+    }
+
+    /// Move on to a point in time, returning the phase of the animation
+    fn advance(&mut self, now_ms: u32) -> u8 {
+        self.now_ms = now_ms;
+        self.heat.expire(now_ms, self.params.speed);
+        (now_ms.wrapping_mul(self.params.speed as u32 + 1) >> 10) as u8
+    }
+
+    /// Color of the LED centered at x,y, in an area of width by height. Any unit works, as long
+    /// as it is the same for all of them.
+    fn color_at(&self, width: u16, height: u16, x: u16, y: u16, register: u8, phase: u8) -> Hsv {
+        let Params {
+            speed,
+            hue,
+            saturation,
+            brightness,
+        } = self.params;
+        match self.effect {
+            Effect::Solid => Hsv::new(hue, saturation, brightness),
+            Effect::Breathing => Hsv::new(hue, saturation, scale8(sin8(phase), brightness)),
+            Effect::RainbowCycle => Hsv::new(hue.wrapping_add(phase), saturation, brightness),
+            Effect::ColorWave => {
+                let offset = (x as u32 * 256 / width.max(1) as u32) as u8;
+                Hsv::new(
+                    hue.wrapping_add(offset).wrapping_sub(phase),
+                    saturation,
+                    brightness,
+                )
+            }
+            Effect::ReactiveFade => {
+                let heat = match register {
+                    0x01..=0xC6 => self.heat.level(register as usize - 1, self.now_ms, speed),
+                    _ => 0,
+                };
+                Hsv::new(hue, saturation, scale8(heat, brightness))
+            }
+            Effect::Spiral => {
+                let dx = x as i16 - (width / 2) as i16;
+                let dy = y as i16 - (height / 2) as i16;
+                Hsv::new(
+                    hue.wrapping_add(atan2_8(dy, dx)).wrapping_add(phase),
+                    saturation,
+                    brightness,
+                )
+            }
+        }
+    }
+}
+
+/// One controller and the engine rendering its LEDs, as driven by the
+/// [OpenRGB protocol](crate::protocol) and [VIA](crate::via). The device can be wrapped in a
+/// [PowerLimited](crate::power::PowerLimited) to keep the zone under a current budget.
+pub struct Zone<O> {
+    pub device: O,
+    /// Renders effects and holds the colors set by the host. Its [ColorOrder] tells how the LEDs
+    /// are connected.
+    pub engine: Engine,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Rgb;
+    use crate::layout::{build, key, Led, Row, Wiring, U};
+    use crate::mock::{device, Bus};
+    use crate::{register, Address};
+
+    /// Six RGB LEDs on every SW row
+    fn rgb_device() -> IS31FL3743<Bus> {
+        IS31FL3743 {
+            width: 6,
+            calc_pixel: |x, y| register(y, 3 * x),
+            ..device()
+        }
+    }
+
+    /// Color of the LED at x,y of [rgb_device] in the frame
+    fn color(engine: &Engine, x: u8, y: u8) -> Rgb {
+        let register = register(y, 3 * x);
+        let frame = &engine.frame;
+        Rgb::new(
+            frame.get(register),
+            frame.get(register + 1),
+            frame.get(register + 2),
+        )
+    }
+
+    /// A fully saturated and bright color
+    fn hue(hue: u8) -> Rgb {
+        Hsv::new(hue, 0xFF, 0xFF).to_rgb()
+    }
+
+    #[test]
+    fn effect_ids() {
+        for (index, effect) in Effect::ALL.into_iter().enumerate() {
+            assert_eq!(effect.id() as usize, index + 1);
+            assert_eq!(Effect::from_id(effect.id()), Some(effect));
+        }
+        assert_eq!(Effect::from_id(0), None);
+        assert_eq!(Effect::from_id(Effect::ALL.len() as u8 + 1), None);
+    }
+
+    #[test]
+    fn reactive_fade() {
+        let device = device();
+        let mut engine = Engine::new(Effect::ReactiveFade, ColorOrder::Mono);
+        engine.params.saturation = 0x00;
+        engine.params.speed = 0xFF;
+        engine.hit(register(1, 2), 1000);
+        // Outside of the PWM page
+        engine.hit(0x00, 1000);
+        engine.hit(0xC7, 1000);
+        engine.render(&device, 1000);
+        assert_eq!(engine.frame.get(register(1, 2)), 0xFF);
+        assert_eq!(engine.frame.get(register(1, 3)), 0x00);
+        engine.render(&device, 1100);
+        assert_eq!(engine.frame.get(register(1, 2)), 0xFF - 100);
+        engine.render(&device, 1300);
+        assert_eq!(engine.frame.get(register(1, 2)), 0x00);
+        // Stays dark when the timer comes around again
+        engine.render(&device, 1 << 31);
+        engine.render(&device, 1000);
+        assert_eq!(engine.frame.get(register(1, 2)), 0x00);
+    }
+
+    #[test]
+    fn solid() {
+        let device = rgb_device();
+        let mut engine = Engine::new(Effect::Solid, ColorOrder::Rgb);
+        for now in [0, 1234, u32::MAX] {
+            engine.render(&device, now);
+            assert!(engine.frame.pwm.chunks(3).all(|led| led == [0xFF, 0, 0]));
+        }
+        engine.params.brightness = 0x80;
+        engine.render(&device, 0);
+        assert!(engine.frame.pwm.chunks(3).all(|led| led == [0x80, 0, 0]));
+    }
+
+    #[test]
+    fn breathing() {
+        let device = device();
+        let mut engine = Engine::new(Effect::Breathing, ColorOrder::Mono);
+        engine.params.saturation = 0x00;
+        engine.params.speed = 0xFF;
+        // A quarter of the phase per ms
+        for (now, brightness) in [(0, 128), (256, 255), (512, 128), (768, 1), (1024, 128)] {
+            engine.render(&device, now);
+            assert!(engine.frame.pwm.iter().all(|&pwm| pwm == brightness));
+        }
+        engine.params.brightness = 0x80;
+        engine.render(&device, 256);
+        assert!(engine.frame.pwm.iter().all(|&pwm| pwm == 0x80));
+        // The slowest speed takes 256 times as long
+        engine.params.speed = 0x00;
+        engine.render(&device, 256 * 256);
+        assert!(engine.frame.pwm.iter().all(|&pwm| pwm == 0x80));
+    }
+
+    #[test]
+    fn rainbow_cycle() {
+        let device = rgb_device();
+        let mut engine = Engine::new(Effect::RainbowCycle, ColorOrder::Rgb);
+        engine.params.speed = 0xFF;
+        engine.render(&device, 0);
+        assert_eq!(color(&engine, 0, 0), Rgb::new(0xFF, 0, 0));
+        engine.render(&device, 4 * 85);
+        assert_eq!(color(&engine, 0, 0), Rgb::new(3, 0xFF, 0));
+        assert!(engine.frame.pwm.chunks(3).all(|led| led == [3, 0xFF, 0]));
+        engine.params.hue = 0x80;
+        engine.render(&device, 4 * 0x80);
+        assert_eq!(color(&engine, 5, 10), hue(0x00));
+    }
+
+    #[test]
+    fn color_wave() {
+        let device = rgb_device();
+        let mut engine = Engine::new(Effect::ColorWave, ColorOrder::Rgb);
+        engine.params.speed = 0xFF;
+        let hues = [21, 64, 106, 149, 192, 234];
+        engine.render(&device, 0);
+        for y in 0..11 {
+            for (x, &h) in hues.iter().enumerate() {
+                assert_eq!(color(&engine, x as u8, y), hue(h));
+            }
+        }
+        // Runs to the right
+        engine.render(&device, 4 * 10);
+        for (x, &h) in hues.iter().enumerate() {
+            assert_eq!(color(&engine, x as u8, 3), hue(h - 10));
+        }
+    }
+
+    #[test]
+    fn spiral() {
+        let device = rgb_device();
+        let mut engine = Engine::new(Effect::Spiral, ColorOrder::Rgb);
+        engine.params.speed = 0xFF;
+        // Right of, left of, above and below the center
+        let leds = [((5, 5), 0), ((0, 5), 128), ((2, 0), 189), ((2, 10), 67)];
+        engine.render(&device, 0);
+        for ((x, y), h) in leds {
+            assert_eq!(color(&engine, x, y), hue(h));
+        }
+        engine.render(&device, 4 * 100);
+        for ((x, y), h) in leds {
+            assert_eq!(color(&engine, x, y), hue(h.wrapping_add(100)));
+        }
+    }
+
+    #[test]
+    fn layout() {
+        const ROWS: [Row; 2] = [(100, &[U, key(200)]), (100, &[key(150), key(150)])];
+        const LEDS: [Led; 4] = build(
+            &ROWS,
+            &[
+                Wiring::new(0, 0, 0),
+                Wiring::new(0, 0, 3),
+                Wiring::new(1, 0, 0),
+                Wiring::new(0, 1, 0),
+            ],
+        );
+        let layout = Layout {
+            leds: &LEDS,
+            controllers: &[Address::MIN, Address::MAX],
+            order: ColorOrder::Rgb,
+            width: 300,
+            height: 200,
+            unit_um: 19_050,
+        };
+        let rgb = |frame: &[u8], register: u8| {
+            let index = register as usize - 1;
+            Rgb::new(frame[index], frame[index + 1], frame[index + 2])
+        };
+
+        // The order of the layout wins over the one of the engine
+        let mut engine = Engine::new(Effect::ColorWave, ColorOrder::Mono);
+        let mut device = device();
+        engine.tick_layout(&mut device, &layout, 0, 0).unwrap();
+        let pwm = device.i2c.pwm();
+        assert_eq!(rgb(pwm, register(0, 0)), hue(42));
+        assert_eq!(rgb(pwm, register(0, 3)), hue(170));
+        assert_eq!(rgb(pwm, register(1, 0)), hue(192));
+        assert_eq!(pwm.iter().filter(|&&pwm| pwm != 0).count(), 6);
+
+        // Only the LEDs of the second controller
+        let mut engine = Engine::new(Effect::ColorWave, ColorOrder::Rgb);
+        engine.render_layout(&layout, 1, 0);
+        assert_eq!(rgb(&engine.frame.pwm, register(0, 0)), hue(64));
+        assert!(engine.frame.pwm[3..].iter().all(|&pwm| pwm == 0));
+
+        // Around the center of the layout, not of the matrix
+        engine.set_effect(Effect::Spiral);
+        engine.render_layout(&layout, 0, 0);
+        assert_eq!(rgb(&engine.frame.pwm, register(0, 0)), hue(144));
+        assert_eq!(rgb(&engine.frame.pwm, register(0, 3)), hue(224));
+    }
+}
@@ -0,0 +1,362 @@
+use crate::framebuffer::Output;
+
+/// Progress of a finished fade
+const DONE: u16 = 0x100;
+
+enum Kind {
+    Current {
+        from: u8,
+        to: u8,
+    },
+    Scaling {
+        from: u8,
+        to: u8,
+    },
+    /// Frame written with the global current at 0, then the global current is raised
+    FadeIn {
+        frame: [u8; 0xC6],
+        current: u8,
+    },
+    /// Global current lowered to 0, then the frame is cleared and the global current restored
+    FadeOut {
+        frame: [u8; 0xC6],
+        current: u8,
+    },
+    /// Two frames that are the same up to a factor. The brighter frame is written once and
+    /// the global current is faded between values that make it look like either frame. If
+    /// the fade ends on the dimmer frame, it is swapped in at the end and the current restored.
+    Proportional {
+        frame: [u8; 0xC6],
+        from: u8,
+        to: u8,
+        last: Option<[u8; 0xC6]>,
+        current: u8,
+    },
+    Frames {
+        from: [u8; 0xC6],
+        to: [u8; 0xC6],
+    },
+}
+
+/// A transition over time, driven by calling [tick](Self::tick) regularly.
+///
+/// The PWM page is the most expensive thing to update, every step writes 199 bytes. The global
+/// current register is a single byte, so [frames](Self::frames) uses it whenever the fade starts
+/// or ends with a dark display, or one frame is a dimmed copy of the other. A register is only
+/// written when its value changes.
+pub struct Fade {
+    kind: Kind,
+    duration_ms: u32,
+    start: Option<u32>,
+    /// Progress of the last step written, from 0 to 0x100
+    progress: Option<u16>,
+    /// Last global current or scaling written
+    value: Option<u8>,
+}
+
+impl Fade {
+    /// Fade the [global current](crate::registers::GlobalCurrent) from one value to another
+    pub fn global_current(from: u8, to: u8, duration_ms: u32) -> Self {
+        Self::new(Kind::Current { from, to }, duration_ms)
+    }
+
+    /// Fade the scaling of all LEDs from one value to another
+    pub fn scaling(from: u8, to: u8, duration_ms: u32) -> Self {
+        Self::new(Kind::Scaling { from, to }, duration_ms)
+    }
+
+    /// Fade from one PWM frame to another. `current` is the global current the display is
+    /// running at, it is the same again when the fade is done.
+    ///
+    /// If either frame is completely dark, or all LEDs of one frame are the LEDs of the other
+    /// scaled by the same factor, only the global current is faded. Otherwise every step writes
+    /// a full PWM frame.
+    pub fn frames(from: &[u8; 0xC6], to: &[u8; 0xC6], current: u8, duration_ms: u32) -> Self {
+        let kind = if from.iter().all(|&pwm| pwm == 0) {
+            Kind::FadeIn {
+                frame: *to,
+                current,
+            }
+        } else if to.iter().all(|&pwm| pwm == 0) {
+            Kind::FadeOut {
+                frame: *from,
+                current,
+            }
+        } else if let Some(dimmed) = proportional(from, to, current) {
+            Kind::Proportional {
+                frame: *from,
+                from: current,
+                to: dimmed,
+                last: Some(*to),
+                current,
+            }
+        } else if let Some(dimmed) = proportional(to, from, current) {
+            Kind::Proportional {
+                frame: *to,
+                from: dimmed,
+                to: current,
+                last: None,
+                current,
+            }
+        } else {
+            Kind::Frames {
+                from: *from,
+                to: *to,
+            }
+        };
+        Self::new(kind, duration_ms)
+    }
+
+    fn new(kind: Kind, duration_ms: u32) -> Self {
+        Fade {
+            kind,
+            duration_ms,
+            start: None,
+            progress: None,
+            value: None,
+        }
+    }
+
+    /// Whether the last step has been written
+    pub fn is_done(&self) -> bool {
+        self.progress == Some(DONE)
+    }
+
+    /// Write the step of the fade at `now_ms` to a device or through a
+    /// [PowerLimited](crate::power::PowerLimited), see [Output]. The first call starts the fade.
+    /// Returns `true` once the fade is done.
+    pub fn tick<O: Output>(&mut self, output: &mut O, now_ms: u32) -> Result<bool, O::Error> {
+        let start = *self.start.get_or_insert(now_ms);
+        let elapsed = now_ms.wrapping_sub(start);
+        let progress = if elapsed >= self.duration_ms {
+            DONE
+        } else {
+            (elapsed as u64 * DONE as u64 / self.duration_ms as u64) as u16
+        };
+        if self.progress == Some(progress) {
+            return Ok(self.is_done());
+        }
+
+        if self.progress.is_none() {
+            match &self.kind {
+                Kind::FadeIn { frame, .. } => {
+                    output.write_global_current(0)?;
+                    output.write_frame(frame)?;
+                    self.value = Some(0);
+                }
+                Kind::FadeOut { frame, .. } => output.write_frame(frame)?,
+                Kind::Proportional { frame, from, .. } => {
+                    // Dim first, so the brighter frame never shows at the full current
+                    output.write_global_current(*from)?;
+                    output.write_frame(frame)?;
+                    self.value = Some(*from);
+                }
+                _ => {}
+            }
+        }
+
+        let value = match &self.kind {
+            Kind::Current { from, to }
+            | Kind::Scaling { from, to }
+            | Kind::Proportional { from, to, .. } => Some(lerp(*from, *to, progress)),
+            Kind::FadeIn { current, .. } => Some(lerp(0, *current, progress)),
+            Kind::FadeOut { current, .. } => Some(lerp(*current, 0, progress)),
+            Kind::Frames { .. } => None,
+        };
+        if let Some(value) = value.filter(|&value| self.value != Some(value)) {
+            if let Kind::Scaling { .. } = self.kind {
+                output.write_scaling(value)?;
+            } else {
+                output.write_global_current(value)?;
+            }
+            self.value = Some(value);
+        }
+
+        match &self.kind {
+            Kind::FadeOut { current, .. } if progress == DONE => {
+                output.write_frame(&[0x00; 0xC6])?;
+                output.write_global_current(*current)?;
+            }
+            Kind::Proportional {
+                last: Some(frame),
+                current,
+                ..
+            } if progress == DONE => {
+                output.write_frame(frame)?;
+                output.write_global_current(*current)?;
+            }
+            Kind::Frames { from, to } => {
+                let mut frame = [0x00; 0xC6];
+                for (pwm, (from, to)) in frame.iter_mut().zip(from.iter().zip(to.iter())) {
+                    *pwm = lerp(*from, *to, progress);
+                }
+                output.write_frame(&frame)?;
+            }
+            _ => {}
+        }
+
+        self.progress = Some(progress);
+        Ok(self.is_done())
+    }
+}
+
+/// If every LED of `dim` is the LED of `bright` scaled by the same factor below one, the global
+/// current that makes `bright` look like `dim`. Off by one PWM step counts as the same.
+fn proportional(bright: &[u8; 0xC6], dim: &[u8; 0xC6], current: u8) -> Option<u8> {
+    let (reference, &bright_max) = bright.iter().enumerate().max_by_key(|&(_, pwm)| pwm)?;
+    let (bright_max, dim_max) = (bright_max as u32, dim[reference] as u32);
+    if dim_max == 0 || dim_max >= bright_max {
+        return None;
+    }
+    let same = bright.iter().zip(dim.iter()).all(|(&bright, &dim)| {
+        (dim as u32 * bright_max).abs_diff(bright as u32 * dim_max) <= bright_max
+    });
+    same.then(|| ((current as u32 * dim_max + bright_max / 2) / bright_max) as u8)
+}
+
+/// Linear interpolation, `progress` goes from 0 to 0x100
+fn lerp(from: u8, to: u8, progress: u16) -> u8 {
+    let progress = progress.min(DONE) as u32;
+    ((from as u32 * (DONE as u32 - progress) + to as u32 * progress) / DONE as u32) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{device, Bus};
+    use crate::registers::GlobalCurrent;
+    use crate::{addresses, IS31FL3743};
+
+    fn current(device: &IS31FL3743<Bus>) -> u8 {
+        device.i2c.function(addresses::CURRENT_REGISTER)
+    }
+
+    fn gradient(max: u8) -> [u8; 0xC6] {
+        let mut frame = [0x00; 0xC6];
+        for (index, pwm) in frame.iter_mut().enumerate() {
+            *pwm = (index as u32 * max as u32 / 0xC5) as u8;
+        }
+        frame
+    }
+
+    #[test]
+    fn lerp_endpoints() {
+        assert_eq!(lerp(10, 200, 0), 10);
+        assert_eq!(lerp(10, 200, DONE), 200);
+        assert_eq!(lerp(200, 10, DONE / 2), 105);
+        assert_eq!(lerp(0, 0xFF, DONE * 2), 0xFF);
+    }
+
+    #[test]
+    fn global_current() {
+        let mut device = device();
+        let mut fade = Fade::global_current(0x00, 0xFF, 100);
+        // The timer wraps around during the fade
+        let start = u32::MAX - 10;
+        assert!(!fade.tick(&mut device, start).unwrap());
+        assert_eq!(current(&device), 0x00);
+        assert!(!fade.tick(&mut device, start.wrapping_add(50)).unwrap());
+        assert_eq!(current(&device), 0x7F);
+        assert!(fade.tick(&mut device, start.wrapping_add(100)).unwrap());
+        assert_eq!(current(&device), 0xFF);
+        assert!(fade.is_done());
+    }
+
+    #[test]
+    fn scaling() {
+        let mut device = device();
+        let mut fade = Fade::scaling(0xFF, 0x01, 10);
+        fade.tick(&mut device, 0).unwrap();
+        assert!(device.i2c.scaling().iter().all(|&scale| scale == 0xFF));
+        fade.tick(&mut device, 20).unwrap();
+        assert!(device.i2c.scaling().iter().all(|&scale| scale == 0x01));
+    }
+
+    #[test]
+    fn proportional_frames() {
+        let bright = gradient(0xFF);
+        let half = gradient(0x80);
+        assert_eq!(proportional(&bright, &half, 0xFF), Some(0x80));
+        assert_eq!(proportional(&bright, &half, 0x40), Some(0x20));
+        // The brighter frame has to come first
+        assert_eq!(proportional(&half, &bright, 0xFF), None);
+        assert_eq!(proportional(&bright, &bright, 0xFF), None);
+        assert_eq!(proportional(&bright, &[0x00; 0xC6], 0xFF), None);
+
+        let mut other = half;
+        other[3] = 0x80;
+        assert_eq!(proportional(&bright, &other, 0xFF), None);
+        let mut other = bright;
+        other.reverse();
+        assert_eq!(proportional(&bright, &other, 0xFF), None);
+    }
+
+    #[test]
+    fn fade_in_and_out() {
+        let frame = gradient(0xFF);
+        let mut device = device();
+        let mut fade = Fade::frames(&[0x00; 0xC6], &frame, 0xC0, 100);
+        fade.tick(&mut device, 0).unwrap();
+        assert_eq!(device.i2c.pwm(), frame);
+        assert_eq!(current(&device), 0x00);
+        fade.tick(&mut device, 100).unwrap();
+        assert_eq!(current(&device), 0xC0);
+
+        let mut fade = Fade::frames(&frame, &[0x00; 0xC6], 0xC0, 100);
+        fade.tick(&mut device, 0).unwrap();
+        fade.tick(&mut device, 50).unwrap();
+        assert_eq!(device.i2c.pwm(), frame);
+        assert_eq!(current(&device), 0x60);
+        fade.tick(&mut device, 100).unwrap();
+        assert!(device.i2c.pwm().iter().all(|&pwm| pwm == 0));
+        assert_eq!(current(&device), 0xC0);
+    }
+
+    #[test]
+    fn fade_down_to_a_dimmer_copy() {
+        let (bright, dim) = (gradient(0xFF), gradient(0x40));
+        let mut device = device();
+        let mut fade = Fade::frames(&bright, &dim, 0xFF, 100);
+        fade.tick(&mut device, 0).unwrap();
+        fade.tick(&mut device, 50).unwrap();
+        // Only the current changes until the end
+        assert_eq!(device.i2c.pwm(), bright);
+        assert_eq!(current(&device), 0x9F);
+        assert!(fade.tick(&mut device, 100).unwrap());
+        assert_eq!(device.i2c.pwm(), dim);
+        assert_eq!(current(&device), 0xFF);
+    }
+
+    #[test]
+    fn fade_up_from_a_dimmer_copy() {
+        let (bright, dim) = (gradient(0xFF), gradient(0x40));
+        let mut device = device();
+        device.fill_matrix(&dim).unwrap();
+        let mut fade = Fade::frames(&dim, &bright, 0xFF, 100);
+        fade.tick(&mut device, 0).unwrap();
+        assert_eq!(device.i2c.pwm(), bright);
+        assert_eq!(current(&device), 0x40);
+        fade.tick(&mut device, 50).unwrap();
+        assert_eq!(current(&device), 0x9F);
+        assert!(fade.tick(&mut device, 100).unwrap());
+        assert_eq!(device.i2c.pwm(), bright);
+        assert_eq!(current(&device), 0xFF);
+    }
+
+    #[test]
+    fn unrelated_frames() {
+        let from = gradient(0xFF);
+        let mut to = from;
+        to.reverse();
+        let mut device = device();
+        device.set_register(GlobalCurrent::new(0xFF)).unwrap();
+        let mut fade = Fade::frames(&from, &to, 0xFF, 100);
+        fade.tick(&mut device, 0).unwrap();
+        assert_eq!(device.i2c.pwm(), from);
+        fade.tick(&mut device, 50).unwrap();
+        assert_eq!(device.i2c.pwm()[0], 0x7F);
+        assert_eq!(current(&device), 0xFF);
+        fade.tick(&mut device, 100).unwrap();
+        assert_eq!(device.i2c.pwm(), to);
+    }
+}
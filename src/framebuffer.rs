@@ -0,0 +1,134 @@
+use crate::color::{ColorOrder, Rgb};
+use crate::registers::GlobalCurrent;
+use crate::IS31FL3743;
+use embedded_hal::i2c::I2c;
+
+/// Copy of the PWM page kept on the host.
+///
+/// Draw into it as often as needed and write it to the device in a single burst with
+/// [flush](Self::flush).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FrameBuffer {
+    /// PWM registers, register 0x01 first
+    pub pwm: [u8; 0xC6],
+}
+
+impl FrameBuffer {
+    pub const fn new() -> Self {
+        FrameBuffer { pwm: [0x00; 0xC6] }
+    }
+
+    /// Turn all LEDs off
+    pub fn clear(&mut self) {
+        self.fill(0x00);
+    }
+
+    /// Set all registers to a single brightness
+    pub fn fill(&mut self, brightness: u8) {
+        self.pwm = [brightness; 0xC6];
+    }
+
+    /// Brightness of a PWM register. Registers outside of 0x01 to 0xC6 read as 0.
+    pub fn get(&self, register: u8) -> u8 {
+        match register {
+            0x01..=0xC6 => self.pwm[register as usize - 1],
+            _ => 0,
+        }
+    }
+
+    /// Set the brightness of a PWM register. Registers outside of 0x01 to 0xC6 are ignored.
+    pub fn set(&mut self, register: u8, brightness: u8) {
+        if let 0x01..=0xC6 = register {
+            self.pwm[register as usize - 1] = brightness;
+        }
+    }
+
+    /// Set the color of the LED whose first channel is at `register`
+    pub fn set_color(&mut self, register: u8, order: ColorOrder, color: Rgb) {
+        let channels = order.split(color);
+        for (offset, value) in channels.iter().take(order.channels() as usize).enumerate() {
+            self.set(register.saturating_add(offset as u8), *value);
+        }
+    }
+
+    /// Set the color of the LED at an x,y coordinate, using the
+    /// [calc_pixel](IS31FL3743::calc_pixel) method of the device. Coordinates outside of the
+    /// display are ignored.
+    pub fn set_pixel<I2C>(
+        &mut self,
+        device: &IS31FL3743<I2C>,
+        x: u8,
+        y: u8,
+        order: ColorOrder,
+        color: Rgb,
+    ) {
+        if x < device.width && y < device.height {
+            self.set_color((device.calc_pixel)(x, y), order, color);
+        }
+    }
+
+    /// Write the whole frame to a device, or through a [PowerLimited](crate::power::PowerLimited)
+    pub fn flush<O: Output>(&self, output: &mut O) -> Result<(), O::Error> {
+        output.write_frame(&self.pwm)
+    }
+}
+
+/// Where frames, global current and scaling are written to. Implemented by [IS31FL3743] and by
+/// [PowerLimited](crate::power::PowerLimited), which keeps every write under its current budget.
+pub trait Output {
+    type Bus;
+    type Error;
+
+    /// The device written to, for its size and [calc_pixel](IS31FL3743::calc_pixel)
+    fn device(&self) -> &IS31FL3743<Self::Bus>;
+
+    /// Write the whole PWM page
+    fn write_frame(&mut self, pwm: &[u8; 0xC6]) -> Result<(), Self::Error>;
+
+    /// Write consecutive PWM registers, `start` is the index into the page (register - 1).
+    /// Panics if the values don't fit into the page.
+    fn write_range(&mut self, start: usize, pwm: &[u8]) -> Result<(), Self::Error>;
+
+    fn write_global_current(&mut self, current: u8) -> Result<(), Self::Error>;
+
+    /// Set the scaling of all LEDs
+    fn write_scaling(&mut self, scale: u8) -> Result<(), Self::Error>;
+}
+
+impl<I2C, I2cError> Output for IS31FL3743<I2C>
+where
+    I2C: I2c<Error = I2cError>,
+{
+    type Bus = I2C;
+    type Error = I2cError;
+
+    fn device(&self) -> &IS31FL3743<I2C> {
+        self
+    }
+
+    fn write_frame(&mut self, pwm: &[u8; 0xC6]) -> Result<(), I2cError> {
+        self.fill_matrix(pwm)
+    }
+
+    fn write_range(&mut self, start: usize, pwm: &[u8]) -> Result<(), I2cError> {
+        assert!(start + pwm.len() <= 0xC6, "range outside of the PWM page");
+        if pwm.is_empty() {
+            return Ok(());
+        }
+        self.write_pwm_range(start as u8 + 1, pwm)
+    }
+
+    fn write_global_current(&mut self, current: u8) -> Result<(), I2cError> {
+        self.set_register(GlobalCurrent::new(current))
+    }
+
+    fn write_scaling(&mut self, scale: u8) -> Result<(), I2cError> {
+        self.set_scaling(scale)
+    }
+}
+
+impl Default for FrameBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
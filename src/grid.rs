@@ -0,0 +1,284 @@
+//! Lookup tables for custom boards, built at compile time by [is31fl3743_layout].
+//!
+//! The functions are what the macro expands to and can also be used in `const` items directly.
+//! Cells are zero based `(sw, cs)` positions of the first channel of an LED, `None` for gaps.
+use crate::{register, CS_PINS, SW_PINS};
+
+/// A grid of LEDs, row by row
+pub type Grid<'a> = &'a [&'a [Option<(u8, u8)>]];
+
+/// Number of columns of the widest row
+pub const fn width(grid: Grid) -> u8 {
+    let mut width = 0;
+    let mut y = 0;
+    while y < grid.len() {
+        if grid[y].len() > width {
+            width = grid[y].len();
+        }
+        y += 1;
+    }
+    assert!(width <= u8::MAX as usize, "grid is too wide");
+    width as u8
+}
+
+/// Number of rows
+pub const fn height(grid: Grid) -> u8 {
+    assert!(grid.len() <= u8::MAX as usize, "grid is too high");
+    grid.len() as u8
+}
+
+/// Fail compilation if an LED is outside of the matrix or two LEDs share a register. Every LED
+/// takes `channels` consecutive CS pins.
+pub const fn check(grid: Grid, channels: u8) {
+    let mut used = [false; 0xC6];
+    let mut y = 0;
+    while y < grid.len() {
+        let mut x = 0;
+        while x < grid[y].len() {
+            if let Some((sw, cs)) = grid[y][x] {
+                assert!(sw < SW_PINS, "SW position outside of the matrix");
+                assert!(
+                    cs + channels <= CS_PINS,
+                    "CS position outside of the matrix"
+                );
+                let mut channel = 0;
+                while channel < channels {
+                    let index = register(sw, cs + channel) as usize - 1;
+                    assert!(!used[index], "register used twice");
+                    used[index] = true;
+                    channel += 1;
+                }
+            }
+            x += 1;
+        }
+        y += 1;
+    }
+}
+
+/// x,y → register table. Gaps map to register 0, which isn't a PWM register.
+pub const fn pixels<const W: usize, const H: usize>(grid: Grid) -> [[u8; W]; H] {
+    let mut pixels = [[0x00; W]; H];
+    let mut y = 0;
+    while y < grid.len() {
+        let mut x = 0;
+        while x < grid[y].len() {
+            if let Some((sw, cs)) = grid[y][x] {
+                pixels[y][x] = register(sw, cs);
+            }
+            x += 1;
+        }
+        y += 1;
+    }
+    pixels
+}
+
+/// Register → x,y table, indexed by register - 1. Only the first channel of every LED is set.
+pub const fn positions(grid: Grid) -> [Option<(u8, u8)>; 0xC6] {
+    let mut positions = [None; 0xC6];
+    let mut y = 0;
+    while y < grid.len() {
+        let mut x = 0;
+        while x < grid[y].len() {
+            if let Some((sw, cs)) = grid[y][x] {
+                positions[register(sw, cs) as usize - 1] = Some((x as u8, y as u8));
+            }
+            x += 1;
+        }
+        y += 1;
+    }
+    positions
+}
+
+/// Defines a module with the lookup tables of a custom board, checked at compile time.
+///
+/// Every row lists the zero based `(sw, cs)` positions of its LEDs, `_` leaves a gap. With `rgb`
+/// every LED takes three consecutive CS pins starting at the given one, with `mono` only one.
+/// Compilation fails if an LED is outside of the matrix or two LEDs share a register.
+///
+/// ```
+/// is31fl3743a::is31fl3743_layout! {
+///     /// Two rows of RGB LEDs, the second one starting further right
+///     pub mod board: rgb {
+///         [(0, 0), (0, 3), (0, 6)],
+///         [_, (1, 0), (1, 3)],
+///     }
+/// }
+///
+/// fn new_board<I2C>(i2c: I2C) -> is31fl3743a::IS31FL3743<I2C> {
+///     board::device(i2c, is31fl3743a::Address::MIN)
+/// }
+/// ```
+///
+/// The module holds:
+///
+/// - `WIDTH`, `HEIGHT` and `CHANNELS`
+/// - `PIXELS`, the x,y → register table, and `calc_pixel` to look it up
+/// - `POSITIONS`, the register → x,y table, and `position` to look it up
+/// - `device`, to create an [IS31FL3743](crate::IS31FL3743) with the tables
+///
+/// Cells are evaluated inside the new module, so constants of the enclosing module need a
+/// `super::` path.
+///
+/// Two LEDs sharing a register don't compile:
+///
+/// ```compile_fail
+/// is31fl3743a::is31fl3743_layout! {
+///     mod board: rgb {
+///         [(0, 0), (0, 2)],
+///     }
+/// }
+/// # fn main() { let _ = board::WIDTH; }
+/// ```
+///
+/// Neither do LEDs outside of the matrix:
+///
+/// ```compile_fail
+/// is31fl3743a::is31fl3743_layout! {
+///     mod board: mono {
+///         [(11, 0)],
+///     }
+/// }
+/// # fn main() { let _ = board::WIDTH; }
+/// ```
+///
+/// ```compile_fail
+/// is31fl3743a::is31fl3743_layout! {
+///     mod board: rgb {
+///         [(0, 16)],
+///     }
+/// }
+/// # fn main() { let _ = board::WIDTH; }
+/// ```
+#[macro_export]
+macro_rules! is31fl3743_layout {
+    (@channels rgb) => { 3 };
+    (@channels mono) => { 1 };
+    (@cell _) => { None };
+    (@cell ($sw:expr, $cs:expr)) => { Some(($sw, $cs)) };
+    (
+        $(#[$attr:meta])*
+        $vis:vis mod $name:ident: $channels:ident {
+            $([$($cell:tt),* $(,)?]),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis mod $name {
+            const GRID: $crate::grid::Grid = &[$(&[$($crate::is31fl3743_layout!(@cell $cell)),*]),*];
+
+            pub const WIDTH: u8 = $crate::grid::width(GRID);
+            pub const HEIGHT: u8 = $crate::grid::height(GRID);
+            /// Registers every LED takes
+            pub const CHANNELS: u8 = $crate::is31fl3743_layout!(@channels $channels);
+            const _: () = $crate::grid::check(GRID, CHANNELS);
+
+            /// Register of the first channel of the LED at x,y, 0 for gaps
+            pub const PIXELS: [[u8; WIDTH as usize]; HEIGHT as usize] =
+                $crate::grid::pixels(GRID);
+            /// Position of the LED whose first channel is at a register, indexed by register - 1
+            pub const POSITIONS: [Option<(u8, u8)>; 0xC6] = $crate::grid::positions(GRID);
+
+            /// Register of the first channel of the LED at x,y. Gaps and positions outside of
+            /// the grid return 0.
+            pub fn calc_pixel(x: u8, y: u8) -> u8 {
+                PIXELS
+                    .get(y as usize)
+                    .and_then(|row| row.get(x as usize))
+                    .copied()
+                    .unwrap_or(0)
+            }
+
+            /// Position of the LED whose first channel is at a register
+            pub fn position(register: u8) -> Option<(u8, u8)> {
+                POSITIONS.get((register as usize).checked_sub(1)?).copied().flatten()
+            }
+
+            /// A device with this layout
+            pub fn device<I2C>(i2c: I2C, address: $crate::Address) -> $crate::IS31FL3743<I2C> {
+                $crate::IS31FL3743::new(i2c, address, WIDTH, HEIGHT, calc_pixel)
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mock::Bus;
+    use crate::{register, Address};
+
+    crate::is31fl3743_layout! {
+        mod board: rgb {
+            [(0, 0), _, (0, 3)],
+            [(1, 15), (10, 0)],
+        }
+    }
+
+    crate::is31fl3743_layout! {
+        mod strip: mono {
+            [(2, 17), (2, 16)],
+        }
+    }
+
+    #[test]
+    fn size() {
+        assert_eq!((board::WIDTH, board::HEIGHT, board::CHANNELS), (3, 2, 3));
+        assert_eq!((strip::WIDTH, strip::HEIGHT, strip::CHANNELS), (2, 1, 1));
+    }
+
+    #[test]
+    fn pixels() {
+        assert_eq!(
+            board::PIXELS,
+            [
+                [register(0, 0), 0, register(0, 3)],
+                [register(1, 15), register(10, 0), 0]
+            ]
+        );
+        assert_eq!(strip::PIXELS, [[register(2, 17), register(2, 16)]]);
+    }
+
+    #[test]
+    fn positions() {
+        let mut expected = [None; 0xC6];
+        expected[register(0, 0) as usize - 1] = Some((0, 0));
+        expected[register(0, 3) as usize - 1] = Some((2, 0));
+        expected[register(1, 15) as usize - 1] = Some((0, 1));
+        expected[register(10, 0) as usize - 1] = Some((1, 1));
+        assert_eq!(board::POSITIONS, expected);
+    }
+
+    #[test]
+    fn calc_pixel() {
+        assert_eq!(board::calc_pixel(0, 0), 0x01);
+        assert_eq!(board::calc_pixel(2, 0), 0x04);
+        assert_eq!(board::calc_pixel(0, 1), 0x22);
+        assert_eq!(board::calc_pixel(1, 1), 0xB5);
+        // Gaps and positions outside of the grid
+        assert_eq!(board::calc_pixel(1, 0), 0);
+        assert_eq!(board::calc_pixel(2, 1), 0);
+        assert_eq!(board::calc_pixel(3, 0), 0);
+        assert_eq!(board::calc_pixel(0, 2), 0);
+    }
+
+    #[test]
+    fn position() {
+        assert_eq!(board::position(0x01), Some((0, 0)));
+        assert_eq!(board::position(0x04), Some((2, 0)));
+        assert_eq!(board::position(0x22), Some((0, 1)));
+        assert_eq!(board::position(0xB5), Some((1, 1)));
+        // Other channels, unused registers and registers outside of the table
+        assert_eq!(board::position(0x02), None);
+        assert_eq!(board::position(0x07), None);
+        assert_eq!(board::position(0), None);
+        assert_eq!(board::position(0xC7), None);
+        assert_eq!(strip::position(register(2, 17)), Some((0, 0)));
+    }
+
+    #[test]
+    fn device() {
+        let board = board::device(Bus::new(0x20), Address::MIN);
+        assert_eq!((board.width, board.height), (3, 2));
+        assert_eq!((board.calc_pixel)(1, 1), 0xB5);
+        let strip = strip::device(Bus::new(0x20), Address::MIN);
+        assert_eq!((strip.width, strip.height), (2, 1));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::registers::{OpenShort, OpenShortDetect};
+use crate::{Error, IS31FL3743};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+/// How an LED failed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Fault {
+    Open,
+    Short,
+}
+
+/// An LED that failed since the baseline was taken
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Event {
+    /// Zero based SW row
+    pub sw: u8,
+    /// Zero based CS column
+    pub cs: u8,
+    pub fault: Fault,
+}
+
+/// Watches for LEDs failing in the field.
+///
+/// Take a baseline once, e.g. right after [setup](IS31FL3743::setup), so that pins without an
+/// LED aren't reported. Then call [poll](Self::poll) regularly. Every [interval](Self::interval_ms)
+/// it runs open and short detection while the display is idle and reports every channel that
+/// failed since the baseline once.
+pub struct Monitor {
+    pub interval_ms: u32,
+    /// Turn off shorted LEDs by setting their scaling to 0. They aren't checked for opens
+    /// afterwards, as detection sees them as open.
+    pub protect_shorts: bool,
+    /// Faults of the baseline and the ones already reported
+    reported_open: OpenShort,
+    reported_short: OpenShort,
+    /// LEDs turned off by [protect_shorts](Self::protect_shorts)
+    protected: OpenShort,
+    last_run: Option<u32>,
+}
+
+impl Monitor {
+    /// A monitor without a baseline, all faults are reported
+    pub const fn new(interval_ms: u32) -> Self {
+        let none = OpenShort::from_bits([0x00; OpenShort::LEN]);
+        Monitor {
+            interval_ms,
+            protect_shorts: false,
+            reported_open: none,
+            reported_short: none,
+            protected: none,
+            last_run: None,
+        }
+    }
+
+    /// Use earlier detection results, e.g. from the factory test, as the baseline
+    pub fn set_baseline(&mut self, open: OpenShort, short: OpenShort) {
+        self.reported_open = open;
+        self.reported_short = short;
+    }
+
+    /// Run open and short detection now and use the results as the baseline
+    pub fn calibrate<I2C, I2cError, DEL>(
+        &mut self,
+        device: &mut IS31FL3743<I2C>,
+        delay: &mut DEL,
+    ) -> Result<(), I2cError>
+    where
+        I2C: I2c<Error = I2cError>,
+        DEL: DelayNs,
+    {
+        let open = device.detect_open_short(delay, OpenShortDetect::Open)?;
+        let short = device.detect_open_short(delay, OpenShortDetect::Short)?;
+        self.set_baseline(open, short);
+        Ok(())
+    }
+
+    /// Faults found so far, including the baseline
+    pub fn faults(&self) -> (OpenShort, OpenShort) {
+        (self.reported_open, self.reported_short)
+    }
+
+    /// Run detection if the interval has passed and the display is `idle`, i.e. nothing is
+    /// shown that may not flicker. `on_fault` is called once for every newly failed LED.
+    /// Returns how many were found.
+    pub fn poll<I2C, I2cError, DEL, F>(
+        &mut self,
+        device: &mut IS31FL3743<I2C>,
+        delay: &mut DEL,
+        now_ms: u32,
+        idle: bool,
+        mut on_fault: F,
+    ) -> Result<usize, Error<I2cError>>
+    where
+        I2C: I2c<Error = I2cError>,
+        DEL: DelayNs,
+        F: FnMut(Event),
+    {
+        let due = self
+            .last_run
+            .is_none_or(|last_run| now_ms.wrapping_sub(last_run) >= self.interval_ms);
+        if !idle || !due {
+            return Ok(0);
+        }
+        self.last_run = Some(now_ms);
+
+        let mut found = 0;
+        for fault in [Fault::Open, Fault::Short] {
+            let (mode, reported) = match fault {
+                Fault::Open => (OpenShortDetect::Open, &mut self.reported_open),
+                Fault::Short => (OpenShortDetect::Short, &mut self.reported_short),
+            };
+            let result = device.detect_open_short(delay, mode)?;
+            for (sw, cs) in result.faults() {
+                if reported.is_faulty(sw, cs) || self.protected.is_faulty(sw, cs) {
+                    continue;
+                }
+                reported.set_faulty(sw, cs, true);
+                if fault == Fault::Short && self.protect_shorts {
+                    device.set_scaling_at(sw, cs, 0x00)?;
+                    self.protected.set_faulty(sw, cs, true);
+                }
+                on_fault(Event { sw, cs, fault });
+                found += 1;
+            }
+        }
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, Bus, NoDelay};
+    use crate::register;
+    use std::vec::Vec;
+
+    fn device() -> IS31FL3743<Bus> {
+        let mut device = mock::device();
+        device.setup(&mut NoDelay).unwrap();
+        device.fill_scaling(&[0xFF; 0xC6]).unwrap();
+        device
+    }
+
+    fn poll(monitor: &mut Monitor, device: &mut IS31FL3743<Bus>, now_ms: u32) -> Vec<Event> {
+        let mut events = Vec::new();
+        let found = monitor
+            .poll(device, &mut NoDelay, now_ms, true, |event| {
+                events.push(event)
+            })
+            .unwrap();
+        assert_eq!(found, events.len());
+        events
+    }
+
+    #[test]
+    fn reports_new_faults_once() {
+        let mut device = device();
+        device.i2c.open.set_faulty(0, 17, true);
+        let mut monitor = Monitor::new(1000);
+        monitor.calibrate(&mut device, &mut NoDelay).unwrap();
+        assert_eq!(monitor.faults().0.faults().collect::<Vec<_>>(), [(0, 17)]);
+
+        // Detection drives every LED, no matter what is shown
+        device.fill(0x00).unwrap();
+        device.i2c.open.set_faulty(4, 5, true);
+        let events = poll(&mut monitor, &mut device, 0);
+        assert_eq!(
+            events,
+            [Event {
+                sw: 4,
+                cs: 5,
+                fault: Fault::Open
+            }]
+        );
+        assert!(device.i2c.pwm().iter().all(|&pwm| pwm == 0x00));
+        // Not due yet, then nothing new
+        assert!(poll(&mut monitor, &mut device, 999).is_empty());
+        assert!(poll(&mut monitor, &mut device, 1999).is_empty());
+    }
+
+    #[test]
+    fn protected_shorts_are_not_reported_open() {
+        let mut device = device();
+        let mut monitor = Monitor::new(0);
+        monitor.protect_shorts = true;
+        monitor.calibrate(&mut device, &mut NoDelay).unwrap();
+        device.fill(0x20).unwrap();
+
+        device.i2c.short.set_faulty(2, 3, true);
+        let events = poll(&mut monitor, &mut device, 0);
+        assert_eq!(
+            events,
+            [Event {
+                sw: 2,
+                cs: 3,
+                fault: Fault::Short
+            }]
+        );
+        assert_eq!(device.i2c.pages[1][register(2, 3) as usize], 0x00);
+        assert!(device.i2c.pwm().iter().all(|&pwm| pwm == 0x20));
+        // Without scaling the LED looks open now
+        assert!(poll(&mut monitor, &mut device, 1).is_empty());
+        assert!(device
+            .detect_open_short(&mut NoDelay, OpenShortDetect::Open)
+            .unwrap()
+            .is_faulty(2, 3));
+    }
+}
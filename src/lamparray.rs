@@ -0,0 +1,396 @@
+//! Every LED of a [Layout](crate::layout::Layout) is one lamp, its lamp ID is its index in the
+//! layout.
+//!
+//! The reports are those of the HID Lighting and Illumination usage page (0x59). The report
+//! descriptor of the USB stack must use the report IDs in
+//! [report_id](crate::lamparray::report_id) and the layouts below, all numbers are little endian
+//! and the report ID isn't part of the data:
+//!
+//! | Report                   | Type        | Layout                                            |
+//! |--------------------------|-------------|---------------------------------------------------|
+//! | LampArrayAttributes      | Feature get | count u16, width, height, depth, kind, interval u32 |
+//! | LampAttributesRequest    | Feature set | lamp ID u16                                       |
+//! | LampAttributesResponse   | Feature get | lamp ID u16, x, y, z, latency, purposes u32, red, green, blue, intensity levels, programmable, input binding u8 |
+//! | LampMultiUpdate          | Feature set | count u8, flags u8, 8 lamp IDs u16, 8 RGBI colors |
+//! | LampRangeUpdate          | Feature set | flags u8, first, last lamp ID u16, RGBI color     |
+//! | LampArrayControl         | Feature set | autonomous mode u8                                |
+use crate::color::Rgb;
+use crate::framebuffer::{FrameBuffer, Output};
+use crate::layout::Layout;
+use crate::reactive::MAX_KEYS;
+
+/// Report IDs of the LampArray reports
+pub mod report_id {
+    pub const ARRAY_ATTRIBUTES: u8 = 0x01;
+    pub const ATTRIBUTES_REQUEST: u8 = 0x02;
+    pub const ATTRIBUTES_RESPONSE: u8 = 0x03;
+    pub const MULTI_UPDATE: u8 = 0x04;
+    pub const RANGE_UPDATE: u8 = 0x05;
+    pub const ARRAY_CONTROL: u8 = 0x06;
+}
+
+/// Length of the LampArrayAttributes report
+pub const ARRAY_ATTRIBUTES_LEN: usize = 22;
+/// Length of the LampAttributesResponse report
+pub const ATTRIBUTES_RESPONSE_LEN: usize = 28;
+/// Number of lamps a LampMultiUpdate report can update
+pub const MULTI_UPDATE_LAMPS: usize = 8;
+
+/// LampUpdateFlags bit telling that the host is done updating and the lamps should change
+const UPDATE_COMPLETE: u8 = 0x01;
+
+/// LampArrayKind, what the lamps are built into
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Kind {
+    Undefined = 0x00,
+    Keyboard = 0x01,
+    Mouse = 0x02,
+    GameController = 0x03,
+    Peripheral = 0x04,
+    Scene = 0x05,
+    Notification = 0x06,
+    Chassis = 0x07,
+    Wearable = 0x08,
+    Furniture = 0x09,
+    Art = 0x0A,
+}
+
+/// LampPurposes flags
+pub mod purpose {
+    pub const CONTROL: u32 = 0x01;
+    pub const ACCENT: u32 = 0x02;
+    pub const BRANDING: u32 = 0x04;
+    pub const STATUS: u32 = 0x08;
+    pub const ILLUMINATION: u32 = 0x10;
+    pub const PRESENTATION: u32 = 0x20;
+}
+
+/// A decoded report sent by the host
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Report {
+    /// Select the lamp the next LampAttributesResponse describes
+    AttributesRequest { lamp_id: u16 },
+    MultiUpdate {
+        flags: u8,
+        count: u8,
+        lamp_ids: [u16; MULTI_UPDATE_LAMPS],
+        /// Red, green, blue and intensity of every lamp
+        colors: [[u8; 4]; MULTI_UPDATE_LAMPS],
+    },
+    RangeUpdate {
+        flags: u8,
+        first: u16,
+        last: u16,
+        color: [u8; 4],
+    },
+    /// Whether the device controls the lamps on its own instead of the host
+    ArrayControl { autonomous: bool },
+}
+
+impl Report {
+    /// Decode a set feature or output report. Returns `None` for unknown report IDs and reports
+    /// that are too short.
+    pub fn parse(report_id: u8, data: &[u8]) -> Option<Self> {
+        let u16_at = |at: usize| Some(u16::from_le_bytes([*data.get(at)?, *data.get(at + 1)?]));
+        let color_at = |at: usize| data.get(at..at + 4)?.try_into().ok();
+        match report_id {
+            report_id::ATTRIBUTES_REQUEST => Some(Report::AttributesRequest {
+                lamp_id: u16_at(0)?,
+            }),
+            report_id::MULTI_UPDATE => {
+                let count = *data.first()?;
+                if count as usize > MULTI_UPDATE_LAMPS {
+                    return None;
+                }
+                let mut lamp_ids = [0; MULTI_UPDATE_LAMPS];
+                let mut colors = [[0; 4]; MULTI_UPDATE_LAMPS];
+                for slot in 0..MULTI_UPDATE_LAMPS {
+                    lamp_ids[slot] = u16_at(2 + 2 * slot)?;
+                    colors[slot] = color_at(2 + 2 * MULTI_UPDATE_LAMPS + 4 * slot)?;
+                }
+                Some(Report::MultiUpdate {
+                    flags: data[1],
+                    count,
+                    lamp_ids,
+                    colors,
+                })
+            }
+            report_id::RANGE_UPDATE => Some(Report::RangeUpdate {
+                flags: *data.first()?,
+                first: u16_at(1)?,
+                last: u16_at(3)?,
+                color: color_at(5)?,
+            }),
+            report_id::ARRAY_CONTROL => Some(Report::ArrayControl {
+                autonomous: *data.first()? != 0,
+            }),
+            _ => None,
+        }
+    }
+}
+
+/// Exposes the LEDs of a keyboard as a HID LampArray, so they can be controlled by the operating
+/// system.
+///
+/// Answer get feature reports with [array_attributes](Self::array_attributes) and
+/// [attributes_response](Self::attributes_response), and pass all set feature reports to
+/// [handle](Self::handle). Once it returns true, write the new colors with
+/// [flush](Self::flush) for every controller of the layout. While
+/// [autonomous](Self::autonomous) is set the firmware drives the LEDs itself and color updates
+/// from the host are ignored.
+///
+/// Positions are taken from the layout, [unit_um](Layout::unit_um) is also used as the depth of
+/// the lamp array.
+pub struct LampArray<'a> {
+    pub layout: Layout<'a>,
+    pub kind: Kind,
+    /// HID keyboard usage of every LED, in the order of the layout. LEDs without one report 0.
+    pub usages: &'a [u8],
+    pub purposes: u32,
+    /// Shortest time between two updates the host should send
+    pub min_update_interval_us: u32,
+    /// Time from an update until the lamps change
+    pub update_latency_us: u32,
+    pub autonomous: bool,
+    pub frame: FrameBuffer,
+    /// Color of every lamp, as set by the host
+    colors: [Rgb; MAX_KEYS],
+    /// Lamp described by the next LampAttributesResponse
+    next_lamp: u16,
+}
+
+impl<'a> LampArray<'a> {
+    /// A keyboard in autonomous mode, the mode the host expects after the device is connected
+    pub fn new(layout: Layout<'a>, usages: &'a [u8]) -> Self {
+        LampArray {
+            layout,
+            kind: Kind::Keyboard,
+            usages,
+            purposes: purpose::CONTROL,
+            min_update_interval_us: 10_000,
+            update_latency_us: 4_000,
+            autonomous: true,
+            frame: FrameBuffer::new(),
+            colors: [Rgb::BLACK; MAX_KEYS],
+            next_lamp: 0,
+        }
+    }
+
+    /// Number of lamps, at most [MAX_KEYS]
+    pub fn lamp_count(&self) -> u16 {
+        self.layout.leds.len().min(MAX_KEYS) as u16
+    }
+
+    /// The LampArrayAttributes report. The bounding box is the size of the layout.
+    pub fn array_attributes(&self) -> [u8; ARRAY_ATTRIBUTES_LEN] {
+        let layout = &self.layout;
+        let width = layout.width as u32 * layout.unit_um / 100;
+        let height = layout.height as u32 * layout.unit_um / 100;
+
+        let mut report = [0x00; ARRAY_ATTRIBUTES_LEN];
+        report[0..2].copy_from_slice(&self.lamp_count().to_le_bytes());
+        report[2..6].copy_from_slice(&width.to_le_bytes());
+        report[6..10].copy_from_slice(&height.to_le_bytes());
+        report[10..14].copy_from_slice(&layout.unit_um.to_le_bytes());
+        report[14..18].copy_from_slice(&(self.kind as u32).to_le_bytes());
+        report[18..22].copy_from_slice(&self.min_update_interval_us.to_le_bytes());
+        report
+    }
+
+    /// The LampAttributesResponse report of the lamp selected by the last
+    /// LampAttributesRequest. Every response selects the next lamp, wrapping around after the
+    /// last one.
+    pub fn attributes_response(&mut self) -> [u8; ATTRIBUTES_RESPONSE_LEN] {
+        let lamp_id = self.next_lamp;
+        self.next_lamp = match lamp_id + 1 {
+            next if next < self.lamp_count() => next,
+            _ => 0,
+        };
+
+        let mut report = [0x00; ATTRIBUTES_RESPONSE_LEN];
+        report[0..2].copy_from_slice(&lamp_id.to_le_bytes());
+        let Some(led) = self.layout.leds.get(lamp_id as usize) else {
+            return report;
+        };
+        // The center of the LED, halfway into the depth
+        let (x, y) = self.layout.position_um(led);
+        report[2..6].copy_from_slice(&x.to_le_bytes());
+        report[6..10].copy_from_slice(&y.to_le_bytes());
+        report[10..14].copy_from_slice(&(self.layout.unit_um / 2).to_le_bytes());
+        report[14..18].copy_from_slice(&self.update_latency_us.to_le_bytes());
+        report[18..22].copy_from_slice(&self.purposes.to_le_bytes());
+        // 256 levels for every color channel, intensity isn't adjustable
+        report[22..25].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+        report[25] = 0x01;
+        // Programmable
+        report[26] = 0x01;
+        report[27] = self.usages.get(lamp_id as usize).copied().unwrap_or(0);
+        report
+    }
+
+    /// Apply a report from the host. Returns whether an update with the LampUpdateComplete
+    /// flag set came in, i.e. the lamps should be [flushed](Self::flush).
+    pub fn handle(&mut self, report: Report) -> bool {
+        let flags = match report {
+            Report::AttributesRequest { lamp_id } => {
+                self.next_lamp = if lamp_id < self.lamp_count() {
+                    lamp_id
+                } else {
+                    0
+                };
+                return false;
+            }
+            Report::ArrayControl { autonomous } => {
+                self.autonomous = autonomous;
+                return false;
+            }
+            _ if self.autonomous => return false,
+            Report::MultiUpdate {
+                flags,
+                count,
+                lamp_ids,
+                colors,
+            } => {
+                for (lamp_id, color) in lamp_ids.iter().zip(colors.iter()).take(count as usize) {
+                    self.set_lamp(*lamp_id, *color);
+                }
+                flags
+            }
+            Report::RangeUpdate {
+                flags,
+                first,
+                last,
+                color,
+            } => {
+                let last = last.min(self.lamp_count().saturating_sub(1));
+                for lamp_id in first..=last {
+                    self.set_lamp(lamp_id, color);
+                }
+                flags
+            }
+        };
+        flags & UPDATE_COMPLETE != 0
+    }
+
+    /// Decode and apply a set feature report, see [handle](Self::handle). Unknown and
+    /// malformed reports are ignored.
+    pub fn set_report(&mut self, report_id: u8, data: &[u8]) -> bool {
+        match Report::parse(report_id, data) {
+            Some(report) => self.handle(report),
+            None => false,
+        }
+    }
+
+    /// Write the lamps of one controller of the layout to the device, see [Output]
+    pub fn flush<O: Output>(&mut self, output: &mut O, controller: u8) -> Result<(), O::Error> {
+        self.frame.clear();
+        for (led, color) in self.layout.leds.iter().zip(self.colors.iter()) {
+            if led.controller == controller {
+                self.frame
+                    .set_color(led.register, self.layout.order, *color);
+            }
+        }
+        self.frame.flush(output)
+    }
+
+    fn set_lamp(&mut self, lamp_id: u16, [r, g, b, _]: [u8; 4]) {
+        if lamp_id < self.lamp_count() {
+            self.colors[lamp_id as usize] = Rgb::new(r, g, b);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::ColorOrder;
+    use crate::layout::{build, key, Led, Wiring, U};
+    use crate::mock::device;
+    use crate::{register, Address};
+
+    /// A wide key and a normal one, each on its own controller
+    const LEDS: [Led; 2] = build(
+        &[(100, &[key(200), U])],
+        &[Wiring::new(0, 0, 0), Wiring::new(1, 2, 3)],
+    );
+
+    fn lamp_array() -> LampArray<'static> {
+        LampArray::new(
+            Layout {
+                leds: &LEDS,
+                controllers: &[Address::MIN, Address::MAX],
+                order: ColorOrder::Rgb,
+                width: 300,
+                height: 100,
+                unit_um: 19_000,
+            },
+            &[0x29],
+        )
+    }
+
+    fn u32_at(report: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(report[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn attributes() {
+        let mut lamps = lamp_array();
+        let report = lamps.array_attributes();
+        assert_eq!(report[0..2], [2, 0]);
+        assert_eq!(
+            [2, 6, 10, 14].map(|at| u32_at(&report, at)),
+            [57_000, 19_000, 19_000, Kind::Keyboard as u32]
+        );
+
+        lamps.handle(Report::AttributesRequest { lamp_id: 1 });
+        let report = lamps.attributes_response();
+        assert_eq!(report[0..2], [1, 0]);
+        assert_eq!(
+            [2, 6, 10].map(|at| u32_at(&report, at)),
+            [47_500, 9_500, 9_500]
+        );
+        assert_eq!(report[27], 0);
+        // Wraps around to the first lamp
+        let report = lamps.attributes_response();
+        assert_eq!(report[0..2], [0, 0]);
+        assert_eq!(u32_at(&report, 2), 19_000);
+        assert_eq!(report[27], 0x29);
+    }
+
+    #[test]
+    fn updates() {
+        let mut lamps = lamp_array();
+        let mut device = device();
+        let update = Report::RangeUpdate {
+            flags: UPDATE_COMPLETE,
+            first: 0,
+            last: 0xFFFF,
+            color: [0x10, 0x20, 0x30, 0x01],
+        };
+        // Ignored until the host takes over
+        assert!(!lamps.handle(update));
+        assert!(!lamps.set_report(report_id::ARRAY_CONTROL, &[0x00]));
+        assert!(lamps.handle(update));
+
+        let mut lamp_ids = [0; MULTI_UPDATE_LAMPS];
+        lamp_ids[..2].copy_from_slice(&[1, 7]);
+        let mut colors = [[0; 4]; MULTI_UPDATE_LAMPS];
+        colors[..2].copy_from_slice(&[[0x40, 0x50, 0x60, 0x01], [0xFF; 4]]);
+        let update = Report::MultiUpdate {
+            flags: 0,
+            count: 2,
+            lamp_ids,
+            colors,
+        };
+        assert!(!lamps.handle(update));
+
+        lamps.flush(&mut device, 0).unwrap();
+        assert_eq!(device.i2c.pwm()[..4], [0x10, 0x20, 0x30, 0x00]);
+        assert_eq!(device.i2c.pages[0][register(2, 3) as usize], 0x00);
+        lamps.flush(&mut device, 1).unwrap();
+        assert_eq!(device.i2c.pwm()[..3], [0x00; 3]);
+        let at = register(2, 3) as usize;
+        assert_eq!(device.i2c.pages[0][at..at + 3], [0x40, 0x50, 0x60]);
+    }
+}
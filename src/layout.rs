@@ -0,0 +1,463 @@
+//! Physical placement of the LEDs of a keyboard.
+//!
+//! [calc_pixel](crate::IS31FL3743::calc_pixel) only maps a regular grid, which doesn't fit
+//! keyboards with keys of different sizes and empty matrix positions. A
+//! [Layout](crate::layout::Layout) lists every LED with its position and register instead, and
+//! can span several controllers.
+//!
+//! Positions are in 1/100 of a key unit, e.g. a position of 150 is one and a half keys from the
+//! left edge. Multiply by [unit_um](crate::layout::Layout::unit_um) to get physical distances.
+//!
+//! The geometry of a keyboard is described row by row with [Key](crate::layout::Key)s, and
+//! [build](crate::layout::build) places the LEDs of a board under them. The wiring depends on
+//! the board, so it is given for every key:
+//!
+//! ```
+//! use is31fl3743a::layout::{self, key, Key, Layout, Led, Row, Wiring, U};
+//! use is31fl3743a::{color::ColorOrder, Address};
+//!
+//! const ROWS: [Row; 2] = [(100, &[U, key(200)]), (100, &[key(150), key(150)])];
+//! const LEDS: [Led; 4] = layout::build(
+//!     &ROWS,
+//!     &[
+//!         Wiring::new(0, 0, 0).key(0, 0),
+//!         Wiring::new(0, 0, 3).key(0, 1),
+//!         Wiring::new(0, 1, 0).key(1, 0),
+//!         Wiring::new(0, 1, 3).key(1, 1),
+//!     ],
+//! );
+//! const SIZE: (u16, u16) = layout::size(&ROWS);
+//! const BOARD: Layout = Layout {
+//!     leds: &LEDS,
+//!     controllers: &[Address::MIN],
+//!     order: ColorOrder::Rgb,
+//!     width: SIZE.0,
+//!     height: SIZE.1,
+//!     unit_um: 19_050,
+//! };
+//! assert_eq!(BOARD.find(1, 1).map(|led| (led.x, led.y)), Some((225, 150)));
+//! ```
+use crate::color::ColorOrder;
+use crate::{register, AddrPin, Address, SwSetting, CS_PINS, SW_PINS};
+
+/// One LED and the key it sits under
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Led {
+    /// Horizontal position of the center of the LED, from the left edge of the layout
+    pub x: u16,
+    /// Vertical position of the center of the LED, from the top edge of the layout
+    pub y: u16,
+    /// Index of the controller in [Layout::controllers]
+    pub controller: u8,
+    /// PWM and scaling register of the first channel, the others follow in [Layout::order]
+    pub register: u8,
+    /// Row and column of the key in the key matrix, if the LED belongs to a key
+    pub matrix: Option<(u8, u8)>,
+}
+
+/// LEDs of a keyboard with their physical positions
+#[derive(Clone, Copy, Debug)]
+pub struct Layout<'a> {
+    pub leds: &'a [Led],
+    /// Address of every controller the LEDs are spread over
+    pub controllers: &'a [Address],
+    /// How the channels of each LED are connected
+    pub order: ColorOrder,
+    /// Width of the layout, in 1/100 of a key unit
+    pub width: u16,
+    /// Height of the layout, in 1/100 of a key unit
+    pub height: u16,
+    /// Size of a key unit in micrometers
+    pub unit_um: u32,
+}
+
+impl<'a> Layout<'a> {
+    /// The LEDs driven by one controller
+    pub fn leds_of(&self, controller: u8) -> impl Iterator<Item = &'a Led> {
+        self.leds
+            .iter()
+            .filter(move |led| led.controller == controller)
+    }
+
+    /// The LED of a key in the key matrix
+    pub fn find(&self, row: u8, col: u8) -> Option<&'a Led> {
+        self.leds.get(self.index_of(row, col)?)
+    }
+
+    /// Index in [leds](Self::leds) of the LED of a key in the key matrix
+    pub fn index_of(&self, row: u8, col: u8) -> Option<usize> {
+        self.leds
+            .iter()
+            .position(|led| led.matrix == Some((row, col)))
+    }
+
+    /// Position of an LED in micrometers
+    pub fn position_um(&self, led: &Led) -> (u32, u32) {
+        (
+            led.x as u32 * self.unit_um / 100,
+            led.y as u32 * self.unit_um / 100,
+        )
+    }
+}
+
+/// A key of a row, in 1/100 of a key unit
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key {
+    pub width: u16,
+    /// 0 for the height of the row
+    pub height: u16,
+    /// Offset from the top of the row
+    pub top: u16,
+    /// Whether the next key starts to the right of this one, or below it
+    pub advance: bool,
+}
+
+/// A key as high as its row
+pub const fn key(width: u16) -> Key {
+    Key {
+        width,
+        height: 0,
+        top: 0,
+        advance: true,
+    }
+}
+
+/// A key one unit wide
+pub const U: Key = key(100);
+
+/// Upper half of a stacked pair of keys, like the up arrow above the down arrow
+pub const UPPER: Key = Key {
+    width: 100,
+    height: 50,
+    top: 0,
+    advance: false,
+};
+/// Lower half of a stacked pair of keys, follows [UPPER]
+pub const LOWER: Key = Key {
+    width: 100,
+    height: 50,
+    top: 50,
+    advance: true,
+};
+
+/// A key reaching into the next row, like the ISO enter key
+pub const fn tall(width: u16, height: u16) -> Key {
+    Key {
+        width,
+        height,
+        top: 0,
+        advance: true,
+    }
+}
+
+/// A row of keys and its height
+pub type Row = (u16, &'static [Key]);
+
+/// Where the LED under a key is connected
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Wiring {
+    /// Index of the controller in [Layout::controllers]
+    pub controller: u8,
+    /// Zero based SW row
+    pub sw: u8,
+    /// Zero based CS column of the first channel
+    pub cs: u8,
+    /// Row and column of the key in the key matrix
+    pub matrix: Option<(u8, u8)>,
+}
+
+impl Wiring {
+    /// An LED without a key in the key matrix
+    pub const fn new(controller: u8, sw: u8, cs: u8) -> Self {
+        Wiring {
+            controller,
+            sw,
+            cs,
+            matrix: None,
+        }
+    }
+
+    /// The same LED, under the key at `row`,`col` of the key matrix
+    pub const fn key(self, row: u8, col: u8) -> Self {
+        Wiring {
+            matrix: Some((row, col)),
+            ..self
+        }
+    }
+}
+
+/// Number of keys in some rows
+pub const fn count(rows: &[Row]) -> usize {
+    let mut count = 0;
+    let mut row = 0;
+    while row < rows.len() {
+        count += rows[row].1.len();
+        row += 1;
+    }
+    count
+}
+
+/// Width of the widest row and height of all rows, for [Layout::width] and [Layout::height]
+pub const fn size(rows: &[Row]) -> (u16, u16) {
+    let mut width = 0;
+    let mut height = 0;
+    let mut row = 0;
+    while row < rows.len() {
+        let (row_height, keys) = rows[row];
+        let mut x = 0;
+        let mut index = 0;
+        while index < keys.len() {
+            if keys[index].advance {
+                x += keys[index].width;
+            }
+            index += 1;
+        }
+        if x > width {
+            width = x;
+        }
+        height += row_height;
+        row += 1;
+    }
+    (width, height)
+}
+
+/// Lay out the keys row by row from the top left and place an LED under each of them, wired
+/// as given in the same order. Fails to compile when used in a constant and the number of keys
+/// doesn't match or an LED is outside of the matrix.
+pub const fn build<const N: usize>(rows: &[Row], wiring: &[Wiring; N]) -> [Led; N] {
+    let mut leds = [Led {
+        x: 0,
+        y: 0,
+        controller: 0,
+        register: 0,
+        matrix: None,
+    }; N];
+    if count(rows) != N {
+        panic!("number of keys doesn't match the number of LEDs");
+    }
+    let mut count = 0;
+    let mut y = 0;
+    let mut row = 0;
+    while row < rows.len() {
+        let (row_height, keys) = rows[row];
+        let mut x = 0;
+        let mut index = 0;
+        while index < keys.len() {
+            let key = keys[index];
+            let height = if key.height == 0 {
+                row_height
+            } else {
+                key.height
+            };
+            let wiring = wiring[count];
+            if wiring.sw >= SW_PINS || wiring.cs >= CS_PINS {
+                panic!("LED outside of the matrix");
+            }
+            leds[count] = Led {
+                x: x + key.width / 2,
+                y: y + key.top + height / 2,
+                controller: wiring.controller,
+                register: register(wiring.sw, wiring.cs),
+                matrix: wiring.matrix,
+            };
+            if key.advance {
+                x += key.width;
+            }
+            count += 1;
+            index += 1;
+        }
+        y += row_height;
+        row += 1;
+    }
+    leds
+}
+
+/// The half height function row of [ANSI_ROWS], [ISO_ROWS] and [JIS_ROWS]
+const FUNCTION_ROW: Row = (
+    50,
+    &[key(150), U, U, U, U, U, U, U, U, U, U, U, U, key(150)],
+);
+
+/// A compact ANSI keyboard as built into laptops, 15 units wide: a half height function row
+/// and the up and down arrows stacked in the bottom row
+pub const ANSI_ROWS: [Row; 6] = [
+    FUNCTION_ROW,
+    (100, &[U, U, U, U, U, U, U, U, U, U, U, U, U, key(200)]),
+    (
+        100,
+        &[key(150), U, U, U, U, U, U, U, U, U, U, U, U, key(150)],
+    ),
+    (100, &[key(175), U, U, U, U, U, U, U, U, U, U, U, key(225)]),
+    (100, &[key(225), U, U, U, U, U, U, U, U, U, U, key(275)]),
+    (100, &[U, U, U, U, key(600), U, U, U, UPPER, LOWER, U]),
+];
+
+/// [ANSI_ROWS] with an ISO enter key and the extra key next to the left shift
+pub const ISO_ROWS: [Row; 6] = [
+    FUNCTION_ROW,
+    ANSI_ROWS[1],
+    (
+        100,
+        &[key(150), U, U, U, U, U, U, U, U, U, U, U, U, tall(150, 200)],
+    ),
+    (100, &[key(175), U, U, U, U, U, U, U, U, U, U, U, U]),
+    (100, &[key(125), U, U, U, U, U, U, U, U, U, U, U, key(275)]),
+    ANSI_ROWS[5],
+];
+
+/// [ISO_ROWS] with the extra keys of the JIS layout and a shorter space bar
+pub const JIS_ROWS: [Row; 6] = [
+    FUNCTION_ROW,
+    (100, &[U, U, U, U, U, U, U, U, U, U, U, U, U, U, U]),
+    ISO_ROWS[2],
+    ISO_ROWS[3],
+    (100, &[key(225), U, U, U, U, U, U, U, U, U, U, U, key(175)]),
+    (100, &[U, U, U, U, U, key(400), U, U, U, U, UPPER, LOWER, U]),
+];
+
+/// Number of keys of [ANSI_ROWS]
+pub const ANSI_KEYS: usize = count(&ANSI_ROWS);
+/// Number of keys of [ISO_ROWS]
+pub const ISO_KEYS: usize = count(&ISO_ROWS);
+/// Number of keys of [JIS_ROWS]
+pub const JIS_KEYS: usize = count(&JIS_ROWS);
+
+/// Addresses of the two controllers of the Framework 16 keyboard module, 0x20 and 0x23
+pub const FRAMEWORK16_CONTROLLERS: [Address; 2] = [
+    Address::new(AddrPin::Gnd, AddrPin::Gnd),
+    Address::new(AddrPin::Vcc, AddrPin::Gnd),
+];
+/// The Framework 16 keyboard module only connects SW1 to SW9
+pub const FRAMEWORK16_SW_SETTING: SwSetting = SwSetting::Sw1Sw9;
+/// How the LEDs of the Framework 16 keyboard module are connected
+pub const FRAMEWORK16_ORDER: ColorOrder = ColorOrder::Bgr;
+
+/// Whether LEDs can be wired like on the Framework 16 keyboard module: on one of the
+/// [FRAMEWORK16_CONTROLLERS], on SW1 to SW9 and with all three channels on the matrix. Check
+/// tables built for it at compile time with `const _: () = assert!(fits_framework16(&LEDS));`.
+///
+/// The per key wiring of the module isn't part of this crate, as it has to be taken from the
+/// board schematic. Build it from [ANSI_ROWS], [ISO_ROWS] or [JIS_ROWS] with [build].
+pub const fn fits_framework16(leds: &[Led]) -> bool {
+    let mut index = 0;
+    while index < leds.len() {
+        let Led {
+            controller,
+            register,
+            ..
+        } = leds[index];
+        let Some(first) = register.checked_sub(1) else {
+            return false;
+        };
+        let (sw, cs) = (first / CS_PINS, first % CS_PINS);
+        if controller as usize >= FRAMEWORK16_CONTROLLERS.len() || sw >= 9 || cs + 3 > CS_PINS {
+            return false;
+        }
+        index += 1;
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Six RGB LEDs on every SW row, keys in the matrix in the same order
+    const fn wiring<const N: usize>() -> [Wiring; N] {
+        let mut wiring = [Wiring::new(0, 0, 0); N];
+        let mut index = 0;
+        while index < N {
+            let on_controller = (index % 66) as u8;
+            wiring[index] =
+                Wiring::new((index / 66) as u8, on_controller / 6, on_controller % 6 * 3)
+                    .key((index / 16) as u8, (index % 16) as u8);
+            index += 1;
+        }
+        wiring
+    }
+
+    const ANSI: [Led; ANSI_KEYS] = build(&ANSI_ROWS, &wiring());
+    const ISO: [Led; ISO_KEYS] = build(&ISO_ROWS, &wiring());
+    const JIS: [Led; JIS_KEYS] = build(&JIS_ROWS, &wiring());
+
+    fn layout(leds: &[Led]) -> Layout<'_> {
+        Layout {
+            leds,
+            controllers: &[Address::MIN, Address::MAX],
+            order: ColorOrder::Bgr,
+            width: 1500,
+            height: 550,
+            unit_um: 18_750,
+        }
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!((ANSI_KEYS, ISO_KEYS, JIS_KEYS), (78, 79, 82));
+        for rows in [ANSI_ROWS, ISO_ROWS, JIS_ROWS] {
+            assert_eq!(size(&rows), (1500, 550));
+        }
+        assert_eq!(size(&[]), (0, 0));
+    }
+
+    #[test]
+    fn positions() {
+        let position = |leds: &[Led], index: usize| (leds[index].x, leds[index].y);
+        // Escape in the half height function row, then the first full row
+        assert_eq!(position(&ANSI, 0), (75, 25));
+        assert_eq!(position(&ANSI, 14), (50, 100));
+        // Stacked arrows and the key after them
+        assert_eq!(position(&ANSI, 75), (1350, 475));
+        assert_eq!(position(&ANSI, 76), (1350, 525));
+        assert_eq!(position(&ANSI, 77), (1450, 500));
+        // The ISO enter key spans two rows
+        assert_eq!(position(&ISO, 41), (1425, 250));
+        assert_eq!(position(&JIS, 81), (1450, 500));
+        for leds in [&ANSI[..], &ISO, &JIS] {
+            let layout = layout(leds);
+            assert!(leds
+                .iter()
+                .all(|led| led.x < layout.width && led.y < layout.height));
+        }
+    }
+
+    #[test]
+    fn wiring_and_lookup() {
+        let layout = layout(&ANSI);
+        assert_eq!(ANSI[7].register, register(1, 3));
+        assert_eq!(ANSI[70].register, register(0, 12));
+        assert_eq!(layout.leds_of(0).count(), 66);
+        assert_eq!(layout.leds_of(1).count(), 12);
+        assert_eq!(layout.index_of(4, 6), Some(70));
+        assert_eq!(layout.find(4, 6), Some(&ANSI[70]));
+        assert_eq!(layout.find(15, 0), None);
+        assert_eq!(layout.position_um(&ANSI[14]), (9_375, 18_750));
+    }
+
+    #[test]
+    fn framework16() {
+        assert_eq!(FRAMEWORK16_CONTROLLERS.map(Address::as_u8), [0x20, 0x23]);
+        // The test wiring spans all 11 SW rows of the first controller
+        assert!(!fits_framework16(&ANSI));
+        let fitting = |sw, cs| {
+            [Led {
+                x: 0,
+                y: 0,
+                controller: 1,
+                register: register(sw, cs),
+                matrix: None,
+            }]
+        };
+        assert!(fits_framework16(&fitting(8, 15)));
+        assert!(!fits_framework16(&fitting(9, 0)));
+        assert!(!fits_framework16(&fitting(0, 16)));
+        let mut unknown = fitting(0, 0);
+        unknown[0].controller = 2;
+        assert!(!fits_framework16(&unknown));
+        unknown[0] = Led {
+            register: 0,
+            ..fitting(0, 0)[0]
+        };
+        assert!(!fits_framework16(&unknown));
+    }
+}
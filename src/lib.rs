@@ -1,17 +1,76 @@
 #![no_std]
 #![doc = include_str!("../README.md")]
+#[cfg(any(feature = "std", test))]
+extern crate std;
+
+/// I2C slave address selection
+pub mod address;
+/// Compact animation format and player
+pub mod animation;
+/// Command line interface, generic over the I2C bus
+#[cfg(feature = "std")]
+pub mod cli;
+/// Color types used by the drawing helpers
+pub mod color;
+/// Conversion of PNG and GIF images into PWM frames
+#[cfg(feature = "convert")]
+pub mod convert;
 /// Preconfigured devices
 pub mod devices;
+/// Time based lighting effects
+pub mod effects;
+/// Smooth transitions of brightness
+pub mod fade;
+/// Host side copy of the PWM page
+pub mod framebuffer;
+/// Compile time lookup tables for custom boards
+pub mod grid;
+/// Periodic open/short detection to find failing LEDs
+pub mod health;
+/// HID LampArray reports, for lighting controlled by the operating system
+pub mod lamparray;
+/// Physical placement of keyboard LEDs
+pub mod layout;
+/// Current budget and power limiting
+pub mod power;
+/// OpenRGB's QMK raw HID protocol
+pub mod protocol;
+/// Keypress driven lighting for keyboards
+pub mod reactive;
+/// Typed access to the registers in the function page
+pub mod registers;
+/// Hardware shutdown through the SDB pin
+pub mod sdb;
+/// Report of the production self test
+pub mod selftest;
+/// Backup of all registers
+pub mod snapshot;
+/// Bitmap fonts and scrolling text
+pub mod text;
+/// Rotation, mirroring and windows in logical coordinates
+pub mod transform;
+/// Lighting commands of the VIA configurator
+pub mod via;
+
+#[cfg(test)]
+mod mock;
+
+pub use address::{AddrPin, Address};
 use embedded_hal::delay::DelayNs;
-use embedded_hal::i2c::I2c;
+use embedded_hal::i2c::{ErrorKind, I2c};
+use registers::{
+    Configuration, GlobalCurrent, OpenShortDetect, PullResistors, Register, Reset, SpreadSpectrum,
+    Temperature,
+};
+use snapshot::Snapshot;
 
 /// A struct to integrate with a new IS31FL3743A powered device.
 pub struct IS31FL3743<I2C> {
     /// The i2c bus that is used to interact with the device. See implementation below for the
     /// trait methods required.
     pub i2c: I2C,
-    /// The 7-bit i2c slave address of the device. By default on most devices this is `0x74`.
-    pub address: u8,
+    /// The 7-bit i2c slave address of the device, selected by the ADDR pins.
+    pub address: Address,
     /// Width of the LED matrix
     pub width: u8,
     /// Height of the LED matrix
@@ -19,6 +78,36 @@ pub struct IS31FL3743<I2C> {
     /// Method to convert an x,y coordinate pair to a binary address that can be accessed using the
     /// bus.
     pub calc_pixel: fn(x: u8, y: u8) -> u8,
+    /// PWM frequency applied by [setup](Self::setup), see
+    /// [configured_pwm_freq](Self::configured_pwm_freq)
+    pwm_freq: PwmFreq,
+}
+
+impl<I2C> IS31FL3743<I2C> {
+    /// A device at the default PWM frequency. Call [setup](Self::setup) before using it.
+    pub const fn new(
+        i2c: I2C,
+        address: Address,
+        width: u8,
+        height: u8,
+        calc_pixel: fn(x: u8, y: u8) -> u8,
+    ) -> Self {
+        IS31FL3743 {
+            i2c,
+            address,
+            width,
+            height,
+            calc_pixel,
+            pwm_freq: PwmFreq::P29k,
+        }
+    }
+
+    /// The PWM frequency set with [set_pwm_freq](Self::set_pwm_freq) or restored from a
+    /// [Snapshot]. It is kept by the driver, so [setup](Self::setup) can apply it again after
+    /// the chip lost power or was reset.
+    pub const fn configured_pwm_freq(&self) -> PwmFreq {
+        self.pwm_freq
+    }
 }
 
 impl<I2C, I2cError> IS31FL3743<I2C>
@@ -41,11 +130,74 @@ where
     pub fn read_matrix(&mut self) -> Result<[u8; 0xC6], I2cError> {
         let mut buf = [0x00; 0xC6];
         self.bank(Page::Pwm)?;
-        self.i2c.write(self.address, &[0x01])?;
-        self.i2c.read(self.address, &mut buf)?;
+        self.i2c.write(self.address.as_u8(), &[0x01])?;
+        self.i2c.read(self.address.as_u8(), &mut buf)?;
         Ok(buf)
     }
 
+    /// Write the brightness of a whole SW row in a single burst. `sw` is zero based (SW1 is 0)
+    /// and the slice holds one value per CS pin, CS1 first.
+    pub fn write_row(
+        &mut self,
+        sw: u8,
+        brightnesses: &[u8; CS_PINS as usize],
+    ) -> Result<(), Error<I2cError>> {
+        if sw >= SW_PINS {
+            return Err(Error::InvalidLocation(sw));
+        }
+        self.write_matrix_range(register(sw, 0), brightnesses)
+    }
+
+    /// Write the brightness of consecutive PWM registers in a single burst, starting at
+    /// `register` (0x01 to 0xC6).
+    pub fn write_matrix_range(
+        &mut self,
+        register: u8,
+        brightnesses: &[u8],
+    ) -> Result<(), Error<I2cError>> {
+        if !(0x01..=0xC6).contains(&register) {
+            return Err(Error::InvalidLocation(register));
+        }
+        if register as usize + brightnesses.len() > 0xC7 {
+            return Err(Error::InvalidFrame(brightnesses.len().min(0xFF) as u8));
+        }
+        self.write_pwm_range(register, brightnesses)?;
+        Ok(())
+    }
+
+    /// [write_matrix_range](Self::write_matrix_range) without the checks
+    pub(crate) fn write_pwm_range(
+        &mut self,
+        register: u8,
+        brightnesses: &[u8],
+    ) -> Result<(), I2cError> {
+        let mut buf = [0x00; 0xC7];
+        buf[0] = register;
+        buf[1..=brightnesses.len()].copy_from_slice(brightnesses);
+        self.bank(Page::Pwm)?;
+        self.write(&buf[..=brightnesses.len()])
+    }
+
+    /// Write the brightness of a CS column, starting at SW1. `cs` is zero based (CS1 is 0) and
+    /// the slice may hold fewer values than there are SW rows, the remaining rows are left
+    /// untouched.
+    ///
+    /// The registers of a column are not adjacent, so every value is written on its own. The
+    /// page is only selected once.
+    pub fn write_column(&mut self, cs: u8, brightnesses: &[u8]) -> Result<(), Error<I2cError>> {
+        if cs >= CS_PINS {
+            return Err(Error::InvalidLocation(cs));
+        }
+        if brightnesses.len() > SW_PINS as usize {
+            return Err(Error::InvalidFrame(brightnesses.len().min(0xFF) as u8));
+        }
+        self.bank(Page::Pwm)?;
+        for (sw, brightness) in brightnesses.iter().enumerate() {
+            self.write(&[register(sw as u8, cs), *brightness])?;
+        }
+        Ok(())
+    }
+
     /// Fill the display with a single brightness. The brightness should range from 0 to 255.
     pub fn fill(&mut self, brightness: u8) -> Result<(), I2cError> {
         self.bank(Page::Pwm)?;
@@ -63,13 +215,19 @@ where
     /// 1. The chip will be told that it's being "reset".
     /// 2. The chip will be put in shutdown mode
     /// 3. The chip will be configured to use the maximum voltage
-    /// 4. The chip will be taken out of shutdown mode
+    /// 4. The [configured PWM frequency](Self::configured_pwm_freq) will be applied again,
+    ///    unless it is the default
+    /// 5. The chip will be taken out of shutdown mode
     pub fn setup<DEL: DelayNs>(&mut self, delay: &mut DEL) -> Result<(), Error<I2cError>> {
         self.reset(delay)?;
         self.shutdown(true)?;
         delay.delay_ms(10);
         // maximum current limiting
-        self.write_register(Page::Config, addresses::CURRENT_REGISTER, 0xFF)?;
+        self.set_register(GlobalCurrent::new(0xFF))?;
+        // The reset went back to the default frequency
+        if self.pwm_freq != PwmFreq::P29k {
+            self.write_pwm_config(self.pwm_freq)?;
+        }
 
         self.shutdown(false)?;
         Ok(())
@@ -78,20 +236,100 @@ where
     /// the brightness should range from 0 to 255. If the coordinate is out of range then the
     /// function will return an error of [InvalidLocation](Error::InvalidLocation).
     pub fn pixel(&mut self, x: u8, y: u8, brightness: u8) -> Result<(), Error<I2cError>> {
-        if x > self.width {
+        if x >= self.width {
             return Err(Error::InvalidLocation(x));
         }
-        if y > self.height {
+        if y >= self.height {
             return Err(Error::InvalidLocation(y));
         }
         let pixel = (self.calc_pixel)(x, y);
+        if !(0x01..=0xC6).contains(&pixel) {
+            return Err(Error::InvalidLocation(pixel));
+        }
         self.write_register(Page::Pwm, pixel, brightness)?;
         Ok(())
     }
 
+    /// Check whether a controller answers at the configured address. Reads the configuration
+    /// register and checks that it holds a valid SW setting.
+    ///
+    /// Returns `Ok(false)` if nothing acknowledges the address, other bus errors are passed on.
+    pub fn probe(&mut self) -> Result<bool, I2cError>
+    where
+        I2cError: embedded_hal::i2c::Error,
+    {
+        match self.get_register::<Configuration>() {
+            Ok(config) => Ok(config.sw_setting().is_some()),
+            Err(err) if matches!(err.kind(), ErrorKind::NoAcknowledge(_)) => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Test a controller for production. Checks that it answers on the bus, writes a set of
+    /// patterns to the PWM and scaling pages and reads them back, runs open and short detection
+    /// and reads the thermal settings. All registers are restored afterwards, also when a bus
+    /// error ends the test early.
+    pub fn self_test<DEL: DelayNs>(
+        &mut self,
+        delay: &mut DEL,
+    ) -> Result<selftest::SelfTestReport, I2cError>
+    where
+        I2cError: embedded_hal::i2c::Error,
+    {
+        let mut report = selftest::SelfTestReport::missing();
+        if !self.probe()? {
+            return Ok(report);
+        }
+        report.present = true;
+        let snapshot = self.snapshot()?;
+        let result = self.run_self_test(delay, &mut report);
+        let restored = self.restore(&snapshot);
+        result?;
+        restored?;
+        Ok(report)
+    }
+
+    /// The part of [self_test](Self::self_test) that changes registers
+    fn run_self_test<DEL: DelayNs>(
+        &mut self,
+        delay: &mut DEL,
+        report: &mut selftest::SelfTestReport,
+    ) -> Result<(), I2cError> {
+        let mut walking = [0x00; 0xC6];
+        for (index, value) in walking.iter_mut().enumerate() {
+            *value = index as u8 + 1;
+        }
+        for pattern in [
+            [0x00; 0xC6],
+            [0xFF; 0xC6],
+            [0x55; 0xC6],
+            [0xAA; 0xC6],
+            walking,
+        ] {
+            // Keep the LEDs dark while the PWM page is tested
+            self.fill_scaling(&[0x00; 0xC6])?;
+            self.fill_matrix(&pattern)?;
+            let read = self.read_matrix()?;
+            report.pwm.check(&pattern, &read);
+
+            self.fill_matrix(&[0x00; 0xC6])?;
+            self.fill_scaling(&pattern)?;
+            let read = self.read_scaling()?;
+            report.scaling.check(&pattern, &read);
+        }
+
+        // Detection only sees LEDs that are driven
+        self.fill_scaling(&[0xFF; 0xC6])?;
+        self.fill(0xFF)?;
+        report.open = self.detect_open_short(delay, OpenShortDetect::Open)?;
+        report.short = self.detect_open_short(delay, OpenShortDetect::Short)?;
+        report.temperature = self.get_register()?;
+        Ok(())
+    }
+
     /// Change the slave address to a new 7-bit address. Should be configured before calling
     /// [setup](Self::setup) method.
-    pub fn set_address(&mut self, address: u8) {
+    pub fn set_address(&mut self, address: Address) {
         self.address = address;
     }
 
@@ -100,28 +338,101 @@ where
     /// MS to perform a reset).
     /// This will result in all registers being restored to their defaults.
     pub fn reset<DEL: DelayNs>(&mut self, delay: &mut DEL) -> Result<(), I2cError> {
-        self.write_register(Page::Config, addresses::RESET_REGISTER, addresses::RESET)?;
+        self.set_register(Reset::new())?;
         delay.delay_ms(10);
         Ok(())
     }
 
-    /// Reset the controller and restore all registers
-    pub fn reset_restore<DEL: DelayNs>(&mut self, delay: &mut DEL) -> Result<(), Error<I2cError>> {
-        // Back up registers
-        let prev_config = self.read_register(Page::Config, addresses::CONFIG_REGISTER)?;
-        // Assumes all scaling registers are set to the same value
-        let prev_scale = self.read_register(Page::Scale, 0x01)?;
-        let prev_brightness = self.read_matrix()?;
+    /// Save the complete state and put the display in shutdown. Keep the snapshot while the
+    /// controller is powered down and pass it to [resume](Self::resume) afterwards.
+    pub fn suspend(&mut self) -> Result<Snapshot, I2cError> {
+        let snapshot = self.snapshot()?;
+        self.shutdown(true)?;
+        Ok(snapshot)
+    }
+
+    /// Bring the controller back into the state saved by [suspend](Self::suspend). It is reset
+    /// first, so this works no matter whether it kept power or not.
+    pub fn resume<DEL: DelayNs>(
+        &mut self,
+        delay: &mut DEL,
+        snapshot: &Snapshot,
+    ) -> Result<(), I2cError> {
+        self.reset(delay)?;
+        self.restore(snapshot)?;
+        self.pwm_freq = snapshot.pwm_freq;
+        Ok(())
+    }
+
+    /// Read and decode the whole configuration of the chip, everything except the PWM and
+    /// scaling pages
+    pub fn read_config(&mut self) -> Result<registers::ChipConfig, I2cError> {
+        Ok(registers::ChipConfig::new(
+            self.get_register()?,
+            self.get_register()?,
+            self.get_register()?,
+            self.get_register()?,
+            self.get_register()?,
+            self.pwm_freq()?,
+        ))
+    }
 
-        self.setup(delay)?;
+    /// Read back every register that can be restored with [restore](Self::restore)
+    pub fn snapshot(&mut self) -> Result<Snapshot, I2cError> {
+        Ok(Snapshot {
+            pwm_freq: self.pwm_freq,
+            pwm: self.read_matrix()?,
+            scaling: self.read_scaling()?,
+            configuration: self.get_register()?,
+            global_current: self.get_register()?,
+            pull_resistors: self.get_register()?,
+            temperature: self.get_register()?,
+            spread_spectrum: self.get_register()?,
+        })
+    }
+
+    /// Write back all registers of a [snapshot](Self::snapshot). The display is kept in
+    /// shutdown until all other registers have been written, the configuration register is
+    /// written last.
+    ///
+    /// Going back to [PwmFreq::P29k] from another frequency needs a reset, use
+    /// [resume](Self::resume) for that.
+    pub fn restore(&mut self, snapshot: &Snapshot) -> Result<(), I2cError> {
+        self.shutdown(true)?;
+        if snapshot.pwm_freq != PwmFreq::P29k {
+            self.write_pwm_config(snapshot.pwm_freq)?;
+            self.pwm_freq = snapshot.pwm_freq;
+        }
+        self.set_register::<PullResistors>(snapshot.pull_resistors)?;
+        self.set_register::<Temperature>(snapshot.temperature)?;
+        self.set_register::<SpreadSpectrum>(snapshot.spread_spectrum)?;
+        self.set_register::<GlobalCurrent>(snapshot.global_current)?;
+        self.fill_scaling(&snapshot.scaling)?;
+        self.fill_matrix(&snapshot.pwm)?;
+        self.set_register::<Configuration>(snapshot.configuration)?;
+        Ok(())
+    }
 
-        // Restore registers
-        self.write_register(Page::Config, addresses::CONFIG_REGISTER, prev_config)?;
-        self.set_scaling(prev_scale)?;
-        self.fill_matrix(&prev_brightness)?;
+    /// Set the current available to each LED individually. 0 is none, 255 is the maximum
+    /// available. scales slice must have 0xC6 elements
+    pub fn fill_scaling(&mut self, scales: &[u8]) -> Result<(), I2cError> {
+        let mut buf = [0x00; 0xC7];
+        buf[0] = 0x01; // set the initial register address
+        buf[1..=0xC6].copy_from_slice(scales);
+        self.bank(Page::Scale)?;
+        self.write(&buf)?;
         Ok(())
     }
 
+    /// Read back the current scaling of each LED
+    pub fn read_scaling(&mut self) -> Result<[u8; 0xC6], I2cError> {
+        let mut buf = [0x00; 0xC6];
+        self.bank(Page::Scale)?;
+        self.i2c.write(self.address.as_u8(), &[0x01])?;
+        self.i2c.read(self.address.as_u8(), &mut buf)?;
+        Ok(buf)
+    }
+
     /// Set the current available to each LED. 0 is none, 255 is the maximum available
     pub fn set_scaling(&mut self, scale: u8) -> Result<(), I2cError> {
         self.bank(Page::Scale)?;
@@ -133,49 +444,162 @@ where
 
     /// Put the device into software shutdown mode
     pub fn shutdown(&mut self, yes: bool) -> Result<(), I2cError> {
-        self.write_register(
-            Page::Config,
-            addresses::CONFIG_REGISTER,
-            if yes { 0 } else { 1 },
-        )?;
-        Ok(())
+        self.update_register(|config: &mut Configuration| config.set_shutdown(yes))
     }
 
     /// How many SW rows to enable
     pub fn sw_enablement(&mut self, setting: SwSetting) -> Result<(), I2cError> {
-        let config_register = self.read_register(Page::Config, addresses::CONFIG_REGISTER)?;
+        self.update_register(|config: &mut Configuration| config.set_sw_setting(setting))
+    }
 
-        let new_val = (config_register & 0x0F) | (setting as u8) << 4;
-        self.write_register(Page::Config, addresses::CONFIG_REGISTER, new_val)?;
+    /// Read a register from the function page, see the [registers] module.
+    pub fn get_register<R: Register>(&mut self) -> Result<R, I2cError> {
+        let value = self.read_register(Page::Config, R::ADDRESS)?;
+        Ok(R::from_bits(value))
+    }
+
+    /// Write a register in the function page, see the [registers] module.
+    pub fn set_register<R: Register>(&mut self, register: R) -> Result<(), I2cError> {
+        self.write_register(Page::Config, R::ADDRESS, register.into_bits())
+    }
+
+    /// Read a register from the function page, modify it and write it back.
+    pub fn update_register<R: Register, F: FnOnce(&mut R)>(
+        &mut self,
+        f: F,
+    ) -> Result<(), I2cError> {
+        let mut register = self.get_register::<R>()?;
+        f(&mut register);
+        self.set_register(register)
+    }
+
+    /// Read the result of the last open/short detection
+    pub fn open_short(&mut self) -> Result<registers::OpenShort, I2cError> {
+        let mut buf = [0x00; registers::OpenShort::LEN];
+        self.bank(Page::Config)?;
+        self.i2c
+            .write(self.address.as_u8(), &[registers::OpenShort::ADDRESS])?;
+        self.i2c.read(self.address.as_u8(), &mut buf)?;
+        Ok(registers::OpenShort::from_bits(buf))
+    }
+
+    /// Run open or short detection and return its result. Takes two scan cycles at the current
+    /// [PWM frequency](Self::pwm_freq), between 1ms and 45ms.
+    ///
+    /// Only LEDs that are driven can be checked, so all PWM registers are set to 0xFF and the
+    /// global current is lowered to 0x01, as the data sheet asks for. The display flickers for
+    /// a moment. PWM, global current and the configuration register are restored afterwards.
+    /// LEDs with a scaling of 0 show up as open. [OpenShortDetect::Disabled] only reads the
+    /// last result.
+    pub fn detect_open_short<DEL: DelayNs>(
+        &mut self,
+        delay: &mut DEL,
+        mode: OpenShortDetect,
+    ) -> Result<registers::OpenShort, I2cError> {
+        if mode == OpenShortDetect::Disabled {
+            return self.open_short();
+        }
+        let configuration: Configuration = self.get_register()?;
+        let current: GlobalCurrent = self.get_register()?;
+        let pwm = self.read_matrix()?;
+        self.set_register(GlobalCurrent::new(0x01))?;
+        self.fill(0xFF)?;
+
+        // Detection starts when the OSDE bits are written, clear them first to start a new one
+        let mut detect = configuration;
+        detect.set_shutdown(false);
+        detect.set_open_short_detect(OpenShortDetect::Disabled);
+        self.set_register(detect)?;
+        detect.set_open_short_detect(mode);
+        self.set_register(detect)?;
+        delay.delay_us(2 * SW_PINS as u32 * self.pwm_freq.period_us());
+        let result = self.open_short()?;
+
+        self.set_register(configuration)?;
+        self.fill_matrix(&pwm)?;
+        self.set_register(current)?;
+        Ok(result)
+    }
+
+    /// Set the scaling of a single LED at a zero based SW row and CS column
+    pub fn set_scaling_at(&mut self, sw: u8, cs: u8, scale: u8) -> Result<(), Error<I2cError>> {
+        if sw >= SW_PINS {
+            return Err(Error::InvalidLocation(sw));
+        }
+        if cs >= CS_PINS {
+            return Err(Error::InvalidLocation(cs));
+        }
+        self.write_register(Page::Scale, register(sw, cs), scale)?;
         Ok(())
     }
 
-    /// Set the PWM frequency
+    /// Set the PWM frequency. The driver keeps it as
+    /// [configured_pwm_freq](Self::configured_pwm_freq), so [setup](Self::setup) applies it
+    /// again. Read the value on the chip back with [pwm_freq](Self::pwm_freq).
+    ///
+    /// The PWM configuration register (E2h) is only reachable in test mode. The sequence is:
+    /// 1. Software shutdown, so that no LED is driven while the frequency changes
+    /// 2. Enter test mode, E0h = 01h in the function page
+    /// 3. Write E2h in the function page and read it back
+    /// 4. Leave test mode, E0h = 00h. Also done when a write before failed.
+    /// 5. Restore the configuration register, including the shutdown state from before
+    ///
+    /// If the value read back doesn't match, [PwmFreqMismatch](Error::PwmFreqMismatch) is
+    /// returned. 29kHz can't be written, it's only set by a reset. Switching back to it resets
+    /// the controller and restores all other registers, see [resume](Self::resume).
     pub fn set_pwm_freq<DEL: DelayNs>(
         &mut self,
         delay: &mut DEL,
         pwm: PwmFreq,
     ) -> Result<(), Error<I2cError>> {
-        // The default frequency, can't set it. Reset the controller and restore the registers
         if let PwmFreq::P29k = pwm {
-            self.reset_restore(delay)?;
+            if self.pwm_freq()? != Some(PwmFreq::P29k) {
+                let mut snapshot = self.snapshot()?;
+                snapshot.pwm_freq = PwmFreq::P29k;
+                self.resume(delay, &snapshot)?;
+            }
+            self.pwm_freq = PwmFreq::P29k;
             return Ok(());
         }
 
-        // Enter test mode
-        self.write_register(Page::Config, addresses::TEST_MODE_REGISTER, 0x01)?;
-
-        // Set PWM
-        self.write(&[addresses::PWM_CONFIG_REGISTER, pwm as u8])?; // 488Hz
+        let configuration: Configuration = self.get_register()?;
+        self.shutdown(true)?;
+        let written = self.write_pwm_config(pwm);
+        self.set_register(configuration)?;
+        let written = written?;
+        if written != pwm as u8 {
+            return Err(Error::PwmFreqMismatch(written));
+        }
+        self.pwm_freq = pwm;
+        Ok(())
+    }
 
-        // Exit test mode
-        self.write_register(Page::Config, addresses::TEST_MODE_REGISTER, 0x00)?;
+    /// Read the PWM frequency on the chip back from test mode. Returns `None` for values that
+    /// don't match a [PwmFreq].
+    pub fn pwm_freq(&mut self) -> Result<Option<PwmFreq>, I2cError> {
+        self.write_register(Page::Config, addresses::TEST_MODE_REGISTER, 0x01)?;
+        let value = self.read_register(Page::Config, addresses::PWM_CONFIG_REGISTER);
+        let left = self.write_register(Page::Config, addresses::TEST_MODE_REGISTER, 0x00);
+        let value = value?;
+        left?;
+        Ok(PwmFreq::from_bits(value))
+    }
 
-        Ok(())
+    /// Write the PWM configuration in test mode and return the value read back. Test mode is
+    /// left even if the write fails.
+    fn write_pwm_config(&mut self, pwm: PwmFreq) -> Result<u8, I2cError> {
+        self.write_register(Page::Config, addresses::TEST_MODE_REGISTER, 0x01)?;
+        let written = self
+            .write_register(Page::Config, addresses::PWM_CONFIG_REGISTER, pwm as u8)
+            .and_then(|()| self.read_register(Page::Config, addresses::PWM_CONFIG_REGISTER));
+        let left = self.write_register(Page::Config, addresses::TEST_MODE_REGISTER, 0x00);
+        let written = written?;
+        left?;
+        Ok(written)
     }
 
     fn write(&mut self, buf: &[u8]) -> Result<(), I2cError> {
-        self.i2c.write(self.address, buf)
+        self.i2c.write(self.address.as_u8(), buf)
     }
 
     fn write_register(&mut self, bank: Page, register: u8, value: u8) -> Result<(), I2cError> {
@@ -186,8 +610,8 @@ where
 
     fn read_u8(&mut self, register: u8) -> Result<u8, I2cError> {
         let mut buf = [0x00];
-        self.i2c.write(self.address, &[register])?;
-        self.i2c.read(self.address, &mut buf)?;
+        self.i2c.write(self.address.as_u8(), &[register])?;
+        self.i2c.read(self.address.as_u8(), &mut buf)?;
         Ok(buf[0])
     }
 
@@ -205,7 +629,7 @@ where
 
     fn unlock(&mut self) -> Result<(), I2cError> {
         self.i2c.write(
-            self.address,
+            self.address.as_u8(),
             &[
                 addresses::CONFIG_LOCK_REGISTER,
                 addresses::CONFIG_WRITE_ENABLE,
@@ -214,6 +638,67 @@ where
     }
 }
 
+/// Probe all 16 addresses selectable with the ADDR1 and ADDR2 pins and return the ones a
+/// controller answers on. See [probe](IS31FL3743::probe).
+pub fn scan<I2C: I2c>(i2c: &mut I2C) -> Result<ScanResult, I2C::Error> {
+    let mut found = ScanResult(0);
+    for index in 0..16 {
+        let mut device = IS31FL3743::new(
+            &mut *i2c,
+            ScanResult::address(index),
+            0,
+            0,
+            |_x: u8, _y: u8| -> u8 { 0 },
+        );
+        if device.probe()? {
+            found.0 |= 1 << index;
+        }
+    }
+    Ok(found)
+}
+
+/// The addresses a controller was found on by [scan]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ScanResult(u16);
+
+impl ScanResult {
+    fn address(index: u8) -> Address {
+        Address::new(AddrPin::from_bits(index), AddrPin::from_bits(index >> 2))
+    }
+
+    /// Whether a controller answered on the address
+    pub fn contains(&self, address: Address) -> bool {
+        self.iter().any(|found| found == address)
+    }
+
+    /// Number of controllers found
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    /// The addresses of all controllers found, in ascending order
+    pub fn iter(&self) -> impl Iterator<Item = Address> {
+        let found = self.0;
+        (0..16)
+            .filter(move |index| found & (1 << index) != 0)
+            .map(Self::address)
+    }
+}
+
+/// Number of CS (current sink) pins, i.e. columns of the LED matrix
+pub const CS_PINS: u8 = 18;
+/// Number of SW (switch) pins, i.e. rows of the LED matrix
+pub const SW_PINS: u8 = 11;
+
+/// Calculate the PWM or scaling register of the LED at a zero based SW row and CS column.
+pub const fn register(sw: u8, cs: u8) -> u8 {
+    sw * CS_PINS + cs + 1
+}
+
 /// See the [data sheet](https://lumissil.com/assets/pdf/core/IS31FL3743A_DS.pdf)
 /// for more information on registers.
 pub mod addresses {
@@ -221,6 +706,9 @@ pub mod addresses {
     pub const CONFIG_REGISTER: u8 = 0x00;
     pub const CURRENT_REGISTER: u8 = 0x01;
     pub const PULL_UP_REGISTER: u8 = 0x02;
+    pub const OPEN_SHORT_REGISTER: u8 = 0x03;
+    pub const TEMPERATURE_REGISTER: u8 = 0x24;
+    pub const SPREAD_SPECTRUM_REGISTER: u8 = 0x25;
     pub const RESET_REGISTER: u8 = 0x2F;
 
     pub const PAGE_SELECT_REGISTER: u8 = 0xFD;
@@ -238,6 +726,12 @@ pub enum Error<I2cError> {
     I2cError(I2cError),
     InvalidLocation(u8),
     InvalidFrame(u8),
+    /// Writing would exceed the current budget, holds the estimated current in µA
+    PowerLimit(u32),
+    /// Setting a GPIO, like the SDB pin, failed
+    Pin,
+    /// The PWM configuration read back a different value
+    PwmFreqMismatch(u8),
 }
 
 impl<E> From<E> for Error<E> {
@@ -253,6 +747,7 @@ enum Page {
     Config = 0x02,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum PwmFreq {
     /// 29kHz, the default. To set this, it'll reset the controller
@@ -273,6 +768,38 @@ pub enum PwmFreq {
     P488 = 0xC0,
 }
 
+impl PwmFreq {
+    /// Length of a PWM period in µs, rounded up
+    pub const fn period_us(self) -> u32 {
+        match self {
+            Self::P29k => 35,
+            Self::P31k25 => 32,
+            Self::P15k6 => 65,
+            Self::P7k8 => 129,
+            Self::P3k9 => 257,
+            Self::P1k95 => 513,
+            Self::P977 => 1024,
+            Self::P488 => 2050,
+        }
+    }
+
+    /// Decode the value of the PWM configuration register
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        Some(match bits {
+            0xFF => Self::P29k,
+            0xE0 => Self::P31k25,
+            0x20 => Self::P15k6,
+            0x40 => Self::P7k8,
+            0x60 => Self::P3k9,
+            0x80 => Self::P1k95,
+            0xA0 => Self::P977,
+            0xC0 => Self::P488,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 #[repr(u8)]
 pub enum SwSetting {
     // SW1-SW11 active
@@ -298,3 +825,159 @@ pub enum SwSetting {
     // All CSx pins only act as current sink, no scanning
     NoScan = 0b1010,
 }
+
+impl SwSetting {
+    /// Decode the SWS field of the [configuration register](registers::Configuration)
+    pub const fn from_bits(bits: u8) -> Option<Self> {
+        Some(match bits & 0x0F {
+            0b0000 => Self::Sw1Sw11,
+            0b0001 => Self::Sw1Sw10,
+            0b0010 => Self::Sw1Sw9,
+            0b0011 => Self::Sw1Sw8,
+            0b0100 => Self::Sw1Sw7,
+            0b0101 => Self::Sw1Sw6,
+            0b0110 => Self::Sw1Sw5,
+            0b0111 => Self::Sw1Sw4,
+            0b1000 => Self::Sw1Sw3,
+            0b1001 => Self::Sw1Sw2,
+            0b1010 => Self::NoScan,
+            _ => return None,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{device, Bus, NoDelay};
+    use std::vec::Vec;
+
+    #[test]
+    fn write_row() {
+        let mut device = device();
+        let row: [u8; CS_PINS as usize] = core::array::from_fn(|cs| cs as u8 + 1);
+        device.write_row(2, &row).unwrap();
+        let expected: Vec<_> = (0..CS_PINS)
+            .map(|cs| (0, register(2, cs), cs + 1))
+            .collect();
+        assert_eq!(device.i2c.writes, expected);
+
+        assert!(matches!(
+            device.write_row(SW_PINS, &row),
+            Err(Error::InvalidLocation(11))
+        ));
+        assert_eq!(device.i2c.writes.len(), expected.len());
+    }
+
+    #[test]
+    fn write_matrix_range() {
+        let mut device = device();
+        device.write_matrix_range(0xC4, &[1, 2, 3]).unwrap();
+        assert_eq!(
+            device.i2c.writes,
+            [(0, 0xC4, 1), (0, 0xC5, 2), (0, 0xC6, 3)]
+        );
+        device.write_matrix_range(0x01, &[0x42; 0xC6]).unwrap();
+        assert!(device.i2c.pwm().iter().all(|&pwm| pwm == 0x42));
+
+        device.i2c.writes.clear();
+        assert!(matches!(
+            device.write_matrix_range(0x00, &[1]),
+            Err(Error::InvalidLocation(0x00))
+        ));
+        assert!(matches!(
+            device.write_matrix_range(0xC7, &[1]),
+            Err(Error::InvalidLocation(0xC7))
+        ));
+        assert!(matches!(
+            device.write_matrix_range(0xC6, &[1, 2]),
+            Err(Error::InvalidFrame(2))
+        ));
+        assert!(matches!(
+            device.write_matrix_range(0x01, &[1; 0x200]),
+            Err(Error::InvalidFrame(0xFF))
+        ));
+        assert!(device.i2c.writes.is_empty());
+    }
+
+    #[test]
+    fn write_column() {
+        let mut device = device();
+        device.write_column(17, &[1, 2, 3]).unwrap();
+        assert_eq!(
+            device.i2c.writes,
+            [
+                (0, register(0, 17), 1),
+                (0, register(1, 17), 2),
+                (0, register(2, 17), 3)
+            ]
+        );
+        device.write_column(0, &[0x42; SW_PINS as usize]).unwrap();
+        assert!((0..SW_PINS).all(|sw| device.i2c.pwm()[register(sw, 0) as usize - 1] == 0x42));
+
+        device.i2c.writes.clear();
+        assert!(matches!(
+            device.write_column(CS_PINS, &[1]),
+            Err(Error::InvalidLocation(18))
+        ));
+        assert!(matches!(
+            device.write_column(0, &[1; SW_PINS as usize + 1]),
+            Err(Error::InvalidFrame(12))
+        ));
+        assert!(matches!(
+            device.write_column(0, &[1; 0x200]),
+            Err(Error::InvalidFrame(0xFF))
+        ));
+        assert!(device.i2c.writes.is_empty());
+    }
+
+    #[test]
+    fn setup_applies_the_configured_pwm_freq() {
+        let mut device = device();
+        device.set_pwm_freq(&mut NoDelay, PwmFreq::P977).unwrap();
+        assert_eq!(device.configured_pwm_freq(), PwmFreq::P977);
+        // Power loss
+        device.i2c = Bus::new(0x20);
+        assert_eq!(device.pwm_freq().unwrap(), Some(PwmFreq::P29k));
+        device.setup(&mut NoDelay).unwrap();
+        assert_eq!(device.pwm_freq().unwrap(), Some(PwmFreq::P977));
+
+        device.set_pwm_freq(&mut NoDelay, PwmFreq::P29k).unwrap();
+        assert_eq!(device.configured_pwm_freq(), PwmFreq::P29k);
+        device.setup(&mut NoDelay).unwrap();
+        assert_eq!(device.pwm_freq().unwrap(), Some(PwmFreq::P29k));
+    }
+
+    #[test]
+    fn test_mode_is_left_after_errors() {
+        let mut device = device();
+        device.setup(&mut NoDelay).unwrap();
+        let test_mode =
+            |device: &IS31FL3743<Bus>| device.i2c.function(addresses::TEST_MODE_REGISTER);
+
+        // Fails right after entering test mode, behind the shutdown
+        device.i2c.writes.clear();
+        device.i2c.fail_at = Some(2);
+        assert!(matches!(
+            device.set_pwm_freq(&mut NoDelay, PwmFreq::P977),
+            Err(Error::I2cError(ErrorKind::Bus))
+        ));
+        assert_eq!(
+            device.i2c.writes[1],
+            (2, addresses::TEST_MODE_REGISTER, 0x01)
+        );
+        assert_eq!(test_mode(&device), 0x00);
+        assert_eq!(device.configured_pwm_freq(), PwmFreq::P29k);
+        let configuration: Configuration = device.get_register().unwrap();
+        assert!(!configuration.shutdown());
+
+        device.i2c.writes.clear();
+        device.i2c.fail_at = Some(1);
+        assert_eq!(device.pwm_freq(), Err(ErrorKind::Bus));
+        assert_eq!(
+            device.i2c.writes[0],
+            (2, addresses::TEST_MODE_REGISTER, 0x01)
+        );
+        assert_eq!(test_mode(&device), 0x00);
+    }
+}
@@ -0,0 +1,187 @@
+//! A fake controller on a fake I2C bus, for tests
+#![allow(dead_code)]
+use crate::registers::{OpenShort, OpenShortDetect};
+use crate::{addresses, Address, PwmFreq, IS31FL3743};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+
+/// Registers of one controller, answering on a single address
+pub struct Bus {
+    pub address: u8,
+    /// PWM, scaling and function page
+    pub pages: [[u8; 256]; 3],
+    /// LEDs that are reported by open detection. LEDs that aren't driven always are.
+    pub open: OpenShort,
+    /// LEDs that are reported by short detection while they are driven
+    pub short: OpenShort,
+    /// Every register written, as page, register and value
+    pub writes: std::vec::Vec<(usize, u8, u8)>,
+    /// Fail the first transaction once this many registers were written
+    pub fail_at: Option<usize>,
+    page: usize,
+    pointer: u8,
+}
+
+impl Bus {
+    /// A controller right after power up
+    pub fn new(address: u8) -> Self {
+        let mut bus = Bus {
+            address,
+            pages: [[0x00; 256]; 3],
+            open: OpenShort::from_bits([0x00; OpenShort::LEN]),
+            short: OpenShort::from_bits([0x00; OpenShort::LEN]),
+            writes: std::vec::Vec::new(),
+            fail_at: None,
+            page: 0,
+            pointer: 0,
+        };
+        bus.reset();
+        bus
+    }
+
+    pub fn pwm(&self) -> &[u8] {
+        &self.pages[0][0x01..=0xC6]
+    }
+
+    pub fn scaling(&self) -> &[u8] {
+        &self.pages[1][0x01..=0xC6]
+    }
+
+    pub fn function(&self, register: u8) -> u8 {
+        self.pages[2][register as usize]
+    }
+
+    fn reset(&mut self) {
+        self.pages = [[0x00; 256]; 3];
+        self.pages[2][addresses::PWM_CONFIG_REGISTER as usize] = PwmFreq::P29k as u8;
+    }
+
+    fn store(&mut self, register: u8, value: u8) {
+        self.writes.push((self.page, register, value));
+        if self.page == 2 {
+            match register {
+                addresses::RESET_REGISTER if value == addresses::RESET => return self.reset(),
+                addresses::PWM_CONFIG_REGISTER
+                    if self.function(addresses::TEST_MODE_REGISTER) != 0x01 =>
+                {
+                    return
+                }
+                addresses::CONFIG_REGISTER => self.detect(value),
+                _ => {}
+            }
+        }
+        self.pages[self.page][register as usize] = value;
+    }
+
+    /// Detection only sees LEDs that are driven during the scan, the others look open
+    fn detect(&mut self, configuration: u8) {
+        let previous = self.function(addresses::CONFIG_REGISTER) >> 1 & 0b11;
+        let (faults, open) = match configuration >> 1 & 0b11 {
+            mode if mode == previous => return,
+            mode if mode == OpenShortDetect::Open as u8 => (self.open, true),
+            mode if mode == OpenShortDetect::Short as u8 => (self.short, false),
+            _ => return,
+        };
+        let mut result = OpenShort::from_bits([0x00; OpenShort::LEN]);
+        for sw in 0..crate::SW_PINS {
+            for cs in 0..crate::CS_PINS {
+                let register = crate::register(sw, cs) as usize;
+                let driven = self.function(addresses::CURRENT_REGISTER) != 0
+                    && self.pages[0][register] != 0
+                    && self.pages[1][register] != 0;
+                let faulty = if driven {
+                    faults.is_faulty(sw, cs)
+                } else {
+                    open
+                };
+                result.set_faulty(sw, cs, faulty);
+            }
+        }
+        let start = OpenShort::ADDRESS as usize;
+        self.pages[2][start..start + OpenShort::LEN].copy_from_slice(&result.into_bits());
+    }
+}
+
+impl ErrorType for Bus {
+    type Error = ErrorKind;
+}
+
+impl I2c for Bus {
+    fn transaction(
+        &mut self,
+        address: u8,
+        operations: &mut [Operation<'_>],
+    ) -> Result<(), ErrorKind> {
+        if address != self.address {
+            return Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address));
+        }
+        if self
+            .fail_at
+            .is_some_and(|fail_at| self.writes.len() >= fail_at)
+        {
+            self.fail_at = None;
+            return Err(ErrorKind::Bus);
+        }
+        for operation in operations {
+            match operation {
+                Operation::Write(&[addresses::CONFIG_LOCK_REGISTER, _]) => {}
+                Operation::Write(&[addresses::PAGE_SELECT_REGISTER, page]) => {
+                    self.page = page as usize;
+                }
+                Operation::Write(bytes) => {
+                    self.pointer = bytes[0];
+                    for (offset, value) in bytes[1..].iter().enumerate() {
+                        self.store(bytes[0].wrapping_add(offset as u8), *value);
+                    }
+                }
+                Operation::Read(buf) => {
+                    for (offset, value) in buf.iter_mut().enumerate() {
+                        *value =
+                            self.pages[self.page][self.pointer.wrapping_add(offset as u8) as usize];
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A delay that returns immediately
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// An output pin that remembers its level
+pub struct Pin {
+    pub high: bool,
+}
+
+impl embedded_hal::digital::ErrorType for Pin {
+    type Error = core::convert::Infallible;
+}
+
+impl OutputPin for Pin {
+    fn set_low(&mut self) -> Result<(), Self::Error> {
+        self.high = false;
+        Ok(())
+    }
+
+    fn set_high(&mut self) -> Result<(), Self::Error> {
+        self.high = true;
+        Ok(())
+    }
+}
+
+/// An 18×11 display on a fresh [Bus], with one register per pixel
+pub fn device() -> IS31FL3743<Bus> {
+    IS31FL3743::new(
+        Bus::new(0x20),
+        Address::MIN,
+        crate::CS_PINS,
+        crate::SW_PINS,
+        |x, y| crate::register(y, x),
+    )
+}
@@ -0,0 +1,514 @@
+use crate::framebuffer::{FrameBuffer, Output};
+use crate::registers::GlobalCurrent;
+use crate::{Error, IS31FL3743, SW_PINS};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::i2c::I2c;
+
+/// Fractional bits of PWM, scaling and global current together, each of them is out of 256
+const FULL_SCALE_SHIFT: u32 = 24;
+
+/// Electrical limits the average current is estimated from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PowerModel {
+    /// Current of a CS pin at full global current, scaling and PWM, set by R_EXT
+    pub max_cs_current_ua: u32,
+    /// Maximum forward current the LEDs are rated for. A CS pin never drives more, even if
+    /// R_EXT would allow it.
+    pub max_led_current_ua: u32,
+    /// Number of SW rows being scanned. Every LED is only on for this fraction of the time.
+    pub active_sw: u8,
+    /// Current of the controller itself, with all LEDs off
+    pub quiescent_ua: u32,
+    /// Maximum average current drawn from the supply
+    pub limit_ma: u32,
+}
+
+impl PowerModel {
+    /// Model of a controller with all SW rows active and the given R_EXT, limited to
+    /// `limit_ma`. The LEDs aren't limited and the quiescent current is ignored until set.
+    pub const fn new(rext_ohm: u32, limit_ma: u32) -> Self {
+        PowerModel {
+            max_cs_current_ua: Self::cs_current_ua(rext_ohm),
+            max_led_current_ua: u32::MAX,
+            active_sw: SW_PINS,
+            quiescent_ua: 0,
+            limit_ma,
+        }
+    }
+
+    /// Maximum current of a CS pin for a R_EXT resistor, I_OUT = 343mV / R_EXT from the data
+    /// sheet
+    pub const fn cs_current_ua(rext_ohm: u32) -> u32 {
+        if rext_ohm == 0 {
+            return u32::MAX;
+        }
+        343_000_000 / rext_ohm
+    }
+
+    /// Peak current of a single LED at full global current, scaling and PWM
+    fn led_current_ua(&self) -> u64 {
+        self.max_cs_current_ua.min(self.max_led_current_ua) as u64
+    }
+
+    /// Divider from the sum of `current * gcc * scaling * pwm` to an average in µA
+    fn divider(&self) -> u64 {
+        (self.active_sw.max(1) as u64) << FULL_SCALE_SHIFT
+    }
+
+    /// Budget for the LEDs, in the same units as the sum of `current * gcc * scaling * pwm`
+    fn raw_budget(&self) -> u64 {
+        let limit_ua = self.limit_ma.saturating_mul(1000);
+        limit_ua.saturating_sub(self.quiescent_ua) as u64 * self.divider()
+    }
+
+    /// Unscaled current of all LEDs
+    fn raw(&self, pwm: &[u8; 0xC6], scaling: &[u8; 0xC6], global_current: u8) -> u64 {
+        let sum: u64 = pwm
+            .iter()
+            .zip(scaling.iter())
+            .map(|(&pwm, &scaling)| pwm as u64 * scaling as u64)
+            .sum();
+        sum * global_current as u64 * self.led_current_ua()
+    }
+
+    /// Estimate the average current drawn with the given registers, in µA
+    pub fn estimate_ua(&self, pwm: &[u8; 0xC6], scaling: &[u8; 0xC6], global_current: u8) -> u32 {
+        self.average_ua(self.raw(pwm, scaling, global_current))
+    }
+
+    /// Average current in µA, including the controller itself
+    fn average_ua(&self, raw: u64) -> u32 {
+        let leds = (raw / self.divider()).min(u32::MAX as u64) as u32;
+        self.quiescent_ua.saturating_add(leds)
+    }
+}
+
+/// What happens to a write that would exceed the budget
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Policy {
+    /// Fail with [Error::PowerLimit] and leave the device untouched
+    Refuse,
+    /// Scale the written values down until they fit
+    Scale,
+}
+
+/// Keeps the estimated current of a device under the limit of a [PowerModel].
+///
+/// Keeps a copy of the PWM and scaling registers and the global current, so every write must go
+/// through this wrapper. Writes straight to [device](Self::device) have to be followed by
+/// [sync](Self::sync).
+///
+/// It implements [Output], so frame buffers, effects, animations, fades and the protocol zones
+/// can write through it instead of the device, e.g. `engine.tick(&mut limited, now_ms)`.
+pub struct PowerLimited<I2C> {
+    pub device: IS31FL3743<I2C>,
+    pub model: PowerModel,
+    pub policy: Policy,
+    pwm: [u8; 0xC6],
+    scaling: [u8; 0xC6],
+    global_current: u8,
+}
+
+impl<I2C, I2cError> PowerLimited<I2C>
+where
+    I2C: I2c<Error = I2cError>,
+{
+    /// Wrap a device, reading its current state
+    pub fn new(
+        device: IS31FL3743<I2C>,
+        model: PowerModel,
+        policy: Policy,
+    ) -> Result<Self, I2cError> {
+        let mut limited = PowerLimited {
+            device,
+            model,
+            policy,
+            pwm: [0x00; 0xC6],
+            scaling: [0x00; 0xC6],
+            global_current: 0x00,
+        };
+        limited.sync()?;
+        Ok(limited)
+    }
+
+    /// Read back the state of the device after it was written to without this wrapper
+    pub fn sync(&mut self) -> Result<(), I2cError> {
+        self.pwm = self.device.read_matrix()?;
+        self.scaling = self.device.read_scaling()?;
+        self.global_current = self.device.get_register::<GlobalCurrent>()?.current();
+        Ok(())
+    }
+
+    /// Estimated current right now, in µA
+    pub fn estimate_ua(&self) -> u32 {
+        self.model
+            .estimate_ua(&self.pwm, &self.scaling, self.global_current)
+    }
+
+    /// See [IS31FL3743::setup]. The PWM registers are cleared by the reset, so the full global
+    /// current doesn't draw anything yet.
+    pub fn setup<DEL: DelayNs>(&mut self, delay: &mut DEL) -> Result<(), Error<I2cError>> {
+        self.device.setup(delay)?;
+        self.sync()?;
+        Ok(())
+    }
+
+    /// See [IS31FL3743::fill_matrix]. With [Policy::Scale] all values are scaled down by the
+    /// same factor.
+    pub fn fill_matrix(&mut self, brightnesses: &[u8]) -> Result<(), Error<I2cError>> {
+        let Ok(frame) = <[u8; 0xC6]>::try_from(brightnesses) else {
+            return Err(Error::InvalidFrame(brightnesses.len().min(0xFF) as u8));
+        };
+        let frame = self.fit_frame(frame, &self.scaling, self.global_current)?;
+        self.device.fill_matrix(&frame)?;
+        self.pwm = frame;
+        Ok(())
+    }
+
+    /// Write a [FrameBuffer], see [fill_matrix](Self::fill_matrix)
+    pub fn flush(&mut self, frame: &FrameBuffer) -> Result<(), Error<I2cError>> {
+        self.fill_matrix(&frame.pwm)
+    }
+
+    /// See [IS31FL3743::fill]. With [Policy::Scale] the brightness is lowered until it fits.
+    pub fn fill(&mut self, brightness: u8) -> Result<(), Error<I2cError>> {
+        let frame = self.fit_frame([brightness; 0xC6], &self.scaling, self.global_current)?;
+        self.device.fill(frame[0])?;
+        self.pwm = frame;
+        Ok(())
+    }
+
+    /// See [IS31FL3743::pixel]. With [Policy::Scale] only this LED is dimmed, the others
+    /// stay as they are.
+    pub fn pixel(&mut self, x: u8, y: u8, brightness: u8) -> Result<(), Error<I2cError>> {
+        if x >= self.device.width {
+            return Err(Error::InvalidLocation(x));
+        }
+        if y >= self.device.height {
+            return Err(Error::InvalidLocation(y));
+        }
+        let register = (self.device.calc_pixel)(x, y);
+        let Some(index) = (register as usize)
+            .checked_sub(1)
+            .filter(|&index| index < 0xC6)
+        else {
+            return Err(Error::InvalidLocation(register));
+        };
+        let mut pwm = self.pwm;
+        pwm[index] = 0;
+        let others = self.model.raw(&pwm, &self.scaling, self.global_current);
+        let per_step = self
+            .model
+            .raw(&single(index, 1), &self.scaling, self.global_current);
+        let brightness = self.fit(brightness, others, per_step)?;
+        self.device.pixel(x, y, brightness)?;
+        self.pwm[index] = brightness;
+        Ok(())
+    }
+
+    /// Set the [GlobalCurrent] of the device. With [Policy::Scale] it is lowered until it fits.
+    pub fn set_global_current(&mut self, current: u8) -> Result<(), Error<I2cError>> {
+        let per_step = self.model.raw(&self.pwm, &self.scaling, 1);
+        let current = self.fit(current, 0, per_step)?;
+        self.device.set_register(GlobalCurrent::new(current))?;
+        self.global_current = current;
+        Ok(())
+    }
+
+    /// See [IS31FL3743::set_scaling]. With [Policy::Scale] it is lowered until it fits.
+    pub fn set_scaling(&mut self, scale: u8) -> Result<(), Error<I2cError>> {
+        let per_step = self.model.raw(&self.pwm, &[1; 0xC6], self.global_current);
+        let scale = self.fit(scale, 0, per_step)?;
+        self.device.set_scaling(scale)?;
+        self.scaling = [scale; 0xC6];
+        Ok(())
+    }
+
+    /// See [IS31FL3743::fill_scaling]. With [Policy::Scale] all values are scaled down by the
+    /// same factor.
+    pub fn fill_scaling(&mut self, scales: &[u8]) -> Result<(), Error<I2cError>> {
+        let Ok(scaling) = <[u8; 0xC6]>::try_from(scales) else {
+            return Err(Error::InvalidFrame(scales.len().min(0xFF) as u8));
+        };
+        // Current is symmetric in PWM and scaling, so the frame fitting works for both
+        let scaling = self.fit_frame(scaling, &self.pwm, self.global_current)?;
+        self.device.fill_scaling(&scaling)?;
+        self.scaling = scaling;
+        Ok(())
+    }
+
+    /// Check a frame against the budget and scale all of its values by the same factor if
+    /// needed. `other` is the page the frame is multiplied with.
+    fn fit_frame(
+        &self,
+        mut frame: [u8; 0xC6],
+        other: &[u8; 0xC6],
+        global_current: u8,
+    ) -> Result<[u8; 0xC6], Error<I2cError>> {
+        let raw = self.model.raw(&frame, other, global_current);
+        let budget = self.model.raw_budget();
+        if raw <= budget {
+            return Ok(frame);
+        }
+        if self.policy == Policy::Refuse {
+            return Err(Error::PowerLimit(self.model.average_ua(raw)));
+        }
+        for value in frame.iter_mut() {
+            *value = (*value as u64 * budget / raw) as u8;
+        }
+        Ok(frame)
+    }
+
+    /// Check a single value that the current grows linearly with. `fixed` is the part of the
+    /// current that doesn't depend on it, `per_step` what every step of the value adds.
+    fn fit(&self, value: u8, fixed: u64, per_step: u64) -> Result<u8, Error<I2cError>> {
+        let budget = self.model.raw_budget();
+        let raw = fixed + per_step * value as u64;
+        if raw <= budget {
+            return Ok(value);
+        }
+        if self.policy == Policy::Refuse {
+            return Err(Error::PowerLimit(self.model.average_ua(raw)));
+        }
+        // per_step can't be 0 here, the budget would be exceeded without the value
+        let max = budget.saturating_sub(fixed) / per_step.max(1);
+        Ok(max.min(value as u64) as u8)
+    }
+}
+
+impl<I2C, I2cError> Output for PowerLimited<I2C>
+where
+    I2C: I2c<Error = I2cError>,
+{
+    type Bus = I2C;
+    type Error = Error<I2cError>;
+
+    fn device(&self) -> &IS31FL3743<I2C> {
+        &self.device
+    }
+
+    fn write_frame(&mut self, pwm: &[u8; 0xC6]) -> Result<(), Self::Error> {
+        self.fill_matrix(pwm)
+    }
+
+    /// With [Policy::Scale] the whole frame is written if it had to be scaled down
+    fn write_range(&mut self, start: usize, pwm: &[u8]) -> Result<(), Self::Error> {
+        let mut frame = self.pwm;
+        frame[start..start + pwm.len()].copy_from_slice(pwm);
+        let fitted = self.fit_frame(frame, &self.scaling, self.global_current)?;
+        if fitted == frame {
+            self.device.write_range(start, pwm)?;
+        } else {
+            self.device.fill_matrix(&fitted)?;
+        }
+        self.pwm = fitted;
+        Ok(())
+    }
+
+    fn write_global_current(&mut self, current: u8) -> Result<(), Self::Error> {
+        self.set_global_current(current)
+    }
+
+    fn write_scaling(&mut self, scale: u8) -> Result<(), Self::Error> {
+        self.set_scaling(scale)
+    }
+}
+
+/// A frame with a single register set
+fn single(index: usize, value: u8) -> [u8; 0xC6] {
+    let mut frame = [0x00; 0xC6];
+    frame[index] = value;
+    frame
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::ColorOrder;
+    use crate::effects::{Effect, Engine};
+    use crate::fade::Fade;
+    use crate::mock::{device, Bus, NoDelay};
+    use crate::{addresses, register};
+
+    /// 25.6mA per LED, one SW row, 1A budget
+    const MODEL: PowerModel = PowerModel {
+        max_cs_current_ua: 25_600,
+        max_led_current_ua: u32::MAX,
+        active_sw: 1,
+        quiescent_ua: 0,
+        limit_ma: 1000,
+    };
+
+    fn limited(model: PowerModel, policy: Policy) -> PowerLimited<Bus> {
+        let device = device();
+        let mut limited = PowerLimited::new(device, model, policy).unwrap();
+        limited.setup(&mut NoDelay).unwrap();
+        limited.set_scaling(0xFF).unwrap();
+        limited
+    }
+
+    #[test]
+    fn cs_current() {
+        assert_eq!(PowerModel::cs_current_ua(343), 1_000_000);
+        assert_eq!(PowerModel::cs_current_ua(3_430), 100_000);
+        assert_eq!(PowerModel::cs_current_ua(0), u32::MAX);
+        let model = PowerModel::new(3_430, 500);
+        assert_eq!(model.max_cs_current_ua, 100_000);
+        assert_eq!(model.active_sw, SW_PINS);
+    }
+
+    #[test]
+    fn estimate() {
+        let half = single(0, 0x80);
+        // 0.5 * 0.5 * 0.5 of 25.6mA
+        assert_eq!(MODEL.estimate_ua(&half, &half, 0x80), 3200);
+        let model = PowerModel {
+            active_sw: 2,
+            quiescent_ua: 500,
+            ..MODEL
+        };
+        assert_eq!(model.estimate_ua(&half, &half, 0x80), 500 + 1600);
+        let model = PowerModel {
+            max_led_current_ua: 12_800,
+            ..MODEL
+        };
+        assert_eq!(model.estimate_ua(&half, &half, 0x80), 1600);
+        assert_eq!(MODEL.estimate_ua(&[0x00; 0xC6], &[0xFF; 0xC6], 0xFF), 0);
+        // Every LED at full brightness, just under 25.6mA each
+        let full = MODEL.estimate_ua(&[0xFF; 0xC6], &[0xFF; 0xC6], 0xFF);
+        assert_eq!(full, ((0xC6 * 25_600u64 * 255 * 255 * 255) >> 24) as u32);
+    }
+
+    #[test]
+    fn refuse() {
+        let mut limited = limited(MODEL, Policy::Refuse);
+        // 39 LEDs at 25.6mA fit into 1A, 40 don't
+        let mut frame = [0x00; 0xC6];
+        frame[..39].fill(0xFF);
+        limited.fill_matrix(&frame).unwrap();
+        frame[39] = 0xFF;
+        assert!(matches!(
+            limited.fill_matrix(&frame),
+            Err(Error::PowerLimit(ua)) if ua > 1_000_000
+        ));
+        assert!(matches!(limited.fill(0xFF), Err(Error::PowerLimit(_))));
+        assert!(matches!(
+            limited.pixel(3, 5, 0xFF),
+            Err(Error::PowerLimit(_))
+        ));
+        assert_eq!(
+            limited.device.i2c.pwm()[..40]
+                .iter()
+                .filter(|&&pwm| pwm != 0)
+                .count(),
+            39
+        );
+        assert!(limited.estimate_ua() <= 1_000_000);
+    }
+
+    #[test]
+    fn scale() {
+        let mut limited = limited(MODEL, Policy::Scale);
+        limited.fill(0xFF).unwrap();
+        assert!(limited.estimate_ua() <= 1_000_000);
+        let pwm = limited.device.i2c.pwm()[0];
+        assert!(pwm > 0 && pwm < 0xFF);
+        assert!(limited.device.i2c.pwm().iter().all(|&value| value == pwm));
+
+        // Raising the global current afterwards is limited as well
+        limited.set_global_current(0x10).unwrap();
+        limited.set_global_current(0xFF).unwrap();
+        assert_eq!(
+            limited.device.i2c.function(addresses::CURRENT_REGISTER),
+            0xFF
+        );
+        limited.fill(0x00).unwrap();
+        limited.pixel(0, 0, 0xFF).unwrap();
+        assert_eq!(limited.device.i2c.pwm()[0], 0xFF);
+    }
+
+    #[test]
+    fn sync_after_direct_writes() {
+        let mut limited = limited(MODEL, Policy::Refuse);
+        limited.device.fill(0xFF).unwrap();
+        assert_eq!(limited.estimate_ua(), 0);
+        limited.sync().unwrap();
+        assert!(limited.estimate_ua() > 1_000_000);
+    }
+
+    #[test]
+    fn outputs_write_through_the_limit() {
+        let mut limited = limited(MODEL, Policy::Scale);
+        let mut engine = Engine::new(Effect::Solid, ColorOrder::Mono);
+        engine.params.saturation = 0;
+        engine.params.brightness = 0xFF;
+        engine.tick(&mut limited, 0).unwrap();
+        assert!(limited.estimate_ua() <= 1_000_000);
+        assert!(limited.device.i2c.pwm()[0] < 0xFF);
+
+        let mut frame = FrameBuffer::new();
+        frame.fill(0xFF);
+        let mut refuse = self::limited(MODEL, Policy::Refuse);
+        assert!(matches!(
+            frame.flush(&mut refuse),
+            Err(Error::PowerLimit(_))
+        ));
+        assert!(matches!(refuse.flush(&frame), Err(Error::PowerLimit(_))));
+        assert!(refuse.device.i2c.pwm().iter().all(|&pwm| pwm == 0));
+
+        // A range that fits is written as is, one that doesn't scales the whole frame
+        let mut limited = self::limited(MODEL, Policy::Scale);
+        limited.write_range(0, &[0xFF; 30]).unwrap();
+        assert_eq!(limited.device.i2c.pwm()[..30], [0xFF; 30]);
+        limited.write_range(30, &[0xFF; 30]).unwrap();
+        assert!(limited.device.i2c.pwm()[0] < 0xFF);
+        assert!(limited.estimate_ua() <= 1_000_000);
+
+        // Fading the global current up stops at the budget
+        let mut limited = self::limited(MODEL, Policy::Scale);
+        limited.set_global_current(0x00).unwrap();
+        limited.fill(0xFF).unwrap();
+        let mut fade = Fade::global_current(0x00, 0xFF, 10);
+        fade.tick(&mut limited, 0).unwrap();
+        fade.tick(&mut limited, 10).unwrap();
+        assert!(limited.device.i2c.function(addresses::CURRENT_REGISTER) < 0xFF);
+        assert!(limited.estimate_ua() <= 1_000_000);
+    }
+
+    #[test]
+    fn invalid_writes() {
+        let device = IS31FL3743 {
+            width: 4,
+            height: 2,
+            calc_pixel: |x, y| {
+                assert!(x < 4 && y < 2, "outside of the display");
+                register(y, x)
+            },
+            ..device()
+        };
+        let mut limited = PowerLimited::new(device, MODEL, Policy::Refuse).unwrap();
+        assert!(matches!(
+            limited.pixel(4, 0, 0xFF),
+            Err(Error::InvalidLocation(4))
+        ));
+        assert!(matches!(
+            limited.pixel(0, 2, 0xFF),
+            Err(Error::InvalidLocation(2))
+        ));
+        assert!(matches!(
+            limited.fill_matrix(&[0x00; 0xC5]),
+            Err(Error::InvalidFrame(0xC5))
+        ));
+        assert!(matches!(
+            limited.fill_matrix(&[0x00; 0x200]),
+            Err(Error::InvalidFrame(0xFF))
+        ));
+        assert!(matches!(
+            limited.fill_scaling(&[0x00; 0x200]),
+            Err(Error::InvalidFrame(0xFF))
+        ));
+        limited.device.i2c.writes.clear();
+        limited.pixel(3, 1, 0x10).unwrap();
+        assert_eq!(limited.device.i2c.writes, [(0, register(1, 3), 0x10)]);
+    }
+}
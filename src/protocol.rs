@@ -0,0 +1,1009 @@
+//! Handles the QMK OpenRGB protocol, the raw HID protocol OpenRGB uses to control QMK
+//! keyboards. OpenRGB detects a device speaking it with its built-in QMK controller, no
+//! plugin needed.
+//!
+//! The byte layouts follow revision D, protocol version [VERSION]. OpenRGB sends 64 byte
+//! reports starting with the command. The response is the same report, cleared, with the
+//! command kept in the first byte and the data filled in:
+//!
+//! | Command                    | Request data                            | Response data                          |
+//! |----------------------------|-----------------------------------------|----------------------------------------|
+//! | [GET_PROTOCOL_VERSION](command::GET_PROTOCOL_VERSION) |              | [VERSION]                              |
+//! | [GET_QMK_VERSION](command::GET_QMK_VERSION) |                        | Firmware version, NUL terminated       |
+//! | [GET_DEVICE_INFO](command::GET_DEVICE_INFO) |                        | Number of LEDs, number of LEDs including gaps, product and manufacturer, both NUL terminated. [END_OF_MESSAGE] at [STATUS]. |
+//! | [GET_MODE_INFO](command::GET_MODE_INFO) |                            | Mode, speed, hue, saturation, value    |
+//! | [GET_LED_INFO](command::GET_LED_INFO) | First LED, count             | 7 bytes for every LED: x, y, flags, red, green, blue and keycode. LEDs that don't exist have [FAILURE] as flags. |
+//! | [GET_ENABLED_MODES](command::GET_ENABLED_MODES) |                    | Ids of all [Mode]s, ending with a 0    |
+//! | [SET_MODE](command::SET_MODE) | Hue, saturation, value, mode, speed, save | [SUCCESS] or [FAILURE] at [STATUS] |
+//! | [DIRECT_MODE_SET_SINGLE_LED](command::DIRECT_MODE_SET_SINGLE_LED) | LED, red, green, blue | [SUCCESS] or [FAILURE] at [STATUS] |
+//! | [DIRECT_MODE_SET_LEDS](command::DIRECT_MODE_SET_LEDS) | Count, then LED, red, green and blue for every LED | No response |
+//!
+//! Every controller is a [Zone]. The LEDs are numbered zone by zone and row by row within a
+//! zone. LED numbers are a single byte, so only the first 255 LEDs can be addressed. Positions
+//! are spread over QMK's 224x64 grid, with the zones stacked from top to bottom.
+//!
+//! The commands overlap with the ones of [VIA](crate::via), so both need their own raw HID
+//! interface.
+use crate::color::{ColorOrder, Rgb};
+use crate::effects::{Effect, Params, Zone};
+use crate::framebuffer::Output;
+
+/// Length of a report
+pub const REPORT_LEN: usize = 64;
+/// Protocol version reported by [GET_PROTOCOL_VERSION](command::GET_PROTOCOL_VERSION)
+pub const VERSION: u8 = 0x0D;
+/// Offset of the status in responses
+pub const STATUS: usize = REPORT_LEN - 2;
+/// Status of a rejected request, and the flags of LEDs that don't exist
+pub const FAILURE: u8 = 25;
+/// Status of an applied request
+pub const SUCCESS: u8 = 50;
+/// Marks the end of a [GET_DEVICE_INFO](command::GET_DEVICE_INFO) response
+pub const END_OF_MESSAGE: u8 = 100;
+/// QMK's flag for LEDs under a key, reported for every LED
+pub const LED_FLAG_KEYLIGHT: u8 = 0x04;
+/// Most LEDs a [GET_LED_INFO](command::GET_LED_INFO) response holds
+pub const LED_INFO_PER_REPORT: usize = (REPORT_LEN - 1) / 7;
+/// Most LEDs a [DIRECT_MODE_SET_LEDS](command::DIRECT_MODE_SET_LEDS) request holds
+pub const LEDS_PER_REPORT: usize = (REPORT_LEN - 2) / 4;
+
+/// Size of QMK's LED grid, the last position is included
+const GRID_WIDTH: usize = 224;
+const GRID_HEIGHT: usize = 64;
+
+/// Reported by [GET_QMK_VERSION](command::GET_QMK_VERSION)
+const FIRMWARE: &str = concat!("is31fl3743a ", env!("CARGO_PKG_VERSION"));
+
+/// Commands of the protocol
+pub mod command {
+    pub const GET_PROTOCOL_VERSION: u8 = 1;
+    pub const GET_QMK_VERSION: u8 = 2;
+    pub const GET_DEVICE_INFO: u8 = 3;
+    pub const GET_MODE_INFO: u8 = 4;
+    pub const GET_LED_INFO: u8 = 5;
+    pub const GET_ENABLED_MODES: u8 = 6;
+    pub const SET_MODE: u8 = 7;
+    pub const DIRECT_MODE_SET_SINGLE_LED: u8 = 8;
+    pub const DIRECT_MODE_SET_LEDS: u8 = 9;
+}
+
+/// Who is in control of the LEDs
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// The host sets every LED with [DIRECT_MODE_SET_LEDS](command::DIRECT_MODE_SET_LEDS)
+    Direct,
+    /// The firmware runs an effect, driven by [Handler::tick]
+    Effect(Effect),
+}
+
+impl Mode {
+    /// Every mode with its id in OpenRGB's QMK controller, in the order
+    /// [GET_ENABLED_MODES](command::GET_ENABLED_MODES) reports them
+    const IDS: [(Mode, u8); 7] = [
+        (Mode::Direct, 1),
+        (Mode::Effect(Effect::Solid), 2),
+        (Mode::Effect(Effect::Breathing), 6),
+        (Mode::Effect(Effect::RainbowCycle), 13),
+        (Mode::Effect(Effect::ColorWave), 14),
+        (Mode::Effect(Effect::Spiral), 20),
+        (Mode::Effect(Effect::ReactiveFade), 31),
+    ];
+
+    /// `None` for ids of QMK effects this crate doesn't have
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        Self::IDS
+            .iter()
+            .find(|&&(_, id)| id == bits)
+            .map(|&(mode, _)| mode)
+    }
+
+    pub fn into_bits(self) -> u8 {
+        Self::IDS
+            .iter()
+            .find(|&&(mode, _)| mode == self)
+            .map_or(0, |&(_, id)| id)
+    }
+}
+
+/// Mode and effect parameters, as sent with [SET_MODE](command::SET_MODE) and stored when the
+/// host asks to save them
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Settings {
+    pub mode: Mode,
+    /// The value of the host is the [brightness](Params::brightness)
+    pub params: Params,
+}
+
+impl Settings {
+    /// Length of the serialized settings
+    pub const LEN: usize = 5;
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let Params {
+            speed,
+            hue,
+            saturation,
+            brightness,
+        } = self.params;
+        [self.mode.into_bits(), speed, hue, saturation, brightness]
+    }
+
+    /// Returns `None` if the data is too short or the mode is unknown
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let &[mode, speed, hue, saturation, brightness, ..] = data else {
+            return None;
+        };
+        Some(Settings {
+            mode: Mode::from_bits(mode)?,
+            params: Params {
+                speed,
+                hue,
+                saturation,
+                brightness,
+            },
+        })
+    }
+}
+
+/// Copy `text` into `out` with a terminating NUL, cut off to fit. Returns the bytes used.
+fn put_str(out: &mut [u8], text: &str) -> usize {
+    let len = text.len().min(out.len().saturating_sub(1));
+    out[..len].copy_from_slice(&text.as_bytes()[..len]);
+    if let Some(nul) = out.get_mut(len) {
+        *nul = 0x00;
+    }
+    len + 1
+}
+
+/// Applies requests from OpenRGB to one or more controllers.
+///
+/// Pass every raw HID report to [handle](Self::handle) and send it back afterwards if it
+/// returns `true`. Call [tick](Self::tick) regularly to show the LEDs set by the host and to
+/// run effects.
+pub struct Handler<'a, O> {
+    pub zones: &'a mut [Zone<O>],
+    /// Reported by [GET_DEVICE_INFO](command::GET_DEVICE_INFO)
+    pub product: &'a str,
+    pub manufacturer: &'a str,
+    mode: Mode,
+    /// LEDs were set since the last tick
+    dirty: bool,
+    save: bool,
+}
+
+impl<'a, O: Output> Handler<'a, O> {
+    /// Starts in [Mode::Direct]
+    pub fn new(zones: &'a mut [Zone<O>], product: &'a str, manufacturer: &'a str) -> Self {
+        Handler {
+            zones,
+            product,
+            manufacturer,
+            mode: Mode::Direct,
+            dirty: false,
+            save: false,
+        }
+    }
+
+    /// Current mode and the parameters of the first zone
+    pub fn settings(&self) -> Settings {
+        Settings {
+            mode: self.mode,
+            params: self
+                .zones
+                .first()
+                .map_or(Params::default(), |zone| zone.engine.params),
+        }
+    }
+
+    /// Apply settings, e.g. the ones stored the last time the host asked to save. Switching
+    /// to [Mode::Direct] turns all LEDs off until the host sets them.
+    pub fn load(&mut self, settings: Settings) {
+        let direct = settings.mode == Mode::Direct;
+        if direct && self.mode != Mode::Direct {
+            for zone in self.zones.iter_mut() {
+                zone.engine.frame.clear();
+            }
+            self.dirty = true;
+        }
+        self.mode = settings.mode;
+        for zone in self.zones.iter_mut() {
+            zone.engine.params = settings.params;
+            if let Mode::Effect(effect) = settings.mode {
+                zone.engine.set_effect(effect);
+            }
+        }
+    }
+
+    /// The settings to store if the host asked to save since the last call
+    pub fn take_save(&mut self) -> Option<Settings> {
+        if core::mem::take(&mut self.save) {
+            Some(self.settings())
+        } else {
+            None
+        }
+    }
+
+    /// In [Mode::Direct] write the zones if the host set LEDs since the last call, otherwise run
+    /// the current effect on all zones
+    pub fn tick(&mut self, now_ms: u32) -> Result<(), O::Error> {
+        match self.mode {
+            Mode::Direct if self.dirty => {
+                for zone in self.zones.iter_mut() {
+                    zone.engine.frame.flush(&mut zone.device)?;
+                }
+                self.dirty = false;
+            }
+            Mode::Direct => {}
+            Mode::Effect(_) => {
+                for zone in self.zones.iter_mut() {
+                    zone.engine.tick(&mut zone.device, now_ms)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Number of LEDs of all zones
+    pub fn led_count(&self) -> usize {
+        self.zones
+            .iter()
+            .map(|zone| {
+                let device = zone.device.device();
+                device.width as usize * device.height as usize
+            })
+            .sum()
+    }
+
+    /// Handle a report in place, turning it into the response. Returns whether the response
+    /// has to be sent, which isn't the case for
+    /// [DIRECT_MODE_SET_LEDS](command::DIRECT_MODE_SET_LEDS) and reports shorter than
+    /// [REPORT_LEN]. Unknown commands are answered with [FAILURE].
+    pub fn handle(&mut self, report: &mut [u8]) -> bool {
+        let Some(report) = report.first_chunk_mut::<REPORT_LEN>() else {
+            return false;
+        };
+        let request = *report;
+        report.fill(0x00);
+        report[0] = request[0];
+        match request[0] {
+            command::GET_PROTOCOL_VERSION => report[1] = VERSION,
+            command::GET_QMK_VERSION => {
+                put_str(&mut report[1..STATUS], FIRMWARE);
+            }
+            command::GET_DEVICE_INFO => {
+                let count = self.led_count().min(0xFF) as u8;
+                report[1..3].copy_from_slice(&[count, count]);
+                let len = put_str(&mut report[3..STATUS], self.product);
+                put_str(&mut report[3 + len..STATUS], self.manufacturer);
+                report[STATUS] = END_OF_MESSAGE;
+            }
+            command::GET_MODE_INFO => {
+                let [mode, speed, hue, saturation, brightness] = self.settings().to_bytes();
+                report[1..6].copy_from_slice(&[mode, speed, hue, saturation, brightness]);
+            }
+            command::GET_LED_INFO => {
+                let count = (request[2] as usize).min(LED_INFO_PER_REPORT);
+                for (index, info) in report[1..].chunks_mut(7).take(count).enumerate() {
+                    match self.led_info(request[1] as usize + index) {
+                        Some(led) => info.copy_from_slice(&led),
+                        None => info[2] = FAILURE,
+                    }
+                }
+            }
+            command::GET_ENABLED_MODES => {
+                for (id, &(_, bits)) in report[1..].iter_mut().zip(Mode::IDS.iter()) {
+                    *id = bits;
+                }
+            }
+            command::SET_MODE => {
+                let [_, hue, saturation, value, mode, speed, save, ..] = request;
+                report[STATUS] = match Mode::from_bits(mode) {
+                    Some(mode) => {
+                        self.load(Settings {
+                            mode,
+                            params: Params {
+                                speed,
+                                hue,
+                                saturation,
+                                brightness: value,
+                            },
+                        });
+                        self.save |= save != 0;
+                        SUCCESS
+                    }
+                    None => FAILURE,
+                };
+            }
+            command::DIRECT_MODE_SET_SINGLE_LED => {
+                let [_, led, r, g, b, ..] = request;
+                report[STATUS] = match self.set_led(led, Rgb::new(r, g, b)) {
+                    true => SUCCESS,
+                    false => FAILURE,
+                };
+            }
+            command::DIRECT_MODE_SET_LEDS => {
+                let count = (request[1] as usize).min(LEDS_PER_REPORT);
+                for led in request[2..].chunks(4).take(count) {
+                    self.set_led(led[0], Rgb::new(led[1], led[2], led[3]));
+                }
+                return false;
+            }
+            _ => report[STATUS] = FAILURE,
+        }
+        true
+    }
+
+    /// Zone and x,y position of an LED
+    fn locate(&self, mut led: usize) -> Option<(usize, u8, u8)> {
+        for (index, zone) in self.zones.iter().enumerate() {
+            let device = zone.device.device();
+            let width = device.width as usize;
+            let leds = width * device.height as usize;
+            if led < leds {
+                return Some((index, (led % width) as u8, (led / width) as u8));
+            }
+            led -= leds;
+        }
+        None
+    }
+
+    /// Response bytes of [GET_LED_INFO](command::GET_LED_INFO) for one LED
+    fn led_info(&self, led: usize) -> Option<[u8; 7]> {
+        let (index, x, y) = self.locate(led)?;
+        let width = self
+            .zones
+            .iter()
+            .map(|zone| zone.device.device().width as usize)
+            .max()?;
+        let heights = self
+            .zones
+            .iter()
+            .map(|zone| zone.device.device().height as usize);
+        let height = heights.clone().sum::<usize>();
+        let row = heights.take(index).sum::<usize>() + y as usize;
+        let spread = |at: usize, size: usize, grid: usize| (at * grid / (size - 1).max(1)) as u8;
+
+        let zone = &self.zones[index];
+        let register = (zone.device.device().calc_pixel)(x, y);
+        let Rgb { r, g, b } = color_at(&zone.engine.frame.pwm, register, zone.engine.order);
+        Some([
+            spread(x as usize, width, GRID_WIDTH),
+            spread(row, height, GRID_HEIGHT),
+            LED_FLAG_KEYLIGHT,
+            r,
+            g,
+            b,
+            // KC_NO, the LEDs aren't tied to keys
+            0x00,
+        ])
+    }
+
+    /// Set the color of an LED in the frame of its zone. Fails outside of [Mode::Direct] and
+    /// for LEDs that don't exist.
+    fn set_led(&mut self, led: u8, color: Rgb) -> bool {
+        if self.mode != Mode::Direct {
+            return false;
+        }
+        let Some((index, x, y)) = self.locate(led as usize) else {
+            return false;
+        };
+        let Zone { device, engine } = &mut self.zones[index];
+        engine
+            .frame
+            .set_pixel(device.device(), x, y, engine.order, color);
+        self.dirty = true;
+        true
+    }
+}
+
+/// Color of the LED whose first channel is at `register`, the reverse of
+/// [set_color](crate::framebuffer::FrameBuffer::set_color)
+fn color_at(pwm: &[u8; 0xC6], register: u8, order: ColorOrder) -> Rgb {
+    let channel = |offset: usize| {
+        (register as usize + offset)
+            .checked_sub(1)
+            .and_then(|index| pwm.get(index))
+            .copied()
+            .unwrap_or(0)
+    };
+    let [first, second, third] = [channel(0), channel(1), channel(2)];
+    match order {
+        ColorOrder::Mono => Rgb::new(first, first, first),
+        ColorOrder::Rgb => Rgb::new(first, second, third),
+        ColorOrder::Rbg => Rgb::new(first, third, second),
+        ColorOrder::Grb => Rgb::new(second, first, third),
+        ColorOrder::Gbr => Rgb::new(third, first, second),
+        ColorOrder::Brg => Rgb::new(second, third, first),
+        ColorOrder::Bgr => Rgb::new(third, second, first),
+    }
+}
+
+/// Response to [GET_DEVICE_INFO](command::GET_DEVICE_INFO)
+#[cfg(feature = "std")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceInfo {
+    pub led_count: u8,
+    pub led_count_with_gaps: u8,
+    pub product: std::string::String,
+    pub manufacturer: std::string::String,
+}
+
+/// One LED of a [GET_LED_INFO](command::GET_LED_INFO) response
+#[cfg(feature = "std")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct LedInfo {
+    /// Position on QMK's 224x64 grid
+    pub x: u8,
+    pub y: u8,
+    pub flags: u8,
+    pub color: Rgb,
+    pub keycode: u8,
+}
+
+/// Errors of the [Client]
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub enum ClientError {
+    Io(std::io::Error),
+    /// The device answered with [FAILURE]
+    Failure,
+    /// The response doesn't match the request
+    Malformed,
+    /// The LEDs to set go past the last LED number the protocol can address
+    OutOfRange,
+}
+
+#[cfg(feature = "std")]
+impl core::fmt::Display for ClientError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match self {
+            ClientError::Io(err) => write!(f, "{err}"),
+            ClientError::Failure => write!(f, "device rejected the request"),
+            ClientError::Malformed => write!(f, "malformed response"),
+            ClientError::OutOfRange => write!(f, "LED number out of range"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ClientError {}
+
+#[cfg(feature = "std")]
+impl From<std::io::Error> for ClientError {
+    fn from(err: std::io::Error) -> Self {
+        ClientError::Io(err)
+    }
+}
+
+/// Host side of the protocol, e.g. for tests or tools without OpenRGB.
+///
+/// Works over a Linux hidraw device or anything else that takes a report ID of 0 followed by
+/// the report on every write, and returns one [REPORT_LEN] byte report per read.
+#[cfg(feature = "std")]
+pub struct Client<T> {
+    pub port: T,
+}
+
+#[cfg(feature = "std")]
+impl<T: std::io::Read + std::io::Write> Client<T> {
+    pub fn new(port: T) -> Self {
+        Client { port }
+    }
+
+    pub fn protocol_version(&mut self) -> Result<u8, ClientError> {
+        Ok(self.request(command::GET_PROTOCOL_VERSION, &[])?[1])
+    }
+
+    /// Version string of the firmware
+    pub fn firmware_version(&mut self) -> Result<std::string::String, ClientError> {
+        let response = self.request(command::GET_QMK_VERSION, &[])?;
+        Ok(take_str(&response[1..STATUS])?.0)
+    }
+
+    pub fn device_info(&mut self) -> Result<DeviceInfo, ClientError> {
+        let response = self.request(command::GET_DEVICE_INFO, &[])?;
+        let (product, len) = take_str(&response[3..STATUS])?;
+        let (manufacturer, _) = take_str(&response[3 + len..STATUS])?;
+        Ok(DeviceInfo {
+            led_count: response[1],
+            led_count_with_gaps: response[2],
+            product,
+            manufacturer,
+        })
+    }
+
+    /// Current mode and effect parameters. The mode is `None` if the firmware runs an effect
+    /// this crate doesn't know.
+    pub fn mode_info(&mut self) -> Result<(Option<Mode>, Params), ClientError> {
+        let response = self.request(command::GET_MODE_INFO, &[])?;
+        let [_, mode, speed, hue, saturation, brightness, ..] = response;
+        let params = Params {
+            speed,
+            hue,
+            saturation,
+            brightness,
+        };
+        Ok((Mode::from_bits(mode), params))
+    }
+
+    /// Position, flags, color and keycode of `count` LEDs starting at `first`, `None` for LEDs
+    /// that don't exist. Sends as many requests as needed.
+    pub fn led_info(
+        &mut self,
+        first: u8,
+        count: u8,
+    ) -> Result<std::vec::Vec<Option<LedInfo>>, ClientError> {
+        let mut leds = std::vec::Vec::with_capacity(count as usize);
+        while leds.len() < count as usize {
+            let start = first as usize + leds.len();
+            let Ok(start) = u8::try_from(start) else {
+                // Past the last LED number, none of them exist
+                leds.resize(count as usize, None);
+                break;
+            };
+            let chunk = (count as usize - leds.len()).min(LED_INFO_PER_REPORT);
+            let response = self.request(command::GET_LED_INFO, &[start, chunk as u8])?;
+            leds.extend(response[1..].chunks(7).take(chunk).map(|info| {
+                (info[2] != FAILURE).then(|| LedInfo {
+                    x: info[0],
+                    y: info[1],
+                    flags: info[2],
+                    color: Rgb::new(info[3], info[4], info[5]),
+                    keycode: info[6],
+                })
+            }));
+        }
+        Ok(leds)
+    }
+
+    /// Modes the firmware offers, skipping QMK effects this crate doesn't know
+    pub fn enabled_modes(&mut self) -> Result<std::vec::Vec<Mode>, ClientError> {
+        let response = self.request(command::GET_ENABLED_MODES, &[])?;
+        Ok(response[1..]
+            .iter()
+            .take_while(|&&id| id != 0)
+            .filter_map(|&id| Mode::from_bits(id))
+            .collect())
+    }
+
+    /// Switch the mode and effect parameters, and store them in the firmware if `save` is set
+    pub fn set_mode(&mut self, settings: Settings, save: bool) -> Result<(), ClientError> {
+        let Params {
+            speed,
+            hue,
+            saturation,
+            brightness,
+        } = settings.params;
+        let mode = settings.mode.into_bits();
+        let data = [hue, saturation, brightness, mode, speed, save as u8];
+        let response = self.request(command::SET_MODE, &data)?;
+        check_status(&response)
+    }
+
+    /// Set the color of a single LED and wait for the firmware to confirm it. Only works in
+    /// [Mode::Direct].
+    pub fn set_led(&mut self, led: u8, color: Rgb) -> Result<(), ClientError> {
+        let data = [led, color.r, color.g, color.b];
+        let response = self.request(command::DIRECT_MODE_SET_SINGLE_LED, &data)?;
+        check_status(&response)
+    }
+
+    /// Set the colors of LEDs starting at `first`, sending as many requests as needed. The
+    /// firmware doesn't confirm these, LEDs that don't exist are skipped. Only works in
+    /// [Mode::Direct].
+    ///
+    /// Returns [OutOfRange](ClientError::OutOfRange) without sending anything if an LED number
+    /// doesn't fit into a byte.
+    pub fn set_leds(&mut self, first: u8, colors: &[Rgb]) -> Result<(), ClientError> {
+        if first as usize + colors.len() > 0x100 {
+            return Err(ClientError::OutOfRange);
+        }
+        for (index, chunk) in colors.chunks(LEDS_PER_REPORT).enumerate() {
+            let mut data = std::vec![chunk.len() as u8];
+            for (offset, color) in chunk.iter().enumerate() {
+                let led = first as usize + index * LEDS_PER_REPORT + offset;
+                data.extend_from_slice(&[led as u8, color.r, color.g, color.b]);
+            }
+            self.send(command::DIRECT_MODE_SET_LEDS, &data)?;
+        }
+        Ok(())
+    }
+
+    /// Write a report with the command and data, preceded by the report ID
+    fn send(&mut self, command: u8, data: &[u8]) -> std::io::Result<()> {
+        let mut report = [0x00; 1 + REPORT_LEN];
+        report[1] = command;
+        report[2..2 + data.len()].copy_from_slice(data);
+        self.port.write_all(&report)?;
+        self.port.flush()
+    }
+
+    /// Send a request and wait for its response
+    fn request(&mut self, command: u8, data: &[u8]) -> Result<[u8; REPORT_LEN], ClientError> {
+        self.send(command, data)?;
+        let mut response = [0x00; REPORT_LEN];
+        self.port.read_exact(&mut response)?;
+        if response[0] != command {
+            return Err(ClientError::Malformed);
+        }
+        Ok(response)
+    }
+}
+
+/// A NUL terminated string at the start of `data`, and the bytes it takes with the NUL
+#[cfg(feature = "std")]
+fn take_str(data: &[u8]) -> Result<(std::string::String, usize), ClientError> {
+    let len = data
+        .iter()
+        .position(|&byte| byte == 0x00)
+        .ok_or(ClientError::Malformed)?;
+    let text = core::str::from_utf8(&data[..len]).map_err(|_| ClientError::Malformed)?;
+    Ok((text.into(), len + 1))
+}
+
+#[cfg(feature = "std")]
+fn check_status(response: &[u8; REPORT_LEN]) -> Result<(), ClientError> {
+    match response[STATUS] {
+        SUCCESS => Ok(()),
+        FAILURE => Err(ClientError::Failure),
+        _ => Err(ClientError::Malformed),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::effects::Engine;
+    use crate::mock::{self, Bus};
+    use crate::{register, IS31FL3743};
+
+    /// 6x11 RGB LEDs, 66 in total
+    fn zone() -> Zone<IS31FL3743<Bus>> {
+        Zone {
+            device: IS31FL3743 {
+                width: 6,
+                calc_pixel: |x, y| register(y, 3 * x),
+                ..mock::device()
+            },
+            engine: Engine::new(Effect::Solid, ColorOrder::Rgb),
+        }
+    }
+
+    fn request(command: u8, data: &[u8]) -> [u8; REPORT_LEN] {
+        let mut report = [0x00; REPORT_LEN];
+        report[0] = command;
+        report[1..1 + data.len()].copy_from_slice(data);
+        report
+    }
+
+    /// Handle a request and return the response, if any
+    fn handle(
+        handler: &mut Handler<IS31FL3743<Bus>>,
+        command: u8,
+        data: &[u8],
+    ) -> Option<[u8; REPORT_LEN]> {
+        let mut report = request(command, data);
+        handler.handle(&mut report).then_some(report)
+    }
+
+    #[test]
+    fn modes_and_settings() {
+        for (mode, bits) in Mode::IDS {
+            assert_eq!(Mode::from_bits(bits), Some(mode));
+            assert_eq!(mode.into_bits(), bits);
+        }
+        for effect in Effect::ALL {
+            assert_ne!(Mode::Effect(effect).into_bits(), 0);
+        }
+        assert_eq!(Mode::from_bits(0), None);
+        assert_eq!(Mode::from_bits(3), None);
+
+        let settings = Settings {
+            mode: Mode::Effect(Effect::ColorWave),
+            params: Params {
+                speed: 1,
+                hue: 2,
+                saturation: 3,
+                brightness: 4,
+            },
+        };
+        assert_eq!(settings.to_bytes(), [14, 1, 2, 3, 4]);
+        assert_eq!(Settings::from_bytes(&settings.to_bytes()), Some(settings));
+        assert_eq!(Settings::from_bytes(&[1, 1, 2, 3]), None);
+    }
+
+    #[test]
+    fn info_responses() {
+        let mut zones = [zone(), zone()];
+        let mut handler = Handler::new(&mut zones, "Keyboard", "Maker");
+
+        let response = handle(&mut handler, command::GET_PROTOCOL_VERSION, &[]).unwrap();
+        assert_eq!(response[..3], [command::GET_PROTOCOL_VERSION, VERSION, 0]);
+
+        let response = handle(&mut handler, command::GET_QMK_VERSION, &[]).unwrap();
+        assert_eq!(response[1..1 + FIRMWARE.len()], *FIRMWARE.as_bytes());
+        assert_eq!(response[1 + FIRMWARE.len()], 0);
+
+        let response = handle(&mut handler, command::GET_DEVICE_INFO, &[]).unwrap();
+        assert_eq!(response[..3], [command::GET_DEVICE_INFO, 132, 132]);
+        assert_eq!(response[3..18], *b"Keyboard\0Maker\0");
+        assert_eq!(response[STATUS], END_OF_MESSAGE);
+
+        let response = handle(&mut handler, command::GET_MODE_INFO, &[]).unwrap();
+        let params = Params::default();
+        assert_eq!(
+            response[..6],
+            [
+                command::GET_MODE_INFO,
+                1,
+                params.speed,
+                params.hue,
+                params.saturation,
+                params.brightness
+            ]
+        );
+
+        let response = handle(&mut handler, command::GET_ENABLED_MODES, &[]).unwrap();
+        assert_eq!(
+            response[..9],
+            [command::GET_ENABLED_MODES, 1, 2, 6, 13, 14, 20, 31, 0]
+        );
+
+        let response = handle(&mut handler, 0x42, &[1, 2, 3]).unwrap();
+        assert_eq!(response[..4], [0x42, 0, 0, 0]);
+        assert_eq!(response[STATUS], FAILURE);
+
+        // Reports have to be complete
+        let mut report = [command::GET_PROTOCOL_VERSION; REPORT_LEN - 1];
+        assert!(!handler.handle(&mut report));
+    }
+
+    #[test]
+    fn led_info() {
+        let mut zones = [zone(), zone()];
+        zones[1].engine.frame.set_pixel(
+            &zones[1].device,
+            5,
+            10,
+            ColorOrder::Rgb,
+            Rgb::new(1, 2, 3),
+        );
+        let mut handler = Handler::new(&mut zones, "", "");
+
+        // The first two LEDs, the first LED of the second zone, the last LED and LEDs that
+        // don't exist
+        let response = handle(&mut handler, command::GET_LED_INFO, &[0, 2]).unwrap();
+        assert_eq!(response[1..15], [0, 0, 4, 0, 0, 0, 0, 44, 0, 4, 0, 0, 0, 0]);
+        let response = handle(&mut handler, command::GET_LED_INFO, &[66, 1]).unwrap();
+        assert_eq!(response[1..8], [0, 33, 4, 0, 0, 0, 0]);
+        let response = handle(&mut handler, command::GET_LED_INFO, &[131, 0xFF]).unwrap();
+        assert_eq!(response[1..8], [224, 64, 4, 1, 2, 3, 0]);
+        for led in 1..LED_INFO_PER_REPORT {
+            assert_eq!(response[1 + led * 7..][..7], [0, 0, FAILURE, 0, 0, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn direct_mode() {
+        let mut zones = [zone(), zone()];
+        let mut handler = Handler::new(&mut zones, "", "");
+
+        let response = handle(
+            &mut handler,
+            command::DIRECT_MODE_SET_SINGLE_LED,
+            &[0, 0x10, 0x20, 0x30],
+        );
+        assert_eq!(response.unwrap()[STATUS], SUCCESS);
+        let response = handle(
+            &mut handler,
+            command::DIRECT_MODE_SET_SINGLE_LED,
+            &[132, 0x10, 0x20, 0x30],
+        );
+        assert_eq!(response.unwrap()[STATUS], FAILURE);
+
+        // No response, the second LED of the second zone and one that doesn't exist
+        let data = [2, 67, 0x40, 0x50, 0x60, 200, 0xFF, 0xFF, 0xFF];
+        assert_eq!(
+            handle(&mut handler, command::DIRECT_MODE_SET_LEDS, &data),
+            None
+        );
+
+        // Nothing is written before the next tick
+        assert!(handler.zones[0].device.i2c.writes.is_empty());
+        handler.tick(0).unwrap();
+        assert_eq!(handler.zones[0].device.i2c.pwm()[..3], [0x10, 0x20, 0x30]);
+        assert_eq!(
+            handler.zones[1].device.i2c.pwm()[..6],
+            [0, 0, 0, 0x40, 0x50, 0x60]
+        );
+        // Only when something changed
+        handler.zones[0].device.i2c.writes.clear();
+        handler.tick(10).unwrap();
+        assert!(handler.zones[0].device.i2c.writes.is_empty());
+    }
+
+    #[test]
+    fn set_mode() {
+        let mut zones = [zone()];
+        let mut handler = Handler::new(&mut zones, "", "");
+
+        // Hue, saturation, value, mode, speed, save
+        let response = handle(&mut handler, command::SET_MODE, &[0, 0, 0xFF, 3, 0, 0]);
+        assert_eq!(response.unwrap()[STATUS], FAILURE);
+        assert_eq!(handler.settings().mode, Mode::Direct);
+
+        let response = handle(&mut handler, command::SET_MODE, &[1, 2, 3, 6, 4, 1]);
+        assert_eq!(response.unwrap()[STATUS], SUCCESS);
+        let settings = Settings {
+            mode: Mode::Effect(Effect::Breathing),
+            params: Params {
+                speed: 4,
+                hue: 1,
+                saturation: 2,
+                brightness: 3,
+            },
+        };
+        assert_eq!(handler.settings(), settings);
+        assert_eq!(handler.zones[0].engine.effect, Effect::Breathing);
+        assert_eq!(handler.take_save(), Some(settings));
+        assert_eq!(handler.take_save(), None);
+
+        // LEDs can only be set in direct mode
+        let response = handle(
+            &mut handler,
+            command::DIRECT_MODE_SET_SINGLE_LED,
+            &[0, 1, 1, 1],
+        );
+        assert_eq!(response.unwrap()[STATUS], FAILURE);
+
+        // Back to direct mode the LEDs are turned off
+        handler.tick(0).unwrap();
+        assert!(handler.zones[0]
+            .device
+            .i2c
+            .pwm()
+            .iter()
+            .any(|&pwm| pwm != 0));
+        let response = handle(&mut handler, command::SET_MODE, &[0, 0, 0xFF, 1, 0, 0]);
+        assert_eq!(response.unwrap()[STATUS], SUCCESS);
+        assert_eq!(handler.take_save(), None);
+        handler.tick(10).unwrap();
+        assert!(handler.zones[0]
+            .device
+            .i2c
+            .pwm()
+            .iter()
+            .all(|&pwm| pwm == 0));
+    }
+
+    /// A hidraw-like port that hands every written report to a [Handler] and reads back its
+    /// responses
+    #[cfg(feature = "std")]
+    struct Loopback<'a> {
+        handler: Handler<'a, IS31FL3743<Bus>>,
+        responses: std::collections::VecDeque<u8>,
+    }
+
+    #[cfg(feature = "std")]
+    impl std::io::Write for Loopback<'_> {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            // One report per write, after the report ID
+            assert_eq!(buf.len(), 1 + REPORT_LEN);
+            assert_eq!(buf[0], 0);
+            let mut report = [0x00; REPORT_LEN];
+            report.copy_from_slice(&buf[1..]);
+            if self.handler.handle(&mut report) {
+                self.responses.extend(report);
+            }
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[cfg(feature = "std")]
+    impl std::io::Read for Loopback<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let len = buf.len().min(self.responses.len());
+            for (byte, response) in buf.iter_mut().zip(self.responses.drain(..len)) {
+                *byte = response;
+            }
+            Ok(len)
+        }
+    }
+
+    #[cfg(feature = "std")]
+    fn client(zones: &mut [Zone<IS31FL3743<Bus>>]) -> Client<Loopback<'_>> {
+        Client::new(Loopback {
+            handler: Handler::new(zones, "Keyboard", "Maker"),
+            responses: Default::default(),
+        })
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn client_round_trip() {
+        let mut zones = [zone(), zone()];
+        let mut client = client(&mut zones);
+
+        assert_eq!(client.protocol_version().unwrap(), VERSION);
+        assert_eq!(client.firmware_version().unwrap(), FIRMWARE);
+        assert_eq!(
+            client.device_info().unwrap(),
+            DeviceInfo {
+                led_count: 132,
+                led_count_with_gaps: 132,
+                product: "Keyboard".into(),
+                manufacturer: "Maker".into(),
+            }
+        );
+        assert_eq!(client.enabled_modes().unwrap().len(), Mode::IDS.len());
+        assert_eq!(
+            client.mode_info().unwrap(),
+            (Some(Mode::Direct), Params::default())
+        );
+
+        // More LEDs than fit into one report, ending past the last LED
+        let leds = client.led_info(120, 20).unwrap();
+        assert_eq!(leds.len(), 20);
+        assert!(leds[..12].iter().all(Option::is_some));
+        assert!(leds[12..].iter().all(Option::is_none));
+        assert_eq!(leds[11].unwrap().x, 224);
+        assert_eq!(client.led_info(250, 10).unwrap(), [None; 10]);
+
+        // All LEDs of the second zone, in more than one report
+        let colors: std::vec::Vec<Rgb> = (0..66).map(|led| Rgb::new(led, 0x10, 0x20)).collect();
+        client.set_leds(66, &colors).unwrap();
+        client.set_led(0, Rgb::new(7, 8, 9)).unwrap();
+        assert!(matches!(
+            client.set_led(200, Rgb::new(7, 8, 9)),
+            Err(ClientError::Failure)
+        ));
+        client.port.handler.tick(0).unwrap();
+        assert_eq!(
+            client.led_info(131, 1).unwrap()[0].unwrap().color,
+            colors[65]
+        );
+
+        let settings = Settings {
+            mode: Mode::Effect(Effect::Breathing),
+            params: Params::default(),
+        };
+        client.set_mode(settings, true).unwrap();
+        assert_eq!(
+            client.mode_info().unwrap(),
+            (Some(settings.mode), settings.params)
+        );
+        assert!(matches!(
+            client.set_led(0, Rgb::new(1, 2, 3)),
+            Err(ClientError::Failure)
+        ));
+        assert!(client.port.responses.is_empty());
+
+        let Loopback { mut handler, .. } = client.port;
+        assert_eq!(handler.take_save(), Some(settings));
+        let pwm = zones[1].device.i2c.pwm();
+        assert_eq!(pwm[..6], [0, 0x10, 0x20, 1, 0x10, 0x20]);
+        assert_eq!(
+            pwm[register(crate::SW_PINS - 1, 15) as usize - 1..][..3],
+            [65, 0x10, 0x20]
+        );
+        assert_eq!(zones[0].device.i2c.pwm()[..3], [7, 8, 9]);
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn client_rejects_leds_past_255() {
+        let mut zones = [zone()];
+        let mut client = client(&mut zones);
+        let colors = [Rgb::new(1, 2, 3); 2];
+        assert!(matches!(
+            client.set_leds(0xFF, &colors),
+            Err(ClientError::OutOfRange)
+        ));
+        client.set_leds(0xFF, &colors[..1]).unwrap();
+        assert!(client.port.responses.is_empty());
+    }
+}
@@ -0,0 +1,363 @@
+use crate::color::{scale8, Hsv};
+use crate::framebuffer::{FrameBuffer, Output};
+use crate::layout::{Layout, Led};
+
+/// Maximum number of LEDs of a [Layout] that light up, further LEDs stay dark. The same as the
+/// number of PWM registers of a single controller.
+pub const MAX_KEYS: usize = 0xC6;
+
+/// Number of key presses that can spread a splash or ripple at the same time
+const EVENTS: usize = 8;
+
+/// How key presses are shown
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    /// Only the pressed key lights up, it fades out after being released
+    PressedKey,
+    /// A filled circle spreads out from the pressed key and fades out
+    Splash,
+    /// A ring spreads out from the pressed key and fades out
+    Ripple,
+}
+
+#[derive(Clone, Copy)]
+struct Event {
+    /// Position of the key, see [Led::x] and [Led::y]
+    x: u16,
+    y: u16,
+    timestamp: u32,
+}
+
+/// Keys or LEDs that light up and fade out, by index. Shared by [Reactive] and
+/// [Effect::ReactiveFade](crate::effects::Effect::ReactiveFade).
+#[derive(Clone, Copy)]
+pub(crate) struct Heat {
+    /// When every key was last pressed or released. `None` once it faded out.
+    lit_at: [Option<u32>; MAX_KEYS],
+    /// Bit set of the keys held down
+    held: [u8; MAX_KEYS.div_ceil(8)],
+}
+
+impl Heat {
+    pub const fn new() -> Self {
+        Heat {
+            lit_at: [None; MAX_KEYS],
+            held: [0x00; MAX_KEYS.div_ceil(8)],
+        }
+    }
+
+    /// Light up a key until it is released
+    pub fn press(&mut self, index: usize, now_ms: u32) {
+        if index < MAX_KEYS {
+            self.held[index / 8] |= 1 << (index % 8);
+            self.lit_at[index] = Some(now_ms);
+        }
+    }
+
+    /// Start fading out a key
+    pub fn release(&mut self, index: usize, now_ms: u32) {
+        if index < MAX_KEYS {
+            self.held[index / 8] &= !(1 << (index % 8));
+            self.lit_at[index] = Some(now_ms);
+        }
+    }
+
+    fn is_held(&self, index: usize) -> bool {
+        self.held[index / 8] & (1 << (index % 8)) != 0
+    }
+
+    /// Brightness of a key. Held keys are at full brightness.
+    pub fn level(&self, index: usize, now_ms: u32, speed: u8) -> u8 {
+        if index >= MAX_KEYS {
+            0
+        } else if self.is_held(index) {
+            0xFF
+        } else {
+            self.lit_at[index].map_or(0, |lit_at| fade(now_ms, lit_at, speed))
+        }
+    }
+
+    /// Forget keys that faded out, so that they don't light up again when the timer wraps
+    /// around
+    pub fn expire(&mut self, now_ms: u32, speed: u8) {
+        for index in 0..MAX_KEYS {
+            let faded = self.lit_at[index].is_some_and(|since| fade(now_ms, since, speed) == 0);
+            if faded && !self.is_held(index) {
+                self.lit_at[index] = None;
+            }
+        }
+    }
+}
+
+/// Lights up keys when they are pressed and fades them out afterwards.
+///
+/// Feed it with [key_event](Self::key_event) from the key scanning code and call
+/// [tick](Self::tick) regularly for every controller of the [Layout]. Every tick renders into a
+/// [FrameBuffer] and writes it in a single burst, no matter how many keys were pressed. Splashes
+/// and ripples spread over the physical positions of the keys, across controllers.
+///
+/// Presses are forgotten once they faded out. Timestamps may wrap around, as long as the
+/// display is rendered at least once every 24 days.
+pub struct Reactive<'a> {
+    /// Keys are found by [Led::matrix]
+    pub layout: Layout<'a>,
+    pub mode: Mode,
+    /// How fast keys fade out and splashes spread. 0 is slowest, 255 is fastest.
+    pub speed: u8,
+    pub hue: u8,
+    pub saturation: u8,
+    pub brightness: u8,
+    pub frame: FrameBuffer,
+    /// Keys in the order of the layout
+    heat: Heat,
+    events: [Option<Event>; EVENTS],
+    next_event: usize,
+}
+
+impl<'a> Reactive<'a> {
+    pub fn new(layout: Layout<'a>, mode: Mode) -> Self {
+        Reactive {
+            layout,
+            mode,
+            speed: 0x80,
+            hue: 0x00,
+            saturation: 0xFF,
+            brightness: 0xFF,
+            frame: FrameBuffer::new(),
+            heat: Heat::new(),
+            events: [None; EVENTS],
+            next_event: 0,
+        }
+    }
+
+    /// Report that a key was pressed or released. Keys not in the layout are ignored. A key
+    /// starts fading out when it is released.
+    pub fn key_event(&mut self, row: u8, col: u8, pressed: bool, timestamp_ms: u32) {
+        let Some(index) = self
+            .layout
+            .index_of(row, col)
+            .filter(|&index| index < MAX_KEYS)
+        else {
+            return;
+        };
+        if !pressed {
+            self.heat.release(index, timestamp_ms);
+            return;
+        }
+        self.heat.press(index, timestamp_ms);
+
+        let led = self.layout.leds[index];
+        self.events[self.next_event] = Some(Event {
+            x: led.x,
+            y: led.y,
+            timestamp: timestamp_ms,
+        });
+        self.next_event = (self.next_event + 1) % EVENTS;
+    }
+
+    /// Render the keys of one controller of the layout and write them to the device, see
+    /// [Output]
+    pub fn tick<O: Output>(
+        &mut self,
+        output: &mut O,
+        controller: u8,
+        now_ms: u32,
+    ) -> Result<(), O::Error> {
+        self.render(controller, now_ms);
+        self.frame.flush(output)
+    }
+
+    /// Render the keys of one controller of the layout into [frame](Self::frame), without
+    /// writing it to the device
+    pub fn render(&mut self, controller: u8, now_ms: u32) {
+        self.expire(now_ms);
+        self.frame.clear();
+        let leds = self.layout.leds.iter().take(MAX_KEYS).enumerate();
+        for (index, key) in leds.filter(|(_, led)| led.controller == controller) {
+            let own = self.heat.level(index, now_ms, self.speed);
+            let spread = match self.mode {
+                Mode::PressedKey => 0,
+                Mode::Splash | Mode::Ripple => self
+                    .events
+                    .iter()
+                    .flatten()
+                    .map(|event| self.spread(key, event, now_ms))
+                    .max()
+                    .unwrap_or(0),
+            };
+            let intensity = own.max(spread);
+            let color = Hsv::new(
+                self.hue,
+                self.saturation,
+                scale8(intensity, self.brightness),
+            );
+            self.frame
+                .set_color(key.register, self.layout.order, color.to_rgb());
+        }
+    }
+
+    /// Forget presses and events that faded out, so that they don't light up again when the
+    /// timer wraps around
+    fn expire(&mut self, now_ms: u32) {
+        self.heat.expire(now_ms, self.speed);
+        for event in self.events.iter_mut() {
+            if event.is_some_and(|event| fade(now_ms, event.timestamp, self.speed) == 0) {
+                *event = None;
+            }
+        }
+    }
+
+    /// Remaining brightness of something that lit up at `since`
+    fn fade(&self, now_ms: u32, since: u32) -> u8 {
+        fade(now_ms, since, self.speed)
+    }
+
+    /// Brightness of a key lit by the splash or ripple of a key press
+    fn spread(&self, key: &Led, event: &Event, now_ms: u32) -> u8 {
+        let brightness = self.fade(now_ms, event.timestamp);
+        if brightness == 0 {
+            return 0;
+        }
+        // At full speed the radius grows by one key unit in 32ms
+        let age = now_ms.wrapping_sub(event.timestamp);
+        let radius = age.saturating_mul(self.speed as u32 + 1).saturating_mul(25) / 2048;
+        let distance = distance(key.x, key.y, event.x, event.y);
+        match self.mode {
+            Mode::Splash if distance <= radius => brightness,
+            Mode::Ripple if distance.abs_diff(radius) <= 50 => brightness,
+            _ => 0,
+        }
+    }
+}
+
+/// Remaining brightness of something that lit up at `since`. Timestamps from the future, more
+/// than half of the timer range ahead, count as faded out.
+fn fade(now_ms: u32, since: u32, speed: u8) -> u8 {
+    let age = now_ms.wrapping_sub(since);
+    if age > u32::MAX / 2 {
+        return 0;
+    }
+    let faded = age.saturating_mul(speed as u32 + 1) / 256;
+    0xFF - faded.min(0xFF) as u8
+}
+
+/// Approximate distance between two positions of a [Layout]
+fn distance(x1: u16, y1: u16, x2: u16, y2: u16) -> u32 {
+    let dx = x1.abs_diff(x2) as u32;
+    let dy = y1.abs_diff(y2) as u32;
+    dx.max(dy) + dx.min(dy) / 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::ColorOrder;
+    use crate::layout::{build, Wiring, U};
+    use crate::{register, Address};
+
+    /// Two keys next to each other and one below, the key on the right on a second controller
+    const LEDS: [Led; 3] = build(
+        &[(100, &[U, U]), (100, &[U])],
+        &[
+            Wiring::new(0, 0, 0).key(0, 0),
+            Wiring::new(1, 0, 0).key(0, 1),
+            Wiring::new(0, 1, 0).key(1, 0),
+        ],
+    );
+
+    fn reactive(mode: Mode) -> Reactive<'static> {
+        let mut reactive = Reactive::new(
+            Layout {
+                leds: &LEDS,
+                controllers: &[Address::MIN, Address::MAX],
+                order: ColorOrder::Mono,
+                width: 200,
+                height: 200,
+                unit_um: 19_050,
+            },
+            mode,
+        );
+        // Fades out in 0xFF * 256 / 256 ms
+        reactive.speed = 0xFF;
+        reactive.saturation = 0x00;
+        reactive
+    }
+
+    fn levels(reactive: &mut Reactive, now_ms: u32) -> [u8; 3] {
+        let mut levels = [0; 3];
+        for controller in 0..2 {
+            reactive.render(controller, now_ms);
+            for (level, led) in levels.iter_mut().zip(LEDS.iter()) {
+                if led.controller == controller {
+                    *level = reactive.frame.get(led.register);
+                }
+            }
+        }
+        levels
+    }
+
+    #[test]
+    fn pressed_key_fades_after_release() {
+        let mut reactive = reactive(Mode::PressedKey);
+        reactive.key_event(0, 1, true, 1000);
+        assert_eq!(levels(&mut reactive, 1000), [0, 0xFF, 0]);
+        // Rendering a controller leaves out the LEDs of the others
+        reactive.render(0, 1000);
+        assert_eq!(reactive.frame.get(register(0, 0)), 0);
+        // Held keys stay lit
+        assert_eq!(levels(&mut reactive, 5000), [0, 0xFF, 0]);
+        reactive.key_event(0, 1, false, 5000);
+        assert_eq!(levels(&mut reactive, 5100)[1], 0xFF - 100);
+        assert_eq!(levels(&mut reactive, 5300), [0, 0, 0]);
+        // Unknown keys are ignored
+        reactive.key_event(5, 5, true, 5300);
+        assert_eq!(levels(&mut reactive, 5300), [0, 0, 0]);
+    }
+
+    #[test]
+    fn faded_presses_stay_dark_when_the_timer_wraps() {
+        for mode in [Mode::PressedKey, Mode::Splash, Mode::Ripple] {
+            let mut reactive = reactive(mode);
+            reactive.key_event(0, 0, true, 10);
+            reactive.key_event(0, 0, false, 10);
+            assert_ne!(levels(&mut reactive, 10), [0, 0, 0]);
+            assert_eq!(levels(&mut reactive, 1000), [0, 0, 0]);
+            assert!(reactive.heat.lit_at.iter().all(Option::is_none));
+            assert!(reactive.events.iter().all(Option::is_none));
+            // The same times again after the counter wrapped around
+            assert_eq!(levels(&mut reactive, 1 << 31), [0, 0, 0]);
+            assert_eq!(levels(&mut reactive, 10), [0, 0, 0]);
+            assert_eq!(levels(&mut reactive, 20), [0, 0, 0]);
+        }
+    }
+
+    #[test]
+    fn splash_spreads_across_controllers() {
+        let mut reactive = reactive(Mode::Splash);
+        reactive.key_event(0, 0, true, u32::MAX - 10);
+        reactive.key_event(0, 0, false, u32::MAX - 10);
+        let [own, right, below] = levels(&mut reactive, u32::MAX - 10);
+        assert_eq!((own, right, below), (0xFF, 0, 0));
+        let [_, right, below] = levels(&mut reactive, (u32::MAX - 10).wrapping_add(32));
+        assert_eq!((right, below), (0xFF - 32, 0xFF - 32));
+    }
+
+    #[test]
+    fn ripple_passes() {
+        let mut reactive = reactive(Mode::Ripple);
+        reactive.key_event(1, 0, true, 0);
+        assert_eq!(levels(&mut reactive, 0), [0, 0, 0xFF]);
+        // The ring is a key wide, the key diagonally up is 150 away
+        assert_eq!(levels(&mut reactive, 32), [0xFF - 32, 0xFF - 32, 0xFF]);
+        assert_eq!(levels(&mut reactive, 64), [0, 0xFF - 64, 0xFF]);
+        assert_eq!(levels(&mut reactive, 96), [0, 0, 0xFF]);
+    }
+
+    #[test]
+    fn distances() {
+        assert_eq!(distance(0, 0, 0, 0), 0);
+        assert_eq!(distance(0, 0, 300, 0), 300);
+        assert_eq!(distance(200, 500, 200, 100), 400);
+        assert_eq!(distance(0, 0, 200, 200), 300);
+    }
+}
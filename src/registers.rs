@@ -0,0 +1,724 @@
+use crate::{addresses, PwmFreq, SwSetting, CS_PINS, SW_PINS};
+use core::fmt;
+
+/// A single byte register in the function page, see [get_register](crate::IS31FL3743::get_register)
+/// and [set_register](crate::IS31FL3743::set_register).
+pub trait Register: Copy {
+    /// Address of the register inside of the function page
+    const ADDRESS: u8;
+
+    /// Decode the raw register value
+    fn from_bits(bits: u8) -> Self;
+
+    /// Encode into the raw register value
+    fn into_bits(self) -> u8;
+}
+
+macro_rules! register {
+    ($name:ident, $address:expr) => {
+        impl Register for $name {
+            const ADDRESS: u8 = $address;
+
+            fn from_bits(bits: u8) -> Self {
+                $name::from_bits(bits)
+            }
+
+            fn into_bits(self) -> u8 {
+                $name::into_bits(self)
+            }
+        }
+    };
+}
+
+/// Configuration register (00h)
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Configuration(u8);
+register!(Configuration, addresses::CONFIG_REGISTER);
+
+impl Configuration {
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn into_bits(self) -> u8 {
+        self.0
+    }
+
+    /// SW scanning setting. `None` if the register holds a value not listed in the datasheet.
+    pub const fn sw_setting(&self) -> Option<SwSetting> {
+        SwSetting::from_bits(self.0 >> 4)
+    }
+
+    pub const fn set_sw_setting(&mut self, setting: SwSetting) {
+        self.0 = (self.0 & 0x0F) | (setting as u8) << 4;
+    }
+
+    /// Voltage level of the I2C pins
+    pub const fn logic_level(&self) -> LogicLevel {
+        if self.0 & 0b1000 == 0 {
+            LogicLevel::Low
+        } else {
+            LogicLevel::High
+        }
+    }
+
+    pub const fn set_logic_level(&mut self, level: LogicLevel) {
+        self.0 = (self.0 & !0b1000) | (level as u8) << 3;
+    }
+
+    /// Which detection, if any, is run on the next scan
+    pub const fn open_short_detect(&self) -> OpenShortDetect {
+        OpenShortDetect::from_bits((self.0 >> 1) & 0b11)
+    }
+
+    pub const fn set_open_short_detect(&mut self, detect: OpenShortDetect) {
+        self.0 = (self.0 & !0b110) | (detect as u8) << 1;
+    }
+
+    /// Whether the chip is in software shutdown mode
+    pub const fn shutdown(&self) -> bool {
+        self.0 & 0b1 == 0
+    }
+
+    pub const fn set_shutdown(&mut self, shutdown: bool) {
+        self.0 = (self.0 & !0b1) | if shutdown { 0 } else { 1 };
+    }
+}
+
+impl fmt::Debug for Configuration {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Configuration")
+            .field("sw_setting", &self.sw_setting())
+            .field("logic_level", &self.logic_level())
+            .field("open_short_detect", &self.open_short_detect())
+            .field("shutdown", &self.shutdown())
+            .finish()
+    }
+}
+
+/// Global current control register (01h)
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct GlobalCurrent(u8);
+register!(GlobalCurrent, addresses::CURRENT_REGISTER);
+
+impl GlobalCurrent {
+    pub const fn new(current: u8) -> Self {
+        Self(current)
+    }
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn into_bits(self) -> u8 {
+        self.0
+    }
+
+    /// Output current of all CS pins. 0 is none, 255 is the maximum set by R_EXT
+    pub const fn current(&self) -> u8 {
+        self.0
+    }
+
+    pub const fn set_current(&mut self, current: u8) {
+        self.0 = current;
+    }
+}
+
+impl fmt::Debug for GlobalCurrent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("GlobalCurrent")
+            .field("current", &self.current())
+            .finish()
+    }
+}
+
+/// Pull down/up resistor selection register (02h)
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct PullResistors(u8);
+register!(PullResistors, addresses::PULL_UP_REGISTER);
+
+impl PullResistors {
+    pub const fn new(sw_pull_down: Resistor, cs_pull_up: Resistor) -> Self {
+        Self((sw_pull_down as u8) << 4 | cs_pull_up as u8)
+    }
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn into_bits(self) -> u8 {
+        self.0
+    }
+
+    /// Pull down resistor of the SW pins
+    pub const fn sw_pull_down(&self) -> Resistor {
+        Resistor::from_bits(self.0 >> 4)
+    }
+
+    pub const fn set_sw_pull_down(&mut self, resistor: Resistor) {
+        self.0 = (self.0 & 0x0F) | (resistor as u8) << 4;
+    }
+
+    /// Pull up resistor of the CS pins
+    pub const fn cs_pull_up(&self) -> Resistor {
+        Resistor::from_bits(self.0)
+    }
+
+    pub const fn set_cs_pull_up(&mut self, resistor: Resistor) {
+        self.0 = (self.0 & 0xF0) | resistor as u8;
+    }
+}
+
+impl fmt::Debug for PullResistors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PullResistors")
+            .field("sw_pull_down", &self.sw_pull_down())
+            .field("cs_pull_up", &self.cs_pull_up())
+            .finish()
+    }
+}
+
+/// Open/short detection result registers (03h~23h). Read only.
+///
+/// Every SW row takes three registers, the lowest bit of the first register is CS1. A set bit
+/// marks a faulty LED.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct OpenShort([u8; OpenShort::LEN]);
+
+impl OpenShort {
+    /// Address of the first register
+    pub const ADDRESS: u8 = addresses::OPEN_SHORT_REGISTER;
+    /// Number of registers
+    pub const LEN: usize = 3 * SW_PINS as usize;
+
+    pub const fn from_bits(bits: [u8; Self::LEN]) -> Self {
+        Self(bits)
+    }
+
+    pub const fn into_bits(self) -> [u8; Self::LEN] {
+        self.0
+    }
+
+    /// Whether the LED at the zero based SW row and CS column was detected as faulty
+    pub const fn is_faulty(&self, sw: u8, cs: u8) -> bool {
+        if sw >= SW_PINS || cs >= CS_PINS {
+            return false;
+        }
+        self.0[sw as usize * 3 + cs as usize / 8] & (1 << (cs % 8)) != 0
+    }
+
+    pub const fn set_faulty(&mut self, sw: u8, cs: u8, faulty: bool) {
+        if sw >= SW_PINS || cs >= CS_PINS {
+            return;
+        }
+        let byte = sw as usize * 3 + cs as usize / 8;
+        let bit = 1 << (cs % 8);
+        if faulty {
+            self.0[byte] |= bit;
+        } else {
+            self.0[byte] &= !bit;
+        }
+    }
+
+    /// Iterate over the zero based SW row and CS column of every faulty LED
+    pub fn faults(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        (0..SW_PINS)
+            .flat_map(|sw| (0..CS_PINS).map(move |cs| (sw, cs)))
+            .filter(|&(sw, cs)| self.is_faulty(sw, cs))
+    }
+}
+
+impl fmt::Debug for OpenShort {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.faults()).finish()
+    }
+}
+
+/// Temperature status register (24h)
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Temperature(u8);
+register!(Temperature, addresses::TEMPERATURE_REGISTER);
+
+impl Temperature {
+    pub const fn new(point: TemperaturePoint, roll_off: ThermalRollOff) -> Self {
+        Self((point as u8) << 2 | roll_off as u8)
+    }
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn into_bits(self) -> u8 {
+        self.0
+    }
+
+    /// Temperature at which the thermal roll off kicks in
+    pub const fn point(&self) -> TemperaturePoint {
+        TemperaturePoint::from_bits(self.0 >> 2)
+    }
+
+    pub const fn set_point(&mut self, point: TemperaturePoint) {
+        self.0 = (self.0 & !0b1100) | (point as u8) << 2;
+    }
+
+    /// Output current once the temperature point is reached
+    pub const fn roll_off(&self) -> ThermalRollOff {
+        ThermalRollOff::from_bits(self.0)
+    }
+
+    pub const fn set_roll_off(&mut self, roll_off: ThermalRollOff) {
+        self.0 = (self.0 & !0b11) | roll_off as u8;
+    }
+}
+
+impl fmt::Debug for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Temperature")
+            .field("point", &self.point())
+            .field("roll_off", &self.roll_off())
+            .finish()
+    }
+}
+
+/// Spread spectrum register (25h)
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct SpreadSpectrum(u8);
+register!(SpreadSpectrum, addresses::SPREAD_SPECTRUM_REGISTER);
+
+impl SpreadSpectrum {
+    pub const fn new(enabled: bool, range: SpreadRange, cycle: SpreadCycle) -> Self {
+        Self((enabled as u8) << 4 | (range as u8) << 2 | cycle as u8)
+    }
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn into_bits(self) -> u8 {
+        self.0
+    }
+
+    pub const fn enabled(&self) -> bool {
+        self.0 & 0b1_0000 != 0
+    }
+
+    pub const fn set_enabled(&mut self, enabled: bool) {
+        self.0 = (self.0 & !0b1_0000) | (enabled as u8) << 4;
+    }
+
+    pub const fn range(&self) -> SpreadRange {
+        SpreadRange::from_bits(self.0 >> 2)
+    }
+
+    pub const fn set_range(&mut self, range: SpreadRange) {
+        self.0 = (self.0 & !0b1100) | (range as u8) << 2;
+    }
+
+    pub const fn cycle(&self) -> SpreadCycle {
+        SpreadCycle::from_bits(self.0)
+    }
+
+    pub const fn set_cycle(&mut self, cycle: SpreadCycle) {
+        self.0 = (self.0 & !0b11) | cycle as u8;
+    }
+}
+
+impl fmt::Debug for SpreadSpectrum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SpreadSpectrum")
+            .field("enabled", &self.enabled())
+            .field("range", &self.range())
+            .field("cycle", &self.cycle())
+            .finish()
+    }
+}
+
+/// Reset register (2Fh). Writing the reset value restores all registers to their defaults.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Reset(u8);
+register!(Reset, addresses::RESET_REGISTER);
+
+impl Reset {
+    /// The only value that triggers a reset
+    pub const fn new() -> Self {
+        Self(addresses::RESET)
+    }
+
+    pub const fn from_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub const fn into_bits(self) -> u8 {
+        self.0
+    }
+
+    /// Whether writing this value resets the chip
+    pub const fn is_reset(&self) -> bool {
+        self.0 == addresses::RESET
+    }
+}
+
+impl Default for Reset {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for Reset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Reset")
+            .field("is_reset", &self.is_reset())
+            .finish()
+    }
+}
+
+/// Voltage thresholds of the I2C pins
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum LogicLevel {
+    /// 1.4V high, 0.4V low
+    Low = 0,
+    /// 2.4V high, 0.6V low
+    High = 1,
+}
+
+/// Open/short detection mode
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum OpenShortDetect {
+    Disabled = 0b00,
+    Open = 0b01,
+    Short = 0b10,
+}
+
+impl OpenShortDetect {
+    const fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b01 => Self::Open,
+            0b10 => Self::Short,
+            _ => Self::Disabled,
+        }
+    }
+}
+
+/// Pull down resistor of the SW pins and pull up resistor of the CS pins
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Resistor {
+    None = 0b000,
+    /// 0.5kΩ, only while the SW pin is off
+    R0k5 = 0b001,
+    /// 1.0kΩ, only while the SW pin is off
+    R1k = 0b010,
+    /// 2.0kΩ, only while the SW pin is off. The default.
+    R2k = 0b011,
+    /// 1.0kΩ, all the time
+    R1kAlways = 0b100,
+    /// 2.0kΩ, all the time
+    R2kAlways = 0b101,
+    /// 4.0kΩ, all the time
+    R4kAlways = 0b110,
+    /// 8.0kΩ, all the time
+    R8kAlways = 0b111,
+}
+
+impl Resistor {
+    const fn from_bits(bits: u8) -> Self {
+        match bits & 0b111 {
+            0b000 => Self::None,
+            0b001 => Self::R0k5,
+            0b010 => Self::R1k,
+            0b011 => Self::R2k,
+            0b100 => Self::R1kAlways,
+            0b101 => Self::R2kAlways,
+            0b110 => Self::R4kAlways,
+            _ => Self::R8kAlways,
+        }
+    }
+}
+
+/// Temperature at which the thermal roll off kicks in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum TemperaturePoint {
+    /// 140°C, the default
+    T140 = 0b00,
+    T120 = 0b01,
+    T100 = 0b10,
+    T90 = 0b11,
+}
+
+impl TemperaturePoint {
+    const fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::T140,
+            0b01 => Self::T120,
+            0b10 => Self::T100,
+            _ => Self::T90,
+        }
+    }
+}
+
+/// Percentage of the output current left after the temperature point is reached
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ThermalRollOff {
+    /// 100%, the default
+    P100 = 0b00,
+    P75 = 0b01,
+    P55 = 0b10,
+    P30 = 0b11,
+}
+
+impl ThermalRollOff {
+    const fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::P100,
+            0b01 => Self::P75,
+            0b10 => Self::P55,
+            _ => Self::P30,
+        }
+    }
+}
+
+/// Spread spectrum range
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SpreadRange {
+    /// ±5%, the default
+    R5 = 0b00,
+    /// ±15%
+    R15 = 0b01,
+    /// ±24%
+    R24 = 0b10,
+    /// ±34%
+    R34 = 0b11,
+}
+
+impl SpreadRange {
+    const fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::R5,
+            0b01 => Self::R15,
+            0b10 => Self::R24,
+            _ => Self::R34,
+        }
+    }
+}
+
+/// Spread spectrum cycle time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[repr(u8)]
+pub enum SpreadCycle {
+    /// 1980µs, the default
+    C1980us = 0b00,
+    C1200us = 0b01,
+    C820us = 0b10,
+    C660us = 0b11,
+}
+
+impl SpreadCycle {
+    const fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => Self::C1980us,
+            0b01 => Self::C1200us,
+            0b10 => Self::C820us,
+            _ => Self::C660us,
+        }
+    }
+}
+
+/// Everything the chip is configured to, decoded. Returned by
+/// [read_config](crate::IS31FL3743::read_config).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChipConfig {
+    /// `None` if the SWS field holds a reserved value
+    pub sw_setting: Option<SwSetting>,
+    pub logic_level: LogicLevel,
+    pub open_short_detect: OpenShortDetect,
+    /// Software shutdown
+    pub shutdown: bool,
+    pub global_current: u8,
+    pub sw_pull_down: Resistor,
+    pub cs_pull_up: Resistor,
+    pub spread_spectrum: bool,
+    pub spread_range: SpreadRange,
+    pub spread_cycle: SpreadCycle,
+    pub temperature_point: TemperaturePoint,
+    pub thermal_roll_off: ThermalRollOff,
+    /// `None` if the PWM configuration doesn't match a known frequency
+    pub pwm_freq: Option<PwmFreq>,
+}
+
+impl ChipConfig {
+    /// Decode the registers of the function page
+    pub const fn new(
+        configuration: Configuration,
+        global_current: GlobalCurrent,
+        pull_resistors: PullResistors,
+        spread_spectrum: SpreadSpectrum,
+        temperature: Temperature,
+        pwm_freq: Option<PwmFreq>,
+    ) -> Self {
+        ChipConfig {
+            sw_setting: configuration.sw_setting(),
+            logic_level: configuration.logic_level(),
+            open_short_detect: configuration.open_short_detect(),
+            shutdown: configuration.shutdown(),
+            global_current: global_current.current(),
+            sw_pull_down: pull_resistors.sw_pull_down(),
+            cs_pull_up: pull_resistors.cs_pull_up(),
+            spread_spectrum: spread_spectrum.enabled(),
+            spread_range: spread_spectrum.range(),
+            spread_cycle: spread_spectrum.cycle(),
+            temperature_point: temperature.point(),
+            thermal_roll_off: temperature.roll_off(),
+            pwm_freq,
+        }
+    }
+}
+
+/// One setting per line, for debug shells
+impl fmt::Display for ChipConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "SW setting:        {:?}", self.sw_setting)?;
+        writeln!(f, "Logic level:       {:?}", self.logic_level)?;
+        writeln!(f, "Open/short detect: {:?}", self.open_short_detect)?;
+        writeln!(f, "Shutdown:          {}", self.shutdown)?;
+        writeln!(f, "Global current:    0x{:02X}", self.global_current)?;
+        writeln!(f, "SW pull down:      {:?}", self.sw_pull_down)?;
+        writeln!(f, "CS pull up:        {:?}", self.cs_pull_up)?;
+        writeln!(
+            f,
+            "Spread spectrum:   {} ({:?}, {:?})",
+            self.spread_spectrum, self.spread_range, self.spread_cycle
+        )?;
+        writeln!(
+            f,
+            "Temperature:       {:?}, {:?}",
+            self.temperature_point, self.thermal_roll_off
+        )?;
+        write!(f, "PWM frequency:     {:?}", self.pwm_freq)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn addresses() {
+        assert_eq!(Configuration::ADDRESS, 0x00);
+        assert_eq!(GlobalCurrent::ADDRESS, 0x01);
+        assert_eq!(PullResistors::ADDRESS, 0x02);
+        assert_eq!(OpenShort::ADDRESS, 0x03);
+        assert_eq!(OpenShort::ADDRESS as usize + OpenShort::LEN - 1, 0x23);
+        assert_eq!(Temperature::ADDRESS, 0x24);
+        assert_eq!(SpreadSpectrum::ADDRESS, 0x25);
+        assert_eq!(Reset::ADDRESS, 0x2F);
+    }
+
+    #[test]
+    fn configuration() {
+        let config = Configuration::from_bits(0b0111_1101);
+        assert_eq!(config.sw_setting(), Some(SwSetting::Sw1Sw4));
+        assert_eq!(config.logic_level(), LogicLevel::High);
+        assert_eq!(config.open_short_detect(), OpenShortDetect::Short);
+        assert!(!config.shutdown());
+        assert_eq!(Configuration::from_bits(0xB0).sw_setting(), None);
+
+        // Every setter only touches its own field
+        let mut config = Configuration::from_bits(0x00);
+        assert!(config.shutdown());
+        config.set_shutdown(false);
+        assert_eq!(config.into_bits(), 0b0000_0001);
+        config.set_open_short_detect(OpenShortDetect::Open);
+        assert_eq!(config.into_bits(), 0b0000_0011);
+        config.set_logic_level(LogicLevel::High);
+        assert_eq!(config.into_bits(), 0b0000_1011);
+        config.set_sw_setting(SwSetting::NoScan);
+        assert_eq!(config.into_bits(), 0b1010_1011);
+        config.set_open_short_detect(OpenShortDetect::Disabled);
+        config.set_logic_level(LogicLevel::Low);
+        config.set_shutdown(true);
+        assert_eq!(config.into_bits(), 0b1010_0000);
+        config.set_sw_setting(SwSetting::Sw1Sw11);
+        assert_eq!(config.into_bits(), 0x00);
+    }
+
+    #[test]
+    fn pull_resistors() {
+        let mut pull = PullResistors::new(Resistor::R8kAlways, Resistor::R0k5);
+        assert_eq!(pull.into_bits(), 0x71);
+        assert_eq!(pull.sw_pull_down(), Resistor::R8kAlways);
+        assert_eq!(pull.cs_pull_up(), Resistor::R0k5);
+        pull.set_cs_pull_up(Resistor::R4kAlways);
+        pull.set_sw_pull_down(Resistor::None);
+        assert_eq!(pull.into_bits(), 0x06);
+        // The reserved top bit of each field is ignored
+        assert_eq!(PullResistors::from_bits(0x8B).cs_pull_up(), Resistor::R2k);
+    }
+
+    #[test]
+    fn open_short() {
+        let mut bits = [0x00; OpenShort::LEN];
+        bits[4] = 0b10;
+        bits[OpenShort::LEN - 1] = 0b10;
+        let mut faults = OpenShort::from_bits(bits);
+        // Three registers per SW row, CS1 in the lowest bit
+        assert!(faults.is_faulty(1, 9));
+        assert!(faults.is_faulty(10, 17));
+        assert!(!faults.is_faulty(1, 8));
+        assert!(!faults.is_faulty(11, 0));
+        assert!(!faults.is_faulty(0, 18));
+        assert_eq!(
+            faults.faults().collect::<std::vec::Vec<_>>(),
+            [(1, 9), (10, 17)]
+        );
+
+        faults.set_faulty(0, 0, true);
+        faults.set_faulty(1, 9, false);
+        faults.set_faulty(11, 0, true);
+        faults.set_faulty(0, 18, true);
+        let mut expected = [0x00; OpenShort::LEN];
+        expected[0] = 0b1;
+        expected[OpenShort::LEN - 1] = 0b10;
+        assert_eq!(faults.into_bits(), expected);
+    }
+
+    #[test]
+    fn temperature() {
+        let mut temperature = Temperature::new(TemperaturePoint::T100, ThermalRollOff::P30);
+        assert_eq!(temperature.into_bits(), 0b1011);
+        assert_eq!(temperature.point(), TemperaturePoint::T100);
+        assert_eq!(temperature.roll_off(), ThermalRollOff::P30);
+        temperature.set_point(TemperaturePoint::T140);
+        assert_eq!(temperature.into_bits(), 0b0011);
+        temperature.set_roll_off(ThermalRollOff::P75);
+        assert_eq!(temperature.into_bits(), 0b0001);
+        temperature.set_point(TemperaturePoint::T90);
+        assert_eq!(temperature.point(), TemperaturePoint::T90);
+        assert_eq!(temperature.roll_off(), ThermalRollOff::P75);
+    }
+
+    #[test]
+    fn spread_spectrum() {
+        let mut spread = SpreadSpectrum::new(true, SpreadRange::R24, SpreadCycle::C660us);
+        assert_eq!(spread.into_bits(), 0b1_1011);
+        assert!(spread.enabled());
+        assert_eq!(spread.range(), SpreadRange::R24);
+        assert_eq!(spread.cycle(), SpreadCycle::C660us);
+        spread.set_enabled(false);
+        assert_eq!(spread.into_bits(), 0b0_1011);
+        spread.set_range(SpreadRange::R15);
+        spread.set_cycle(SpreadCycle::C1980us);
+        assert_eq!(spread.into_bits(), 0b0_0100);
+    }
+
+    #[test]
+    fn reset() {
+        assert!(Reset::new().is_reset());
+        assert_eq!(Reset::default().into_bits(), 0xAE);
+        assert!(!Reset::from_bits(0x00).is_reset());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::snapshot::Snapshot;
+use crate::{Error, IS31FL3743};
+use embedded_hal::delay::DelayNs;
+use embedded_hal::digital::OutputPin;
+use embedded_hal::i2c::I2c;
+
+/// Time the controller needs after SDB goes high before it accepts commands
+const WAKE_DELAY_MS: u32 = 1;
+
+/// What is done after waking up from hardware shutdown
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recovery {
+    /// Nothing, the controller stayed powered and kept its registers
+    Keep,
+    /// Take a [Snapshot] before shutting down, reset and restore it after waking up, see
+    /// [resume](IS31FL3743::resume). Works even if the controller lost power in between.
+    Restore,
+    /// Run [setup](IS31FL3743::setup) after waking up
+    Setup,
+}
+
+/// Power state of a controller with an SDB pin, see [set_power](HardwareShutdown::set_power)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PowerState {
+    On,
+    /// Software shutdown. The registers stay accessible over I2C and waking up is a single
+    /// register write.
+    Standby,
+    /// Hardware shutdown through SDB. Draws the least current, the registers are brought back
+    /// according to the [Recovery].
+    Off,
+}
+
+/// A device that owns its SDB (shutdown) pin.
+///
+/// The pin is assumed to be high, i.e. the controller running, when it is handed over.
+pub struct HardwareShutdown<I2C, SDB> {
+    pub device: IS31FL3743<I2C>,
+    pub sdb: SDB,
+    pub recovery: Recovery,
+    state: PowerState,
+    snapshot: Option<Snapshot>,
+}
+
+impl<I2C, I2cError, SDB> HardwareShutdown<I2C, SDB>
+where
+    I2C: I2c<Error = I2cError>,
+    SDB: OutputPin,
+{
+    pub fn new(device: IS31FL3743<I2C>, sdb: SDB, recovery: Recovery) -> Self {
+        HardwareShutdown {
+            device,
+            sdb,
+            recovery,
+            state: PowerState::On,
+            snapshot: None,
+        }
+    }
+
+    /// Give back the device and the pin
+    pub fn release(self) -> (IS31FL3743<I2C>, SDB) {
+        (self.device, self.sdb)
+    }
+
+    pub fn power_state(&self) -> PowerState {
+        self.state
+    }
+
+    /// Pull SDB low. With [Recovery::Restore] the registers are read first.
+    pub fn hw_shutdown(&mut self) -> Result<(), Error<I2cError>> {
+        if self.state == PowerState::Off {
+            return Ok(());
+        }
+        if self.recovery == Recovery::Restore {
+            self.snapshot = Some(self.device.snapshot()?);
+        }
+        self.sdb.set_low().map_err(|_| Error::Pin)?;
+        self.state = PowerState::Off;
+        Ok(())
+    }
+
+    /// Pull SDB high, wait for the controller to start and recover its registers. The display
+    /// is on afterwards, even if it was in software shutdown before.
+    pub fn hw_wake<DEL: DelayNs>(&mut self, delay: &mut DEL) -> Result<(), Error<I2cError>> {
+        self.wake(delay, PowerState::On)
+    }
+
+    /// Leave hardware shutdown into `state`. The controller stays in software shutdown until
+    /// its registers are back, so nothing lights up on the way to [PowerState::Standby].
+    fn wake<DEL: DelayNs>(
+        &mut self,
+        delay: &mut DEL,
+        state: PowerState,
+    ) -> Result<(), Error<I2cError>> {
+        if self.state != PowerState::Off {
+            return Ok(());
+        }
+        // The other recoveries start with a reset, which enters software shutdown as well
+        if self.recovery == Recovery::Keep {
+            self.device.shutdown(true)?;
+        }
+        self.sdb.set_high().map_err(|_| Error::Pin)?;
+        delay.delay_ms(WAKE_DELAY_MS);
+        match (self.recovery, self.snapshot.take()) {
+            (Recovery::Keep, _) => {}
+            (Recovery::Restore, Some(mut snapshot)) => {
+                snapshot.configuration.set_shutdown(true);
+                self.device.resume(delay, &snapshot)?;
+            }
+            // The PWM registers are still cleared while setup turns the display on
+            (Recovery::Restore, None) | (Recovery::Setup, _) => self.device.setup(delay)?,
+        }
+        self.device.shutdown(state == PowerState::Standby)?;
+        self.state = state;
+        Ok(())
+    }
+
+    /// Move to another power state, using software or hardware shutdown as needed
+    pub fn set_power<DEL: DelayNs>(
+        &mut self,
+        delay: &mut DEL,
+        state: PowerState,
+    ) -> Result<(), Error<I2cError>> {
+        match (self.state, state) {
+            (PowerState::On, PowerState::On)
+            | (PowerState::Standby, PowerState::Standby)
+            | (PowerState::Off, PowerState::Off) => {}
+            (_, PowerState::Off) => self.hw_shutdown()?,
+            (PowerState::Off, _) => self.wake(delay, state)?,
+            (PowerState::On, PowerState::Standby) => self.device.shutdown(true)?,
+            (PowerState::Standby, PowerState::On) => self.device.shutdown(false)?,
+        }
+        self.state = state;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::addresses::{CONFIG_REGISTER, RESET, RESET_REGISTER};
+    use crate::mock::{device, Bus, NoDelay, Pin};
+    use crate::PwmFreq;
+
+    fn shutdown(recovery: Recovery) -> HardwareShutdown<Bus, Pin> {
+        let mut device = device();
+        device.setup(&mut NoDelay).unwrap();
+        device.fill(0x40).unwrap();
+        HardwareShutdown::new(device, Pin { high: true }, recovery)
+    }
+
+    /// Whether the display was turned on by any write since the log was cleared
+    fn turned_on(bus: &Bus) -> bool {
+        bus.writes.iter().any(|&(page, register, value)| {
+            page == 2 && register == CONFIG_REGISTER && value & 1 != 0
+        })
+    }
+
+    fn is_on(bus: &Bus) -> bool {
+        bus.function(CONFIG_REGISTER) & 1 != 0
+    }
+
+    #[test]
+    fn wakes_into_standby_without_turning_on() {
+        for recovery in [Recovery::Keep, Recovery::Restore, Recovery::Setup] {
+            let mut sdb = shutdown(recovery);
+            sdb.set_power(&mut NoDelay, PowerState::Off).unwrap();
+            assert!(!sdb.sdb.high);
+            sdb.device.i2c.writes.clear();
+            sdb.set_power(&mut NoDelay, PowerState::Standby).unwrap();
+            assert!(sdb.sdb.high);
+            assert_eq!(sdb.power_state(), PowerState::Standby);
+            assert!(!is_on(&sdb.device.i2c));
+            if recovery != Recovery::Setup {
+                assert!(!turned_on(&sdb.device.i2c), "{recovery:?}");
+            }
+            sdb.set_power(&mut NoDelay, PowerState::On).unwrap();
+            assert!(is_on(&sdb.device.i2c));
+            let pwm = if recovery == Recovery::Setup {
+                0x00
+            } else {
+                0x40
+            };
+            assert!(sdb.device.i2c.pwm().iter().all(|&value| value == pwm));
+        }
+    }
+
+    #[test]
+    fn restore_resets_first() {
+        let mut sdb = shutdown(Recovery::Restore);
+        sdb.set_power(&mut NoDelay, PowerState::Off).unwrap();
+        // The controller kept some registers that are not part of the snapshot
+        sdb.device.i2c.pages[0][0xC7] = 0x55;
+        sdb.device.i2c.writes.clear();
+        sdb.hw_wake(&mut NoDelay).unwrap();
+        assert_eq!(sdb.device.i2c.writes[0], (2, RESET_REGISTER, RESET));
+        assert_eq!(sdb.device.i2c.pages[0][0xC7], 0x00);
+        assert!(sdb.device.i2c.pwm().iter().all(|&pwm| pwm == 0x40));
+        assert!(is_on(&sdb.device.i2c));
+        assert_eq!(sdb.power_state(), PowerState::On);
+    }
+
+    #[test]
+    fn setup_keeps_the_pwm_freq() {
+        let mut sdb = shutdown(Recovery::Setup);
+        sdb.device
+            .set_pwm_freq(&mut NoDelay, PwmFreq::P977)
+            .unwrap();
+        sdb.hw_shutdown().unwrap();
+        // The controller lost power while SDB was low
+        sdb.device.i2c = Bus::new(0x20);
+        sdb.hw_wake(&mut NoDelay).unwrap();
+        assert_eq!(sdb.device.pwm_freq().unwrap(), Some(PwmFreq::P977));
+    }
+}
@@ -0,0 +1,239 @@
+use crate::registers::{OpenShort, Temperature};
+use core::fmt;
+
+/// Result of the write/read-back test of a page
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PageResult {
+    /// Number of registers that read back a different value, summed over all patterns
+    pub mismatches: u16,
+    /// First register that read back a different value
+    pub first_mismatch: Option<u8>,
+}
+
+impl PageResult {
+    pub const fn passed(&self) -> bool {
+        self.mismatches == 0
+    }
+
+    /// Compare a page read back with the pattern written
+    pub(crate) fn check(&mut self, written: &[u8; 0xC6], read: &[u8; 0xC6]) {
+        for (index, (written, read)) in written.iter().zip(read.iter()).enumerate() {
+            if written != read {
+                self.mismatches = self.mismatches.saturating_add(1);
+                self.first_mismatch.get_or_insert(index as u8 + 1);
+            }
+        }
+    }
+
+    fn to_bytes(self) -> [u8; 4] {
+        let [lo, hi] = self.mismatches.to_le_bytes();
+        [
+            lo,
+            hi,
+            self.first_mismatch.is_some() as u8,
+            self.first_mismatch.unwrap_or(0),
+        ]
+    }
+
+    fn from_bytes(buf: &[u8]) -> Self {
+        PageResult {
+            mismatches: u16::from_le_bytes([buf[0], buf[1]]),
+            first_mismatch: (buf[2] != 0).then_some(buf[3]),
+        }
+    }
+}
+
+/// Everything [self_test](crate::IS31FL3743::self_test) found out about a controller
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SelfTestReport {
+    /// The controller answered on its address. Nothing else was tested if it didn't.
+    pub present: bool,
+    pub pwm: PageResult,
+    pub scaling: PageResult,
+    /// LEDs detected as open. Positions without an LED show up here as well.
+    pub open: OpenShort,
+    /// LEDs detected as shorted
+    pub short: OpenShort,
+    /// Thermal settings read back. The chip has no over-temperature flag, a failed read
+    /// shows up as a bus error instead.
+    pub temperature: Temperature,
+}
+
+impl SelfTestReport {
+    /// Version of the serialization format, stored in the first byte
+    pub const VERSION: u8 = 1;
+    /// Length of the serialized report in bytes
+    pub const LEN: usize = 3 + 2 * 4 + 2 * OpenShort::LEN;
+
+    /// Report of a controller that didn't answer
+    pub const fn missing() -> Self {
+        SelfTestReport {
+            present: false,
+            pwm: PageResult {
+                mismatches: 0,
+                first_mismatch: None,
+            },
+            scaling: PageResult {
+                mismatches: 0,
+                first_mismatch: None,
+            },
+            open: OpenShort::from_bits([0x00; OpenShort::LEN]),
+            short: OpenShort::from_bits([0x00; OpenShort::LEN]),
+            temperature: Temperature::from_bits(0x00),
+        }
+    }
+
+    /// Present, all registers read back correctly and no LED is open or shorted. Boards with
+    /// unpopulated positions should compare [open](Self::open) against what they expect
+    /// instead.
+    pub fn passed(&self) -> bool {
+        self.present
+            && self.pwm.passed()
+            && self.scaling.passed()
+            && self.open.faults().next().is_none()
+            && self.short.faults().next().is_none()
+    }
+
+    /// Serialize for logging.
+    ///
+    /// Layout: version, present, temperature register, PWM and scaling results (mismatches as
+    /// u16 little endian, whether there is a first mismatch, first mismatch), open and short
+    /// results as read from the chip.
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut buf = [0x00; Self::LEN];
+        buf[0] = Self::VERSION;
+        buf[1] = self.present as u8;
+        buf[2] = self.temperature.into_bits();
+        buf[3..7].copy_from_slice(&self.pwm.to_bytes());
+        buf[7..11].copy_from_slice(&self.scaling.to_bytes());
+        buf[11..11 + OpenShort::LEN].copy_from_slice(&self.open.into_bits());
+        buf[11 + OpenShort::LEN..].copy_from_slice(&self.short.into_bits());
+        buf
+    }
+
+    /// Deserialize a buffer created by [to_bytes](Self::to_bytes). Returns `None` if the length
+    /// or version doesn't match.
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() != Self::LEN || buf[0] != Self::VERSION {
+            return None;
+        }
+        let mut open = [0x00; OpenShort::LEN];
+        open.copy_from_slice(&buf[11..11 + OpenShort::LEN]);
+        let mut short = [0x00; OpenShort::LEN];
+        short.copy_from_slice(&buf[11 + OpenShort::LEN..]);
+        Some(SelfTestReport {
+            present: buf[1] != 0,
+            temperature: Temperature::from_bits(buf[2]),
+            pwm: PageResult::from_bytes(&buf[3..7]),
+            scaling: PageResult::from_bytes(&buf[7..11]),
+            open: OpenShort::from_bits(open),
+            short: OpenShort::from_bits(short),
+        })
+    }
+}
+
+impl fmt::Display for SelfTestReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = if self.passed() { "PASS" } else { "FAIL" };
+        writeln!(f, "Result:      {verdict}")?;
+        writeln!(f, "Present:     {}", self.present)?;
+        if !self.present {
+            return Ok(());
+        }
+        for (name, page) in [("PWM:", self.pwm), ("Scaling:", self.scaling)] {
+            match page.first_mismatch {
+                None => writeln!(f, "{name:<12} ok")?,
+                Some(register) => writeln!(
+                    f,
+                    "{name:<12} {} mismatches, first at 0x{register:02X}",
+                    page.mismatches
+                )?,
+            }
+        }
+        writeln!(f, "Open:        {:?}", self.open)?;
+        writeln!(f, "Short:       {:?}", self.short)?;
+        write!(
+            f,
+            "Temperature: {:?}, {:?}",
+            self.temperature.point(),
+            self.temperature.roll_off()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, Bus, NoDelay};
+    use crate::registers::GlobalCurrent;
+    use crate::IS31FL3743;
+    use std::vec::Vec;
+
+    fn device() -> IS31FL3743<Bus> {
+        let mut device = mock::device();
+        device.setup(&mut NoDelay).unwrap();
+        device.fill(0x12).unwrap();
+        device.fill_scaling(&[0x34; 0xC6]).unwrap();
+        device.set_register(GlobalCurrent::new(0x56)).unwrap();
+        device
+    }
+
+    /// Everything but the open/short results, which aren't writable
+    fn writable(bus: &Bus) -> Vec<u8> {
+        let [pwm, scaling, function] = &bus.pages;
+        let results = OpenShort::ADDRESS as usize..OpenShort::ADDRESS as usize + OpenShort::LEN;
+        let function = function
+            .iter()
+            .enumerate()
+            .filter(|(register, _)| !results.contains(register))
+            .map(|(_, value)| *value);
+        pwm.iter().chain(scaling).copied().chain(function).collect()
+    }
+
+    #[test]
+    fn finds_open_and_shorted_leds() {
+        let mut device = device();
+        device.i2c.open.set_faulty(3, 4, true);
+        device.i2c.short.set_faulty(5, 6, true);
+        let before = writable(&device.i2c);
+        let report = device.self_test(&mut NoDelay).unwrap();
+        assert!(report.present);
+        assert!(report.pwm.passed() && report.scaling.passed());
+        assert_eq!(report.open.faults().collect::<Vec<_>>(), [(3, 4)]);
+        assert_eq!(report.short.faults().collect::<Vec<_>>(), [(5, 6)]);
+        assert!(!report.passed());
+        assert_eq!(writable(&device.i2c), before);
+    }
+
+    #[test]
+    fn restores_after_bus_errors() {
+        let mut device = device();
+        let before = writable(&device.i2c);
+        device.i2c.fail_at = Some(device.i2c.writes.len() + 1000);
+        assert_eq!(
+            device.self_test(&mut NoDelay),
+            Err(embedded_hal::i2c::ErrorKind::Bus)
+        );
+        assert_eq!(writable(&device.i2c), before);
+    }
+
+    #[test]
+    fn report_bytes() {
+        let mut report = SelfTestReport::missing();
+        assert_eq!(SelfTestReport::from_bytes(&report.to_bytes()), Some(report));
+        report.present = true;
+        report.pwm.check(&[0x00; 0xC6], &[0x01; 0xC6]);
+        report.open.set_faulty(10, 17, true);
+        report.temperature = Temperature::from_bits(0x25);
+        let bytes = report.to_bytes();
+        assert_eq!(
+            bytes[..7],
+            [SelfTestReport::VERSION, 1, 0x25, 0xC6, 0x00, 1, 0x01]
+        );
+        assert_eq!(SelfTestReport::from_bytes(&bytes), Some(report));
+        assert_eq!(SelfTestReport::from_bytes(&bytes[1..]), None);
+        let mut future = bytes;
+        future[0] += 1;
+        assert_eq!(SelfTestReport::from_bytes(&future), None);
+    }
+}
@@ -0,0 +1,143 @@
+use crate::registers::{Configuration, GlobalCurrent, PullResistors, SpreadSpectrum, Temperature};
+use crate::PwmFreq;
+
+/// Number of PWM or scaling registers
+const MATRIX_LEN: usize = 0xC6;
+
+/// Copy of every register of the chip that can be read back and written again.
+///
+/// Take one with [snapshot](crate::IS31FL3743::snapshot) or
+/// [suspend](crate::IS31FL3743::suspend) and write it back with
+/// [restore](crate::IS31FL3743::restore) or [resume](crate::IS31FL3743::resume). The open/short
+/// results are not included, they are read only and get regenerated by the chip.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Snapshot {
+    /// PWM page, register 0x01 first
+    pub pwm: [u8; MATRIX_LEN],
+    /// Scaling page, register 0x01 first
+    pub scaling: [u8; MATRIX_LEN],
+    pub configuration: Configuration,
+    pub global_current: GlobalCurrent,
+    pub pull_resistors: PullResistors,
+    pub temperature: Temperature,
+    pub spread_spectrum: SpreadSpectrum,
+    /// Set through test mode
+    pub pwm_freq: PwmFreq,
+}
+
+impl Snapshot {
+    /// Version of the serialization format, stored in the first byte
+    pub const VERSION: u8 = 1;
+    /// Length of the serialized snapshot in bytes
+    pub const LEN: usize = 1 + 2 * MATRIX_LEN + 6;
+
+    /// Serialize into a fixed size buffer that can be kept in RAM or written to flash.
+    ///
+    /// Layout: version, PWM page, scaling page, configuration, global current, pull resistors,
+    /// temperature, spread spectrum, PWM frequency.
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        let mut buf = [0x00; Self::LEN];
+        buf[0] = Self::VERSION;
+        buf[1..=MATRIX_LEN].copy_from_slice(&self.pwm);
+        buf[MATRIX_LEN + 1..=2 * MATRIX_LEN].copy_from_slice(&self.scaling);
+        let registers = &mut buf[2 * MATRIX_LEN + 1..];
+        registers[0] = self.configuration.into_bits();
+        registers[1] = self.global_current.into_bits();
+        registers[2] = self.pull_resistors.into_bits();
+        registers[3] = self.temperature.into_bits();
+        registers[4] = self.spread_spectrum.into_bits();
+        registers[5] = self.pwm_freq as u8;
+        buf
+    }
+
+    /// Deserialize a buffer created by [to_bytes](Self::to_bytes). Returns `None` if the length
+    /// or version doesn't match or the PWM frequency is unknown.
+    pub fn from_bytes(buf: &[u8]) -> Option<Self> {
+        if buf.len() != Self::LEN || buf[0] != Self::VERSION {
+            return None;
+        }
+        let mut pwm = [0x00; MATRIX_LEN];
+        pwm.copy_from_slice(&buf[1..=MATRIX_LEN]);
+        let mut scaling = [0x00; MATRIX_LEN];
+        scaling.copy_from_slice(&buf[MATRIX_LEN + 1..=2 * MATRIX_LEN]);
+        let registers = &buf[2 * MATRIX_LEN + 1..];
+        Some(Snapshot {
+            pwm,
+            scaling,
+            configuration: Configuration::from_bits(registers[0]),
+            global_current: GlobalCurrent::from_bits(registers[1]),
+            pull_resistors: PullResistors::from_bits(registers[2]),
+            temperature: Temperature::from_bits(registers[3]),
+            spread_spectrum: SpreadSpectrum::from_bits(registers[4]),
+            pwm_freq: PwmFreq::from_bits(registers[5])?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{device, Bus, NoDelay};
+    use crate::registers::{Resistor, SpreadCycle, SpreadRange, TemperaturePoint, ThermalRollOff};
+
+    fn snapshot() -> Snapshot {
+        let mut pwm = [0x00; MATRIX_LEN];
+        let mut scaling = [0x00; MATRIX_LEN];
+        for index in 0..MATRIX_LEN {
+            pwm[index] = index as u8;
+            scaling[index] = !(index as u8);
+        }
+        Snapshot {
+            pwm,
+            scaling,
+            configuration: Configuration::from_bits(0x71),
+            global_current: GlobalCurrent::new(0x80),
+            pull_resistors: PullResistors::new(Resistor::R1k, Resistor::R4kAlways),
+            temperature: Temperature::new(TemperaturePoint::T90, ThermalRollOff::P55),
+            spread_spectrum: SpreadSpectrum::new(true, SpreadRange::R15, SpreadCycle::C820us),
+            pwm_freq: PwmFreq::P977,
+        }
+    }
+
+    #[test]
+    fn bytes() {
+        let snapshot = snapshot();
+        let bytes = snapshot.to_bytes();
+        assert_eq!(bytes[0], Snapshot::VERSION);
+        assert_eq!(bytes[1..4], [0x00, 0x01, 0x02]);
+        assert_eq!(bytes[MATRIX_LEN + 1..MATRIX_LEN + 3], [0xFF, 0xFE]);
+        assert_eq!(
+            bytes[2 * MATRIX_LEN + 1..],
+            [0x71, 0x80, 0x26, 0x0E, 0x16, 0xA0]
+        );
+        assert_eq!(Snapshot::from_bytes(&bytes), Some(snapshot));
+    }
+
+    #[test]
+    fn invalid_bytes() {
+        let bytes = snapshot().to_bytes();
+        assert_eq!(Snapshot::from_bytes(&[]), None);
+        assert_eq!(Snapshot::from_bytes(&bytes[..Snapshot::LEN - 1]), None);
+        assert_eq!(Snapshot::from_bytes(&[&bytes[..], &[0x00]].concat()), None);
+        let mut future = bytes;
+        future[0] = Snapshot::VERSION + 1;
+        assert_eq!(Snapshot::from_bytes(&future), None);
+        let mut unknown_freq = bytes;
+        unknown_freq[Snapshot::LEN - 1] = 0x01;
+        assert_eq!(Snapshot::from_bytes(&unknown_freq), None);
+    }
+
+    #[test]
+    fn resume_after_power_loss() {
+        let mut device = device();
+        device.setup(&mut NoDelay).unwrap();
+        device.restore(&snapshot()).unwrap();
+        let suspended = device.suspend().unwrap();
+        assert_eq!(suspended.pwm_freq, PwmFreq::P977);
+        assert!(device.get_register::<Configuration>().unwrap().shutdown());
+
+        device.i2c = Bus::new(0x20);
+        device.resume(&mut NoDelay, &suspended).unwrap();
+        assert_eq!(device.snapshot().unwrap(), snapshot());
+    }
+}
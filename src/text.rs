@@ -0,0 +1,556 @@
+use crate::color::{ColorOrder, Rgb};
+use crate::framebuffer::{FrameBuffer, Output};
+use crate::IS31FL3743;
+
+/// A fixed width bitmap font covering printable ASCII. Every glyph is stored column by column,
+/// the lowest bit is the top row.
+#[derive(Clone, Copy, Debug)]
+pub struct Font {
+    /// Width of a glyph in pixels
+    pub width: u8,
+    /// Height of a glyph in pixels, at most 8
+    pub height: u8,
+    glyphs: &'static [u8],
+}
+
+/// 3x5 font. Lowercase letters are drawn as uppercase ones.
+pub const FONT_3X5: Font = Font {
+    width: 3,
+    height: 5,
+    glyphs: &FONT_3X5_GLYPHS,
+};
+
+/// 5x7 font
+pub const FONT_5X7: Font = Font {
+    width: 5,
+    height: 7,
+    glyphs: &FONT_5X7_GLYPHS,
+};
+
+impl Font {
+    /// Columns of a character. Characters outside of printable ASCII are drawn as `?`.
+    pub fn glyph(&self, c: char) -> &'static [u8] {
+        let code = match c {
+            ' '..='~' => c as usize,
+            _ => '?' as usize,
+        };
+        let start = (code - ' ' as usize) * self.width as usize;
+        &self.glyphs[start..start + self.width as usize]
+    }
+}
+
+/// A string laid out in a [Font]. Lines are separated by `\n`.
+#[derive(Clone, Copy, Debug)]
+pub struct Text<'a> {
+    pub text: &'a str,
+    pub font: &'a Font,
+    /// Empty columns between two glyphs and empty rows between two lines
+    pub spacing: u8,
+}
+
+impl<'a> Text<'a> {
+    pub const fn new(text: &'a str, font: &'a Font) -> Self {
+        Text {
+            text,
+            font,
+            spacing: 1,
+        }
+    }
+
+    /// Width of the longest line in pixels, saturating at `u32::MAX`
+    pub fn width(&self) -> u32 {
+        self.text
+            .lines()
+            .map(|line| self.extent(line.chars().count(), self.font.width))
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Height of all lines in pixels, saturating at `u32::MAX`
+    pub fn height(&self) -> u32 {
+        self.extent(self.text.lines().count(), self.font.height)
+    }
+
+    /// Length of `count` glyphs or lines of `size` pixels with spacing in between
+    fn extent(&self, count: usize, size: u8) -> u32 {
+        let count = u32::try_from(count).unwrap_or(u32::MAX);
+        count
+            .saturating_mul(size as u32 + self.spacing as u32)
+            .saturating_sub(self.spacing as u32)
+    }
+
+    /// Draw the text with its top left corner at `x`,`y`, which may be outside of the display.
+    /// Pixels outside of the display are skipped.
+    ///
+    /// `color` is called with the index of every glyph and its character and returns the color
+    /// to draw it in. Use [ColorOrder::Mono] for single color displays, the brightness is then
+    /// taken from the color.
+    pub fn draw<I2C, F>(
+        &self,
+        frame: &mut FrameBuffer,
+        device: &IS31FL3743<I2C>,
+        x: i32,
+        y: i32,
+        order: ColorOrder,
+        mut color: F,
+    ) where
+        F: FnMut(usize, char) -> Rgb,
+    {
+        // Wide enough for any offset of any glyph of a string that fits into memory
+        let advance = self.font.width as i64 + self.spacing as i64;
+        let line_height = self.font.height as i64 + self.spacing as i64;
+        let mut index = 0;
+        for (line_index, line) in self.text.lines().enumerate() {
+            let top = y as i64 + line_index as i64 * line_height;
+            for (glyph_index, c) in line.chars().enumerate() {
+                let left = x as i64 + glyph_index as i64 * advance;
+                let glyph_color = color(index, c);
+                index += 1;
+                for (column, bits) in self.font.glyph(c).iter().enumerate() {
+                    for row in 0..self.font.height {
+                        if bits & (1 << row) == 0 {
+                            continue;
+                        }
+                        let px = left + column as i64;
+                        let py = top + row as i64;
+                        if (0..=0xFF).contains(&px) && (0..=0xFF).contains(&py) {
+                            frame.set_pixel(device, px as u8, py as u8, order, glyph_color);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Direction the text moves in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// Scrolls a [Text] across the display. The text enters at one edge, leaves at the opposite edge
+/// and starts over.
+pub struct Scroller<'a> {
+    pub text: Text<'a>,
+    pub direction: Direction,
+    /// Milliseconds it takes to move by one pixel
+    pub ms_per_pixel: u16,
+    pub order: ColorOrder,
+    pub frame: FrameBuffer,
+    start: Option<u32>,
+}
+
+impl<'a> Scroller<'a> {
+    pub fn new(text: Text<'a>, direction: Direction, order: ColorOrder) -> Self {
+        Scroller {
+            text,
+            direction,
+            ms_per_pixel: 100,
+            order,
+            frame: FrameBuffer::new(),
+            start: None,
+        }
+    }
+
+    /// Start over at the edge of the display with the next tick
+    pub fn restart(&mut self) {
+        self.start = None;
+    }
+
+    /// Render the text at its position at `now_ms` and write the frame to the device, see
+    /// [Output]
+    pub fn tick<O: Output, F>(
+        &mut self,
+        output: &mut O,
+        now_ms: u32,
+        color: F,
+    ) -> Result<(), O::Error>
+    where
+        F: FnMut(usize, char) -> Rgb,
+    {
+        self.render(output.device(), now_ms, color);
+        self.frame.flush(output)
+    }
+
+    /// Render the text at its position at `now_ms` into [frame](Self::frame), without writing
+    /// it to the device. See [Text::draw] for `color`.
+    pub fn render<I2C, F>(&mut self, device: &IS31FL3743<I2C>, now_ms: u32, color: F)
+    where
+        F: FnMut(usize, char) -> Rgb,
+    {
+        let start = *self.start.get_or_insert(now_ms);
+        let moved = now_ms.wrapping_sub(start) / self.ms_per_pixel.max(1) as u32;
+
+        let (display, text) = match self.direction {
+            Direction::Left | Direction::Right => (device.width as u32, self.text.width()),
+            Direction::Up | Direction::Down => (device.height as u32, self.text.height()),
+        };
+        self.frame.clear();
+        // Nothing to show, and nothing to move through
+        let cycle = display.saturating_add(text);
+        if cycle == 0 {
+            return;
+        }
+        let position = (moved % cycle) as i64;
+        let (display, text) = (display as i64, text as i64);
+        let offset = match self.direction {
+            Direction::Left | Direction::Up => display - position,
+            Direction::Right | Direction::Down => position - text,
+        };
+        // Texts too long for an i32 are cut off at the end
+        let offset = offset.clamp(i32::MIN as i64, i32::MAX as i64) as i32;
+        let (x, y) = match self.direction {
+            Direction::Left | Direction::Right => (offset, 0),
+            Direction::Up | Direction::Down => (0, offset),
+        };
+        self.text
+            .draw(&mut self.frame, device, x, y, self.order, color);
+    }
+}
+
+const FONT_3X5_GLYPHS: [u8; 95 * 3] = [
+    0x00, 0x00, 0x00, // ' '
+    0x00, 0x17, 0x00, // '!'
+    0x03, 0x00, 0x03, // '"'
+    0x1F, 0x0A, 0x1F, // '#'
+    0x12, 0x1F, 0x09, // '$'
+    0x09, 0x04, 0x12, // '%'
+    0x0A, 0x15, 0x1A, // '&'
+    0x00, 0x03, 0x00, // '\''
+    0x00, 0x0E, 0x11, // '('
+    0x11, 0x0E, 0x00, // ')'
+    0x0A, 0x04, 0x0A, // '*'
+    0x04, 0x0E, 0x04, // '+'
+    0x10, 0x08, 0x00, // ','
+    0x04, 0x04, 0x04, // '-'
+    0x00, 0x10, 0x00, // '.'
+    0x18, 0x04, 0x03, // '/'
+    0x1F, 0x11, 0x1F, // '0'
+    0x12, 0x1F, 0x10, // '1'
+    0x1D, 0x15, 0x17, // '2'
+    0x11, 0x15, 0x1F, // '3'
+    0x07, 0x04, 0x1F, // '4'
+    0x17, 0x15, 0x1D, // '5'
+    0x1F, 0x15, 0x1D, // '6'
+    0x01, 0x1D, 0x03, // '7'
+    0x1F, 0x15, 0x1F, // '8'
+    0x17, 0x15, 0x1F, // '9'
+    0x00, 0x0A, 0x00, // ':'
+    0x10, 0x0A, 0x00, // ';'
+    0x04, 0x0A, 0x11, // '<'
+    0x0A, 0x0A, 0x0A, // '='
+    0x11, 0x0A, 0x04, // '>'
+    0x01, 0x15, 0x07, // '?'
+    0x0F, 0x11, 0x17, // '@'
+    0x1E, 0x05, 0x1E, // 'A'
+    0x1F, 0x15, 0x0A, // 'B'
+    0x0E, 0x11, 0x11, // 'C'
+    0x1F, 0x11, 0x0E, // 'D'
+    0x1F, 0x15, 0x11, // 'E'
+    0x1F, 0x05, 0x01, // 'F'
+    0x0E, 0x11, 0x1D, // 'G'
+    0x1F, 0x04, 0x1F, // 'H'
+    0x11, 0x1F, 0x11, // 'I'
+    0x08, 0x10, 0x0F, // 'J'
+    0x1F, 0x04, 0x1B, // 'K'
+    0x1F, 0x10, 0x10, // 'L'
+    0x1F, 0x06, 0x1F, // 'M'
+    0x1F, 0x01, 0x1E, // 'N'
+    0x0E, 0x11, 0x0E, // 'O'
+    0x1F, 0x05, 0x02, // 'P'
+    0x0E, 0x19, 0x16, // 'Q'
+    0x1F, 0x05, 0x1A, // 'R'
+    0x12, 0x15, 0x09, // 'S'
+    0x01, 0x1F, 0x01, // 'T'
+    0x1F, 0x10, 0x1F, // 'U'
+    0x0F, 0x10, 0x0F, // 'V'
+    0x1F, 0x0C, 0x1F, // 'W'
+    0x1B, 0x04, 0x1B, // 'X'
+    0x03, 0x1C, 0x03, // 'Y'
+    0x19, 0x15, 0x13, // 'Z'
+    0x1F, 0x11, 0x00, // '['
+    0x03, 0x04, 0x18, // '\\'
+    0x00, 0x11, 0x1F, // ']'
+    0x02, 0x01, 0x02, // '^'
+    0x10, 0x10, 0x10, // '_'
+    0x01, 0x02, 0x00, // '`'
+    0x1E, 0x05, 0x1E, // 'a'
+    0x1F, 0x15, 0x0A, // 'b'
+    0x0E, 0x11, 0x11, // 'c'
+    0x1F, 0x11, 0x0E, // 'd'
+    0x1F, 0x15, 0x11, // 'e'
+    0x1F, 0x05, 0x01, // 'f'
+    0x0E, 0x11, 0x1D, // 'g'
+    0x1F, 0x04, 0x1F, // 'h'
+    0x11, 0x1F, 0x11, // 'i'
+    0x08, 0x10, 0x0F, // 'j'
+    0x1F, 0x04, 0x1B, // 'k'
+    0x1F, 0x10, 0x10, // 'l'
+    0x1F, 0x06, 0x1F, // 'm'
+    0x1F, 0x01, 0x1E, // 'n'
+    0x0E, 0x11, 0x0E, // 'o'
+    0x1F, 0x05, 0x02, // 'p'
+    0x0E, 0x19, 0x16, // 'q'
+    0x1F, 0x05, 0x1A, // 'r'
+    0x12, 0x15, 0x09, // 's'
+    0x01, 0x1F, 0x01, // 't'
+    0x1F, 0x10, 0x1F, // 'u'
+    0x0F, 0x10, 0x0F, // 'v'
+    0x1F, 0x0C, 0x1F, // 'w'
+    0x1B, 0x04, 0x1B, // 'x'
+    0x03, 0x1C, 0x03, // 'y'
+    0x19, 0x15, 0x13, // 'z'
+    0x04, 0x1F, 0x11, // '{'
+    0x00, 0x1F, 0x00, // '|'
+    0x11, 0x1F, 0x04, // '}'
+    0x08, 0x0C, 0x04, // '~'
+];
+
+const FONT_5X7_GLYPHS: [u8; 95 * 5] = [
+    0x00, 0x00, 0x00, 0x00, 0x00, // ' '
+    0x00, 0x00, 0x5F, 0x00, 0x00, // '!'
+    0x00, 0x07, 0x00, 0x07, 0x00, // '"'
+    0x14, 0x7F, 0x14, 0x7F, 0x14, // '#'
+    0x24, 0x2A, 0x7F, 0x2A, 0x12, // '$'
+    0x23, 0x13, 0x08, 0x64, 0x62, // '%'
+    0x36, 0x49, 0x55, 0x22, 0x50, // '&'
+    0x00, 0x05, 0x03, 0x00, 0x00, // '\''
+    0x00, 0x1C, 0x22, 0x41, 0x00, // '('
+    0x00, 0x41, 0x22, 0x1C, 0x00, // ')'
+    0x14, 0x08, 0x3E, 0x08, 0x14, // '*'
+    0x08, 0x08, 0x3E, 0x08, 0x08, // '+'
+    0x00, 0x50, 0x30, 0x00, 0x00, // ','
+    0x08, 0x08, 0x08, 0x08, 0x08, // '-'
+    0x00, 0x60, 0x60, 0x00, 0x00, // '.'
+    0x20, 0x10, 0x08, 0x04, 0x02, // '/'
+    0x3E, 0x51, 0x49, 0x45, 0x3E, // '0'
+    0x00, 0x42, 0x7F, 0x40, 0x00, // '1'
+    0x42, 0x61, 0x51, 0x49, 0x46, // '2'
+    0x21, 0x41, 0x45, 0x4B, 0x31, // '3'
+    0x18, 0x14, 0x12, 0x7F, 0x10, // '4'
+    0x27, 0x45, 0x45, 0x45, 0x39, // '5'
+    0x3C, 0x4A, 0x49, 0x49, 0x30, // '6'
+    0x01, 0x71, 0x09, 0x05, 0x03, // '7'
+    0x36, 0x49, 0x49, 0x49, 0x36, // '8'
+    0x06, 0x49, 0x49, 0x29, 0x1E, // '9'
+    0x00, 0x36, 0x36, 0x00, 0x00, // ':'
+    0x00, 0x56, 0x36, 0x00, 0x00, // ';'
+    0x08, 0x14, 0x22, 0x41, 0x00, // '<'
+    0x14, 0x14, 0x14, 0x14, 0x14, // '='
+    0x00, 0x41, 0x22, 0x14, 0x08, // '>'
+    0x02, 0x01, 0x51, 0x09, 0x06, // '?'
+    0x32, 0x49, 0x79, 0x41, 0x3E, // '@'
+    0x7E, 0x11, 0x11, 0x11, 0x7E, // 'A'
+    0x7F, 0x49, 0x49, 0x49, 0x36, // 'B'
+    0x3E, 0x41, 0x41, 0x41, 0x22, // 'C'
+    0x7F, 0x41, 0x41, 0x22, 0x1C, // 'D'
+    0x7F, 0x49, 0x49, 0x49, 0x41, // 'E'
+    0x7F, 0x09, 0x09, 0x01, 0x01, // 'F'
+    0x3E, 0x41, 0x41, 0x51, 0x32, // 'G'
+    0x7F, 0x08, 0x08, 0x08, 0x7F, // 'H'
+    0x00, 0x41, 0x7F, 0x41, 0x00, // 'I'
+    0x20, 0x40, 0x41, 0x3F, 0x01, // 'J'
+    0x7F, 0x08, 0x14, 0x22, 0x41, // 'K'
+    0x7F, 0x40, 0x40, 0x40, 0x40, // 'L'
+    0x7F, 0x02, 0x04, 0x02, 0x7F, // 'M'
+    0x7F, 0x04, 0x08, 0x10, 0x7F, // 'N'
+    0x3E, 0x41, 0x41, 0x41, 0x3E, // 'O'
+    0x7F, 0x09, 0x09, 0x09, 0x06, // 'P'
+    0x3E, 0x41, 0x51, 0x21, 0x5E, // 'Q'
+    0x7F, 0x09, 0x19, 0x29, 0x46, // 'R'
+    0x46, 0x49, 0x49, 0x49, 0x31, // 'S'
+    0x01, 0x01, 0x7F, 0x01, 0x01, // 'T'
+    0x3F, 0x40, 0x40, 0x40, 0x3F, // 'U'
+    0x1F, 0x20, 0x40, 0x20, 0x1F, // 'V'
+    0x7F, 0x20, 0x18, 0x20, 0x7F, // 'W'
+    0x63, 0x14, 0x08, 0x14, 0x63, // 'X'
+    0x03, 0x04, 0x78, 0x04, 0x03, // 'Y'
+    0x61, 0x51, 0x49, 0x45, 0x43, // 'Z'
+    0x00, 0x00, 0x7F, 0x41, 0x41, // '['
+    0x02, 0x04, 0x08, 0x10, 0x20, // '\\'
+    0x41, 0x41, 0x7F, 0x00, 0x00, // ']'
+    0x04, 0x02, 0x01, 0x02, 0x04, // '^'
+    0x40, 0x40, 0x40, 0x40, 0x40, // '_'
+    0x00, 0x01, 0x02, 0x04, 0x00, // '`'
+    0x20, 0x54, 0x54, 0x54, 0x78, // 'a'
+    0x7F, 0x48, 0x44, 0x44, 0x38, // 'b'
+    0x38, 0x44, 0x44, 0x44, 0x20, // 'c'
+    0x38, 0x44, 0x44, 0x48, 0x7F, // 'd'
+    0x38, 0x54, 0x54, 0x54, 0x18, // 'e'
+    0x08, 0x7E, 0x09, 0x01, 0x02, // 'f'
+    0x0C, 0x52, 0x52, 0x52, 0x3E, // 'g'
+    0x7F, 0x08, 0x04, 0x04, 0x78, // 'h'
+    0x00, 0x44, 0x7D, 0x40, 0x00, // 'i'
+    0x20, 0x40, 0x44, 0x3D, 0x00, // 'j'
+    0x00, 0x7F, 0x10, 0x28, 0x44, // 'k'
+    0x00, 0x41, 0x7F, 0x40, 0x00, // 'l'
+    0x7C, 0x04, 0x18, 0x04, 0x78, // 'm'
+    0x7C, 0x08, 0x04, 0x04, 0x78, // 'n'
+    0x38, 0x44, 0x44, 0x44, 0x38, // 'o'
+    0x7C, 0x14, 0x14, 0x14, 0x08, // 'p'
+    0x08, 0x14, 0x14, 0x18, 0x7C, // 'q'
+    0x7C, 0x08, 0x04, 0x04, 0x08, // 'r'
+    0x48, 0x54, 0x54, 0x54, 0x20, // 's'
+    0x04, 0x3F, 0x44, 0x40, 0x20, // 't'
+    0x3C, 0x40, 0x40, 0x20, 0x7C, // 'u'
+    0x1C, 0x20, 0x40, 0x20, 0x1C, // 'v'
+    0x3C, 0x40, 0x30, 0x40, 0x3C, // 'w'
+    0x44, 0x28, 0x10, 0x28, 0x44, // 'x'
+    0x0C, 0x50, 0x50, 0x50, 0x3C, // 'y'
+    0x44, 0x64, 0x54, 0x4C, 0x44, // 'z'
+    0x00, 0x08, 0x36, 0x41, 0x00, // '{'
+    0x00, 0x00, 0x7F, 0x00, 0x00, // '|'
+    0x00, 0x41, 0x36, 0x08, 0x00, // '}'
+    0x08, 0x04, 0x08, 0x10, 0x08, // '~'
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::{self, Bus, NoDelay};
+    use crate::power::{Policy, PowerLimited, PowerModel};
+    use crate::register;
+    use std::string::String;
+    use std::vec::Vec;
+
+    fn device(width: u8, height: u8) -> IS31FL3743<Bus> {
+        IS31FL3743 {
+            width,
+            height,
+            ..mock::device()
+        }
+    }
+
+    /// Pixels lit in a frame of an 18 by 11 display
+    fn lit(frame: &FrameBuffer) -> Vec<(u8, u8)> {
+        let mut pixels = Vec::new();
+        for y in 0..11 {
+            for x in 0..18 {
+                if frame.get(register(y, x)) != 0 {
+                    pixels.push((x, y));
+                }
+            }
+        }
+        pixels
+    }
+
+    fn white(_: usize, _: char) -> Rgb {
+        Rgb::WHITE
+    }
+
+    #[test]
+    fn size() {
+        let text = Text::new("AB\nC", &FONT_3X5);
+        assert_eq!((text.width(), text.height()), (7, 11));
+        let text = Text::new("", &FONT_5X7);
+        assert_eq!((text.width(), text.height()), (0, 0));
+        let mut text = Text::new("ABC", &FONT_5X7);
+        text.spacing = 0;
+        assert_eq!((text.width(), text.height()), (15, 7));
+        let long = String::from("W").repeat(20_000);
+        assert_eq!(Text::new(&long, &FONT_3X5).width(), 79_999);
+    }
+
+    #[test]
+    fn draw() {
+        let device = device(18, 11);
+        let mut frame = FrameBuffer::new();
+        let mut glyphs = Vec::new();
+        Text::new("!\n!", &FONT_3X5).draw(
+            &mut frame,
+            &device,
+            -1,
+            2,
+            ColorOrder::Mono,
+            |index, c| {
+                glyphs.push((index, c));
+                Rgb::WHITE
+            },
+        );
+        assert_eq!(glyphs, [(0, '!'), (1, '!')]);
+        assert_eq!(
+            lit(&frame),
+            [(0, 2), (0, 3), (0, 4), (0, 6), (0, 8), (0, 9), (0, 10)]
+        );
+    }
+
+    #[test]
+    fn scroll() {
+        let device = device(18, 11);
+        let text = Text::new("!", &FONT_3X5);
+        let mut scroller = Scroller::new(text, Direction::Left, ColorOrder::Mono);
+        scroller.render(&device, 1000, white);
+        assert_eq!(lit(&scroller.frame), []);
+        scroller.render(&device, 1200, white);
+        assert_eq!(lit(&scroller.frame), [(17, 0), (17, 1), (17, 2), (17, 4)]);
+        // Starts over after moving through the display and the text
+        scroller.render(&device, 1000 + 21 * 100, white);
+        assert_eq!(lit(&scroller.frame), []);
+
+        let mut scroller = Scroller::new(text, Direction::Right, ColorOrder::Mono);
+        scroller.render(&device, u32::MAX, white);
+        scroller.render(&device, 199, white);
+        assert_eq!(lit(&scroller.frame), [(0, 0), (0, 1), (0, 2), (0, 4)]);
+
+        let mut scroller = Scroller::new(text, Direction::Up, ColorOrder::Mono);
+        scroller.ms_per_pixel = 0;
+        scroller.render(&device, 0, white);
+        scroller.render(&device, 2, white);
+        assert_eq!(lit(&scroller.frame), [(1, 9), (1, 10)]);
+    }
+
+    #[test]
+    fn scroll_nothing() {
+        let mut scroller =
+            Scroller::new(Text::new("", &FONT_3X5), Direction::Down, ColorOrder::Mono);
+        scroller.render(&device(18, 0), 0, white);
+        scroller.render(&device(18, 0), 500, white);
+        assert_eq!(lit(&scroller.frame), []);
+    }
+
+    #[test]
+    fn scroll_long_text() {
+        let device = device(18, 11);
+        let long = String::from("W").repeat(20_000);
+        let mut scroller = Scroller::new(
+            Text::new(&long, &FONT_3X5),
+            Direction::Left,
+            ColorOrder::Mono,
+        );
+        scroller.ms_per_pixel = 1;
+        scroller.render(&device, 0, white);
+        // The last glyph starts 2 pixels from the left edge
+        scroller.render(&device, 80_012, white);
+        let pixels = lit(&scroller.frame);
+        assert!(pixels.contains(&(2, 0)));
+        assert!(!pixels.contains(&(1, 0)));
+        assert!(!pixels.iter().any(|&(x, _)| x > 4));
+    }
+
+    #[test]
+    fn tick_through_power_limit() {
+        // 25.6mA per LED, the 4 LEDs of the text need more than 50mA
+        let model = PowerModel {
+            active_sw: 1,
+            ..PowerModel::new(13_398, 50)
+        };
+        let mut limited = PowerLimited::new(device(18, 11), model, Policy::Scale).unwrap();
+        limited.setup(&mut NoDelay).unwrap();
+        limited.set_scaling(0xFF).unwrap();
+        let mut scroller = Scroller::new(
+            Text::new("!", &FONT_3X5),
+            Direction::Right,
+            ColorOrder::Mono,
+        );
+        scroller.tick(&mut limited, 0, white).unwrap();
+        scroller.tick(&mut limited, 200, white).unwrap();
+        assert_eq!(lit(&scroller.frame).len(), 4);
+        let pwm = limited.device.i2c.pwm()[register(0, 0) as usize - 1];
+        assert!(pwm > 0 && pwm < 0xFF);
+        assert!(limited.estimate_ua() <= 50_000);
+    }
+}
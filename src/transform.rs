@@ -0,0 +1,360 @@
+use crate::color::{ColorOrder, Rgb};
+use crate::framebuffer::FrameBuffer;
+use crate::{Error, IS31FL3743};
+use embedded_hal::i2c::I2c;
+
+/// Clockwise rotation of the image on the matrix
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rotation {
+    Deg0,
+    Deg90,
+    Deg180,
+    Deg270,
+}
+
+/// Maps logical coordinates to the coordinates passed to [calc_pixel](IS31FL3743::calc_pixel).
+///
+/// The image is flipped first, then rotated and finally moved into the window.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transform {
+    pub rotation: Rotation,
+    /// Mirror the image left to right
+    pub flip_horizontal: bool,
+    /// Mirror the image top to bottom
+    pub flip_vertical: bool,
+    /// Left edge of the window on the matrix
+    pub x: u8,
+    /// Top edge of the window on the matrix
+    pub y: u8,
+    /// Width of the window on the matrix, before rotation
+    pub width: u8,
+    /// Height of the window on the matrix, before rotation
+    pub height: u8,
+}
+
+impl Transform {
+    /// No transformation, over a whole matrix of the given size
+    pub const fn new(width: u8, height: u8) -> Self {
+        Transform {
+            rotation: Rotation::Deg0,
+            flip_horizontal: false,
+            flip_vertical: false,
+            x: 0,
+            y: 0,
+            width,
+            height,
+        }
+    }
+
+    /// Only use part of the matrix, e.g. to draw into a section of it
+    pub const fn window(self, x: u8, y: u8, width: u8, height: u8) -> Self {
+        Transform {
+            x,
+            y,
+            width,
+            height,
+            ..self
+        }
+    }
+
+    pub const fn rotate(self, rotation: Rotation) -> Self {
+        Transform { rotation, ..self }
+    }
+
+    pub const fn flip_horizontal(self) -> Self {
+        Transform {
+            flip_horizontal: !self.flip_horizontal,
+            ..self
+        }
+    }
+
+    pub const fn flip_vertical(self) -> Self {
+        Transform {
+            flip_vertical: !self.flip_vertical,
+            ..self
+        }
+    }
+
+    /// Width of the image after rotation
+    pub const fn logical_width(&self) -> u8 {
+        match self.rotation {
+            Rotation::Deg0 | Rotation::Deg180 => self.width,
+            Rotation::Deg90 | Rotation::Deg270 => self.height,
+        }
+    }
+
+    /// Height of the image after rotation
+    pub const fn logical_height(&self) -> u8 {
+        match self.rotation {
+            Rotation::Deg0 | Rotation::Deg180 => self.height,
+            Rotation::Deg90 | Rotation::Deg270 => self.width,
+        }
+    }
+
+    /// Coordinates on the matrix of a logical pixel. `None` if it is outside of the image.
+    pub const fn apply(&self, x: u8, y: u8) -> Option<(u8, u8)> {
+        let (width, height) = (self.logical_width(), self.logical_height());
+        if x >= width || y >= height {
+            return None;
+        }
+        let x = if self.flip_horizontal {
+            width - 1 - x
+        } else {
+            x
+        };
+        let y = if self.flip_vertical {
+            height - 1 - y
+        } else {
+            y
+        };
+        let (x, y) = match self.rotation {
+            Rotation::Deg0 => (x, y),
+            Rotation::Deg90 => (self.width - 1 - y, x),
+            Rotation::Deg180 => (self.width - 1 - x, self.height - 1 - y),
+            Rotation::Deg270 => (y, self.height - 1 - x),
+        };
+        match (self.x.checked_add(x), self.y.checked_add(y)) {
+            (Some(x), Some(y)) => Some((x, y)),
+            _ => None,
+        }
+    }
+}
+
+/// A device drawn in logical coordinates, see [Transform].
+///
+/// [pixel](Self::pixel) writes straight to the device. [set_pixel](Self::set_pixel) and the
+/// embedded-graphics `DrawTarget` draw into [frame](Self::frame), write it with
+/// [flush](Self::flush).
+pub struct Transformed<I2C> {
+    pub device: IS31FL3743<I2C>,
+    pub transform: Transform,
+    /// How the channels of each LED are connected, for [set_pixel](Self::set_pixel)
+    pub order: ColorOrder,
+    pub frame: FrameBuffer,
+}
+
+impl<I2C> Transformed<I2C> {
+    /// Wrap a device, without transforming it yet
+    pub fn new(device: IS31FL3743<I2C>, order: ColorOrder) -> Self {
+        Transformed {
+            transform: Transform::new(device.width, device.height),
+            device,
+            order,
+            frame: FrameBuffer::new(),
+        }
+    }
+
+    /// Logical width, after rotation
+    pub fn width(&self) -> u8 {
+        self.transform.logical_width()
+    }
+
+    /// Logical height, after rotation
+    pub fn height(&self) -> u8 {
+        self.transform.logical_height()
+    }
+
+    /// Register of the first channel of the LED at a logical coordinate. `None` if it is
+    /// outside of the image or the device.
+    pub fn register(&self, x: u8, y: u8) -> Option<u8> {
+        let (x, y) = self.transform.apply(x, y)?;
+        if x >= self.device.width || y >= self.device.height {
+            return None;
+        }
+        Some((self.device.calc_pixel)(x, y))
+    }
+
+    /// Set the color of the LED at a logical coordinate in [frame](Self::frame). Coordinates
+    /// outside of the image are ignored.
+    pub fn set_pixel(&mut self, x: u8, y: u8, color: Rgb) {
+        if let Some(register) = self.register(x, y) {
+            self.frame.set_color(register, self.order, color);
+        }
+    }
+}
+
+impl<I2C, I2cError> Transformed<I2C>
+where
+    I2C: I2c<Error = I2cError>,
+{
+    /// See [IS31FL3743::pixel], with logical coordinates
+    pub fn pixel(&mut self, x: u8, y: u8, brightness: u8) -> Result<(), Error<I2cError>> {
+        let Some((x, y)) = self.transform.apply(x, y) else {
+            return Err(Error::InvalidLocation(x.max(y)));
+        };
+        self.device.pixel(x, y, brightness)
+    }
+
+    /// Write [frame](Self::frame) to the device
+    pub fn flush(&mut self) -> Result<(), I2cError> {
+        self.frame.flush(&mut self.device)
+    }
+}
+
+#[cfg(feature = "embedded_graphics")]
+mod graphics {
+    use super::Transformed;
+    use crate::color::Rgb;
+    use core::convert::{Infallible, TryFrom};
+    use embedded_graphics_core::draw_target::DrawTarget;
+    use embedded_graphics_core::geometry::{OriginDimensions, Size};
+    use embedded_graphics_core::pixelcolor::{Rgb888, RgbColor};
+    use embedded_graphics_core::Pixel;
+
+    impl<I2C> OriginDimensions for Transformed<I2C> {
+        fn size(&self) -> Size {
+            Size::new(self.width() as u32, self.height() as u32)
+        }
+    }
+
+    impl<I2C> DrawTarget for Transformed<I2C> {
+        type Color = Rgb888;
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            for Pixel(point, color) in pixels {
+                if let (Ok(x), Ok(y)) = (u8::try_from(point.x), u8::try_from(point.y)) {
+                    self.set_pixel(x, y, Rgb::new(color.r(), color.g(), color.b()));
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::device;
+    use crate::register;
+
+    /// The corners of a 3×2 image, clockwise from the top left
+    fn corners(transform: Transform) -> [Option<(u8, u8)>; 4] {
+        let (right, bottom) = (
+            transform.logical_width() - 1,
+            transform.logical_height() - 1,
+        );
+        [
+            transform.apply(0, 0),
+            transform.apply(right, 0),
+            transform.apply(right, bottom),
+            transform.apply(0, bottom),
+        ]
+    }
+
+    #[test]
+    fn rotations() {
+        let transform = Transform::new(3, 2);
+        assert_eq!(
+            corners(transform),
+            [Some((0, 0)), Some((2, 0)), Some((2, 1)), Some((0, 1))]
+        );
+        let transform = transform.rotate(Rotation::Deg90);
+        assert_eq!(
+            (transform.logical_width(), transform.logical_height()),
+            (2, 3)
+        );
+        assert_eq!(
+            corners(transform),
+            [Some((2, 0)), Some((2, 1)), Some((0, 1)), Some((0, 0))]
+        );
+        let transform = transform.rotate(Rotation::Deg180);
+        assert_eq!(
+            (transform.logical_width(), transform.logical_height()),
+            (3, 2)
+        );
+        assert_eq!(
+            corners(transform),
+            [Some((2, 1)), Some((0, 1)), Some((0, 0)), Some((2, 0))]
+        );
+        let transform = transform.rotate(Rotation::Deg270);
+        assert_eq!(
+            (transform.logical_width(), transform.logical_height()),
+            (2, 3)
+        );
+        assert_eq!(
+            corners(transform),
+            [Some((0, 1)), Some((0, 0)), Some((2, 0)), Some((2, 1))]
+        );
+    }
+
+    #[test]
+    fn flips() {
+        let transform = Transform::new(3, 2);
+        assert_eq!(
+            corners(transform.flip_horizontal()),
+            [Some((2, 0)), Some((0, 0)), Some((0, 1)), Some((2, 1))]
+        );
+        assert_eq!(
+            corners(transform.flip_vertical()),
+            [Some((0, 1)), Some((2, 1)), Some((2, 0)), Some((0, 0))]
+        );
+        assert_eq!(
+            corners(transform.flip_horizontal().flip_vertical()),
+            corners(transform.rotate(Rotation::Deg180))
+        );
+        assert_eq!(transform.flip_horizontal().flip_horizontal(), transform);
+        // Flipped before rotating, so mirroring stays left to right in logical coordinates
+        assert_eq!(
+            corners(transform.rotate(Rotation::Deg90).flip_horizontal()),
+            [Some((2, 1)), Some((2, 0)), Some((0, 0)), Some((0, 1))]
+        );
+        assert_eq!(
+            corners(transform.rotate(Rotation::Deg270).flip_vertical()),
+            [Some((2, 1)), Some((2, 0)), Some((0, 0)), Some((0, 1))]
+        );
+    }
+
+    #[test]
+    fn window() {
+        let transform = Transform::new(18, 11).window(4, 5, 3, 2);
+        assert_eq!(
+            corners(transform),
+            [Some((4, 5)), Some((6, 5)), Some((6, 6)), Some((4, 6))]
+        );
+        assert_eq!(
+            corners(transform.rotate(Rotation::Deg90)),
+            [Some((6, 5)), Some((6, 6)), Some((4, 6)), Some((4, 5))]
+        );
+        assert_eq!(Transform::new(3, 2).window(0xFE, 0, 3, 2).apply(2, 0), None);
+    }
+
+    #[test]
+    fn outside() {
+        for rotation in [
+            Rotation::Deg0,
+            Rotation::Deg90,
+            Rotation::Deg180,
+            Rotation::Deg270,
+        ] {
+            let transform = Transform::new(3, 2).rotate(rotation).flip_horizontal();
+            let (width, height) = (transform.logical_width(), transform.logical_height());
+            assert_eq!(transform.apply(width, 0), None);
+            assert_eq!(transform.apply(0, height), None);
+            assert!(transform.apply(width - 1, height - 1).is_some());
+        }
+    }
+
+    #[test]
+    fn transformed() {
+        let device = device();
+        let mut display = Transformed::new(device, ColorOrder::Rgb);
+        display.transform = display.transform.rotate(Rotation::Deg90);
+        assert_eq!((display.width(), display.height()), (11, 18));
+        assert_eq!(display.register(0, 0), Some(register(0, 17)));
+        assert_eq!(display.register(10, 17), Some(register(10, 0)));
+        assert_eq!(display.register(11, 0), None);
+
+        display.pixel(0, 0, 0x80).unwrap();
+        assert_eq!(display.device.i2c.pwm()[register(0, 17) as usize - 1], 0x80);
+        assert!(display.pixel(0, 18, 0x80).is_err());
+
+        // The window may reach past the device, those pixels are ignored
+        display.transform = Transform::new(18, 11).window(16, 0, 4, 1);
+        assert_eq!(display.register(1, 0), Some(register(0, 17)));
+        assert_eq!(display.register(2, 0), None);
+    }
+}
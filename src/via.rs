@@ -0,0 +1,414 @@
+//! Handles the lighting part of the VIA raw HID protocol, as used by QMK.
+//!
+//! VIA sends 32 byte reports. The custom value commands are laid out as
+//! `command, channel, value, data...` and the response is the same report with the data of
+//! get commands filled in. Only the RGB matrix channel is handled, with these values:
+//!
+//! | Value             | Data             | Applied to                                       |
+//! |-------------------|------------------|--------------------------------------------------|
+//! | Brightness (0x01) | brightness       | [GlobalCurrent](crate::registers::GlobalCurrent) or scaling, see [Brightness] |
+//! | Effect (0x02)     | 0 is off, 1 and up are the [Effect]s by [id](Effect::id) | Engines of all zones |
+//! | Effect speed (0x03) | speed          | [Params::speed]                                  |
+//! | Color (0x04)      | hue, saturation  | [Params::hue] and [Params::saturation]           |
+use crate::effects::{Effect, Params, Zone};
+use crate::framebuffer::Output;
+
+/// Length of a VIA report
+pub const REPORT_LEN: usize = 32;
+
+/// VIA commands
+pub mod command {
+    pub const CUSTOM_SET_VALUE: u8 = 0x07;
+    pub const CUSTOM_GET_VALUE: u8 = 0x08;
+    pub const CUSTOM_SAVE: u8 = 0x09;
+    /// Replaces the command of reports that weren't handled
+    pub const UNHANDLED: u8 = 0xFF;
+}
+
+/// Channel of the RGB matrix values
+pub const RGB_MATRIX_CHANNEL: u8 = 0x03;
+
+/// Values of the RGB matrix channel
+pub mod value {
+    pub const BRIGHTNESS: u8 = 0x01;
+    pub const EFFECT: u8 = 0x02;
+    pub const EFFECT_SPEED: u8 = 0x03;
+    pub const COLOR: u8 = 0x04;
+}
+
+/// What the brightness slider controls
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Brightness {
+    /// The [GlobalCurrent](crate::registers::GlobalCurrent) register, a single byte per controller
+    GlobalCurrent,
+    /// The scaling of all LEDs, keeping the global current as set up
+    Scaling,
+    /// [Params::brightness] of the effects, keeping the hardware at full current
+    Effect,
+}
+
+/// The lighting state VIA can change, and what [Via::take_save] returns to store
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Config {
+    pub brightness: u8,
+    /// `None` turns all LEDs off
+    pub effect: Option<Effect>,
+    pub speed: u8,
+    pub hue: u8,
+    pub saturation: u8,
+}
+
+impl Config {
+    /// Length of the serialized config
+    pub const LEN: usize = 5;
+
+    pub fn effect_id(&self) -> u8 {
+        self.effect.map_or(0, Effect::id)
+    }
+
+    pub fn to_bytes(&self) -> [u8; Self::LEN] {
+        [
+            self.brightness,
+            self.effect_id(),
+            self.speed,
+            self.hue,
+            self.saturation,
+        ]
+    }
+
+    /// Returns `None` if the data is too short or the effect is unknown
+    pub fn from_bytes(data: &[u8]) -> Option<Self> {
+        let &[brightness, effect, speed, hue, saturation, ..] = data else {
+            return None;
+        };
+        Some(Config {
+            brightness,
+            effect: effect_from_id(effect)?,
+            speed,
+            hue,
+            saturation,
+        })
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        let params = Params::default();
+        Config {
+            brightness: 0xFF,
+            effect: Some(Effect::Solid),
+            speed: params.speed,
+            hue: params.hue,
+            saturation: params.saturation,
+        }
+    }
+}
+
+/// 0 is off, `None` for unknown ids
+fn effect_from_id(id: u8) -> Option<Option<Effect>> {
+    match id {
+        0 => Some(None),
+        _ => Effect::from_id(id).map(Some),
+    }
+}
+
+/// Applies VIA lighting commands to the [Zone]s of a keyboard.
+///
+/// Pass every raw HID report to [handle](Self::handle) and send it back afterwards, then call
+/// [tick](Self::tick) regularly to run the effect.
+pub struct Via {
+    pub config: Config,
+    pub brightness: Brightness,
+    save: bool,
+}
+
+impl Via {
+    pub fn new(config: Config, brightness: Brightness) -> Self {
+        Via {
+            config,
+            brightness,
+            save: false,
+        }
+    }
+
+    /// Write the whole config to the zones, e.g. after loading it from storage
+    pub fn apply<O: Output>(&mut self, zones: &mut [Zone<O>]) -> Result<(), O::Error> {
+        self.apply_brightness(zones)?;
+        self.apply_effect(zones)
+    }
+
+    /// The config to store if VIA asked to save since the last call
+    pub fn take_save(&mut self) -> Option<Config> {
+        if core::mem::take(&mut self.save) {
+            Some(self.config)
+        } else {
+            None
+        }
+    }
+
+    /// Run the effect on all zones. Does nothing while the LEDs are off.
+    pub fn tick<O: Output>(&mut self, zones: &mut [Zone<O>], now_ms: u32) -> Result<(), O::Error> {
+        if self.config.effect.is_none() {
+            return Ok(());
+        }
+        for zone in zones.iter_mut() {
+            zone.engine.tick(&mut zone.device, now_ms)?;
+        }
+        Ok(())
+    }
+
+    /// Handle a report in place, turning it into the response. Reports for other commands or
+    /// channels are left untouched and `false` is returned, so the firmware can handle them
+    /// itself. Unknown RGB matrix values get the [UNHANDLED](command::UNHANDLED) command.
+    pub fn handle<O: Output>(
+        &mut self,
+        zones: &mut [Zone<O>],
+        report: &mut [u8],
+    ) -> Result<bool, O::Error> {
+        let [command, channel, value, data @ ..] = report else {
+            return Ok(false);
+        };
+        if *channel != RGB_MATRIX_CHANNEL {
+            return Ok(false);
+        }
+        match *command {
+            command::CUSTOM_SET_VALUE => {
+                let handled = match (*value, &*data) {
+                    (value::BRIGHTNESS, [brightness, ..]) => {
+                        self.config.brightness = *brightness;
+                        self.apply_brightness(zones)?;
+                        true
+                    }
+                    (value::EFFECT, [id, ..]) => match effect_from_id(*id) {
+                        Some(effect) => {
+                            self.config.effect = effect;
+                            self.apply_effect(zones)?;
+                            true
+                        }
+                        None => false,
+                    },
+                    (value::EFFECT_SPEED, [speed, ..]) => {
+                        self.config.speed = *speed;
+                        self.apply_effect(zones)?;
+                        true
+                    }
+                    (value::COLOR, [hue, saturation, ..]) => {
+                        self.config.hue = *hue;
+                        self.config.saturation = *saturation;
+                        self.apply_effect(zones)?;
+                        true
+                    }
+                    _ => false,
+                };
+                if !handled {
+                    *command = command::UNHANDLED;
+                }
+            }
+            command::CUSTOM_GET_VALUE => match (*value, data) {
+                (value::BRIGHTNESS, [brightness, ..]) => *brightness = self.config.brightness,
+                (value::EFFECT, [id, ..]) => *id = self.config.effect_id(),
+                (value::EFFECT_SPEED, [speed, ..]) => *speed = self.config.speed,
+                (value::COLOR, [hue, saturation, ..]) => {
+                    *hue = self.config.hue;
+                    *saturation = self.config.saturation;
+                }
+                _ => *command = command::UNHANDLED,
+            },
+            command::CUSTOM_SAVE => self.save = true,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+
+    fn apply_brightness<O: Output>(&mut self, zones: &mut [Zone<O>]) -> Result<(), O::Error> {
+        let brightness = self.config.brightness;
+        for zone in zones.iter_mut() {
+            match self.brightness {
+                Brightness::GlobalCurrent => zone.device.write_global_current(brightness)?,
+                Brightness::Scaling => zone.device.write_scaling(brightness)?,
+                Brightness::Effect => zone.engine.params.brightness = brightness,
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_effect<O: Output>(&mut self, zones: &mut [Zone<O>]) -> Result<(), O::Error> {
+        for zone in zones.iter_mut() {
+            let params = &mut zone.engine.params;
+            params.speed = self.config.speed;
+            params.hue = self.config.hue;
+            params.saturation = self.config.saturation;
+            match self.config.effect {
+                Some(effect) => zone.engine.set_effect(effect),
+                None => {
+                    zone.engine.frame.clear();
+                    zone.engine.frame.flush(&mut zone.device)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::ColorOrder;
+    use crate::effects::Engine;
+    use crate::mock::{self, Bus};
+    use crate::{addresses, register, IS31FL3743};
+
+    fn zone() -> Zone<IS31FL3743<Bus>> {
+        Zone {
+            device: IS31FL3743 {
+                width: 6,
+                calc_pixel: |x, y| register(y, 3 * x),
+                ..mock::device()
+            },
+            engine: Engine::new(Effect::Solid, ColorOrder::Rgb),
+        }
+    }
+
+    fn report(command: u8, channel: u8, value: u8, data: &[u8]) -> [u8; REPORT_LEN] {
+        let mut report = [0x00; REPORT_LEN];
+        report[..3].copy_from_slice(&[command, channel, value]);
+        report[3..3 + data.len()].copy_from_slice(data);
+        report
+    }
+
+    #[test]
+    fn config_bytes() {
+        let config = Config {
+            brightness: 0x80,
+            effect: Some(Effect::Spiral),
+            speed: 1,
+            hue: 2,
+            saturation: 3,
+        };
+        assert_eq!(config.to_bytes(), [0x80, 6, 1, 2, 3]);
+        assert_eq!(Config::from_bytes(&config.to_bytes()), Some(config));
+        let off = Config {
+            effect: None,
+            ..config
+        };
+        assert_eq!(off.effect_id(), 0);
+        assert_eq!(Config::from_bytes(&off.to_bytes()), Some(off));
+        assert_eq!(Config::from_bytes(&[0x80, 7, 1, 2, 3]), None);
+        assert_eq!(Config::from_bytes(&[0x80, 1, 2, 3]), None);
+    }
+
+    #[test]
+    fn set_and_get_values() {
+        let mut zones = [zone(), zone()];
+        let mut via = Via::new(Config::default(), Brightness::GlobalCurrent);
+
+        let mut set = report(
+            command::CUSTOM_SET_VALUE,
+            RGB_MATRIX_CHANNEL,
+            value::EFFECT,
+            &[3],
+        );
+        assert!(via.handle(&mut zones, &mut set).unwrap());
+        assert_eq!(set[0], command::CUSTOM_SET_VALUE);
+        assert!(zones
+            .iter()
+            .all(|zone| zone.engine.effect == Effect::RainbowCycle));
+
+        let mut set = report(
+            command::CUSTOM_SET_VALUE,
+            RGB_MATRIX_CHANNEL,
+            value::COLOR,
+            &[10, 20],
+        );
+        via.handle(&mut zones, &mut set).unwrap();
+        assert_eq!(
+            (
+                zones[1].engine.params.hue,
+                zones[1].engine.params.saturation
+            ),
+            (10, 20)
+        );
+
+        let mut set = report(
+            command::CUSTOM_SET_VALUE,
+            RGB_MATRIX_CHANNEL,
+            value::BRIGHTNESS,
+            &[0x40],
+        );
+        via.handle(&mut zones, &mut set).unwrap();
+        assert!(zones
+            .iter()
+            .all(|zone| zone.device.i2c.function(addresses::CURRENT_REGISTER) == 0x40));
+
+        let mut get = report(
+            command::CUSTOM_GET_VALUE,
+            RGB_MATRIX_CHANNEL,
+            value::EFFECT,
+            &[],
+        );
+        via.handle(&mut zones, &mut get).unwrap();
+        assert_eq!(get[3], 3);
+        let mut get = report(
+            command::CUSTOM_GET_VALUE,
+            RGB_MATRIX_CHANNEL,
+            value::COLOR,
+            &[],
+        );
+        via.handle(&mut zones, &mut get).unwrap();
+        assert_eq!(get[3..5], [10, 20]);
+
+        let mut save = report(command::CUSTOM_SAVE, RGB_MATRIX_CHANNEL, 0, &[]);
+        via.handle(&mut zones, &mut save).unwrap();
+        assert_eq!(
+            via.take_save().map(|config| config.effect),
+            Some(Some(Effect::RainbowCycle))
+        );
+        assert_eq!(via.take_save(), None);
+    }
+
+    #[test]
+    fn effect_off_clears_the_leds() {
+        let mut zones = [zone()];
+        zones[0].device.fill(0x55).unwrap();
+        let mut via = Via::new(Config::default(), Brightness::Scaling);
+        let mut set = report(
+            command::CUSTOM_SET_VALUE,
+            RGB_MATRIX_CHANNEL,
+            value::EFFECT,
+            &[0],
+        );
+        assert!(via.handle(&mut zones, &mut set).unwrap());
+        assert_eq!(via.config.effect, None);
+        assert!(zones[0].device.i2c.pwm().iter().all(|&pwm| pwm == 0));
+    }
+
+    #[test]
+    fn unhandled_reports() {
+        let mut zones = [zone()];
+        let mut via = Via::new(Config::default(), Brightness::Effect);
+
+        // Other channels and commands are left to the firmware
+        let mut other = report(command::CUSTOM_SET_VALUE, 0x02, value::EFFECT, &[1]);
+        let copy = other;
+        assert!(!via.handle(&mut zones, &mut other).unwrap());
+        assert_eq!(other, copy);
+        let mut other = report(0x01, RGB_MATRIX_CHANNEL, 0, &[]);
+        assert!(!via.handle(&mut zones, &mut other).unwrap());
+
+        for mut unknown in [
+            report(
+                command::CUSTOM_SET_VALUE,
+                RGB_MATRIX_CHANNEL,
+                value::EFFECT,
+                &[7],
+            ),
+            report(command::CUSTOM_SET_VALUE, RGB_MATRIX_CHANNEL, 0x42, &[]),
+            report(command::CUSTOM_GET_VALUE, RGB_MATRIX_CHANNEL, 0x42, &[]),
+        ] {
+            assert!(via.handle(&mut zones, &mut unknown).unwrap());
+            assert_eq!(unknown[0], command::UNHANDLED);
+        }
+        assert_eq!(via.config, Config::default());
+        assert!(via.handle(&mut zones, &mut [0x07, 0x03][..]).is_ok());
+    }
+}